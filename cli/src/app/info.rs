@@ -0,0 +1,118 @@
+//! What `diem info` knows about an app before and after it is installed.
+//!
+//! Versions are gathered by the `Catalog` from every provider and
+//! subscription. Download sizes come from the package files themselves:
+//! local archives are measured on disk and remote ones with a `HEAD`
+//! request. Installed versions are read from the layout `PackageManager`
+//! leaves under `install_dir`, `<package>/<version>/`.
+
+use semver::Version;
+
+use std::path::{Path, PathBuf};
+
+use crate::{App, Package, Provider, artifactory::include::Location, platform::Platform};
+
+/// A version of an app offered by a provider or subscription.
+#[derive(Debug, Clone)]
+pub struct AppOffer {
+    pub app: App,
+    /// Provider or artifactory offering the app, e.g. `Provider: github:...`
+    pub source: String,
+    /// Provider to download the app's packages through
+    pub provider: Provider,
+}
+
+/// Picks the offer matching `version`, or the newest one. `offers` must be
+/// sorted newest first.
+pub fn select<'a>(offers: &'a [AppOffer], version: Option<&Version>) -> Option<&'a AppOffer> {
+    match version {
+        Some(version) => offers.iter().find(|offer| offer.app.version == *version),
+        None => offers.first(),
+    }
+}
+
+/// Resolves a package source the way providers download it: URLs and
+/// absolute paths as they are, other paths next to the artifactory file.
+pub fn resolve_source(source: &str, location: Option<&Location>) -> Location {
+    match location {
+        Some(location) => location.join(source),
+        None => Location::parse(source),
+    }
+}
+
+/// Size of the archive this platform would download for `package`, without
+/// its dependencies. `None` when the package has no build for this
+/// platform or the size cannot be determined.
+pub async fn download_size(package: &Package, location: Option<&Location>) -> Option<u64> {
+    let (source, _) = package.artifact_for(&Platform::current()).ok()??;
+
+    match resolve_source(source, location) {
+        Location::Path(path) => std::fs::metadata(path).ok().map(|metadata| metadata.len()),
+        Location::Url(url) => {
+            let response = reqwest::Client::new()
+                .head(&url)
+                .header(reqwest::header::USER_AGENT, "diem")
+                .send()
+                .await
+                .ok()?
+                .error_for_status()
+                .ok()?;
+            // `content_length` reports the empty body of a HEAD response
+            response
+                .headers()
+                .get(reqwest::header::CONTENT_LENGTH)?
+                .to_str()
+                .ok()?
+                .parse()
+                .ok()
+        }
+    }
+}
+
+/// Size of everything this platform would download for `packages` and
+/// their dependencies, counting unknown sizes as nothing.
+pub async fn total_download_size(packages: &[Package], location: Option<&Location>) -> u64 {
+    let mut total = 0;
+    let mut pending: Vec<&Package> = packages.iter().collect();
+    while let Some(package) = pending.pop() {
+        total += download_size(package, location).await.unwrap_or(0);
+        pending.extend(&package.dependencies);
+    }
+    total
+}
+
+/// Versions of a package installed under `install_dir`, newest first.
+pub fn installed_versions(install_dir: &Path, package: &str) -> Vec<Version> {
+    let Ok(entries) = std::fs::read_dir(install_dir.join(package)) else {
+        return Vec::new();
+    };
+
+    let mut versions: Vec<Version> = entries
+        .flatten()
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| Version::parse(&entry.file_name().to_string_lossy()).ok())
+        .collect();
+    versions.sort_by(|a, b| b.cmp(a));
+    versions
+}
+
+/// Where the files of a package version live.
+pub fn package_dir(install_dir: &Path, package: &Package) -> PathBuf {
+    install_dir.join(&package.name).join(package.version.to_string())
+}
+
+/// Formats a byte count for humans, e.g. `1.5 MiB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
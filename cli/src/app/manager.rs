@@ -14,20 +14,18 @@ fn find_binary_in_dir(dir: &std::path::Path, filename: String) -> Option<std::pa
         Err(_) => return None,
     };
     
-    for entry in entries {
-        if let Ok(entry) = entry {
-            let path = entry.path();
-            
-            if path.is_dir() {
-                if let Some(found) = find_binary_in_dir(&path, filename.clone()) {
-                    return Some(found);
-                }
-            } else if path.file_name()
-                .and_then(|name| name.to_str())
-                .map_or(false, |name| name == filename)
-            {
-                return Some(path);
+    for entry in entries.flatten() {
+        let path = entry.path();
+        
+        if path.is_dir() {
+            if let Some(found) = find_binary_in_dir(&path, filename.clone()) {
+                return Some(found);
             }
+        } else if path.file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name == filename)
+        {
+            return Some(path);
         }
     }
     
@@ -119,6 +117,13 @@ impl AppManager {
             println!("{}", ui::success(&format!("Command '{}' is now available", cmd.command)));
         }
 
+        // The links and modes set up above are part of the package from now
+        // on, so that `diem verify` does not report them
+        if !app.commands.is_empty() {
+            let package = &app.packages[0];
+            self.package_manager.record_files(&package.name, &package.version)?;
+        }
+
         pb.finish_with_message(ui::success(&format!(
             "Successfully installed {} {}",
             app.name, app.version
@@ -1,7 +1,9 @@
 /// This files defines an App as defined in an app configuration file.
 pub(crate) mod command;
+pub mod info;
 pub(crate) mod manager;
 
+use anyhow::Result;
 use semver::Version;
 use serde::{Deserialize, Serialize};
 
@@ -16,4 +18,33 @@ pub struct App {
     pub license: String,
     pub app_handler_version: u8,
     pub description: Option<String>,
+    /// Free-form keywords used by search filters, e.g. `editor`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Broad groups the app belongs to, e.g. `development`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub categories: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub homepage: Option<String>,
+    /// Where the app's source code lives
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repository: Option<String>,
+    /// People responsible for the app, e.g. `Jane Doe <jane@example.org>`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub maintainers: Vec<String>,
+    /// Long, possibly multi-line description shown by `diem info`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub readme: Option<String>,
+}
+
+/// Splits an `app[@version]` argument.
+pub fn parse_spec(spec: &str) -> Result<(String, Option<Version>)> {
+    match spec.split_once('@') {
+        Some((name, version)) => {
+            let version = Version::parse(version)
+                .map_err(|e| anyhow::anyhow!("Invalid version '{}' in '{}': {}", version, spec, e))?;
+            Ok((name.to_string(), Some(version)))
+        }
+        None => Ok((spec.to_string(), None)),
+    }
 }
@@ -0,0 +1,310 @@
+//! Generating artifactory entries from upstream GitHub releases.
+//!
+//! A release's assets are matched to target triples from their file names,
+//! e.g. `eza_x86_64-unknown-linux-gnu.tar.gz` or `gh_2.40.0_linux_amd64.tar.gz`.
+//! Each matching asset is downloaded once to compute its sha256 and becomes
+//! a package variant pointing at the upstream download URL.
+
+use anyhow::Result;
+use reqwest::Client;
+use semver::Version;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use std::path::{Path, PathBuf};
+
+use crate::{
+    App, AppCommand, PackageVariant,
+    artifactory::pack,
+    package::archive::{self, ArchiveFormat},
+    platform,
+};
+
+/// The parts of a GitHub release, as returned by the REST API, that diem
+/// needs.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Release {
+    pub tag_name: String,
+    #[serde(default)]
+    pub assets: Vec<ReleaseAsset>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReleaseAsset {
+    pub name: String,
+    pub browser_download_url: String,
+}
+
+/// What to import and where to register it.
+#[derive(Debug, Clone)]
+pub struct ImportRequest {
+    /// Artifactory file to update
+    pub artifactory: PathBuf,
+    /// Name of the app and package
+    pub name: String,
+    /// Overrides the version parsed from the release tag
+    pub version: Option<Version>,
+    /// Required when the app or package does not exist yet
+    pub license: Option<String>,
+    pub description: Option<String>,
+    /// Commands to expose; when empty, a file named after the app is looked
+    /// up in the first archive
+    pub commands: Vec<AppCommand>,
+}
+
+/// Splits an `owner/repo` argument.
+pub fn parse_repo(spec: &str) -> Result<(String, String)> {
+    match spec.split_once('/') {
+        Some((owner, repo)) if !owner.is_empty() && !repo.is_empty() && !repo.contains('/') => {
+            Ok((owner.to_string(), repo.to_string()))
+        }
+        _ => anyhow::bail!("Invalid repository '{}', expected owner/repo", spec),
+    }
+}
+
+/// Fetches a release from the GitHub API, the latest one when `tag` is
+/// `None`. `GITHUB_TOKEN` is used when set, to avoid rate limits.
+pub async fn fetch_release(owner: &str, repo: &str, tag: Option<&str>) -> Result<Release> {
+    let url = match tag {
+        Some(tag) => format!("https://api.github.com/repos/{}/{}/releases/tags/{}", owner, repo, tag),
+        None => format!("https://api.github.com/repos/{}/{}/releases/latest", owner, repo),
+    };
+
+    let mut request = Client::new()
+        .get(&url)
+        .header(reqwest::header::USER_AGENT, "diem")
+        .header(reqwest::header::ACCEPT, "application/vnd.github+json");
+    if let Ok(token) = std::env::var("GITHUB_TOKEN") {
+        request = request.bearer_auth(token);
+    }
+
+    let response = request.send().await?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        anyhow::bail!("Release {} not found in {}/{}", tag.unwrap_or("latest"), owner, repo);
+    }
+
+    let content = response.error_for_status()?.text().await?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+/// Reads a release saved from the GitHub API, e.g. with
+/// `gh api repos/eza-community/eza/releases/latest > release.json`.
+pub fn load_release(path: &Path) -> Result<Release> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read release file {}: {}", path.display(), e))?;
+    serde_json::from_str(&content).map_err(|e| anyhow::anyhow!("Invalid release file {}: {}", path.display(), e))
+}
+
+/// Parses a version out of a release tag such as `v0.20.16` or
+/// `ripgrep-14.1.0`.
+pub fn version_from_tag(tag: &str) -> Result<Version> {
+    let start = tag.find(|c: char| c.is_ascii_digit()).unwrap_or(0);
+    Version::parse(&tag[start..])
+        .map_err(|e| anyhow::anyhow!("Cannot read a version from tag '{}' ({}), pass --version", tag, e))
+}
+
+/// Guesses the target triple an asset was built for from its file name.
+/// Returns `None` for assets that are not package archives or that do not
+/// name both an architecture and an operating system.
+pub fn asset_target(name: &str) -> Option<String> {
+    let name = name.to_lowercase();
+    ArchiveFormat::from_source(&name).ok()?;
+
+    let stem = [".tar.gz", ".tgz", ".tar", ".zip"]
+        .iter()
+        .find_map(|extension| name.strip_suffix(extension))
+        .unwrap_or(&name);
+
+    // `x86_64` is split by the `_` separator, glue it back together
+    let raw: Vec<&str> = stem.split(['-', '_', '.']).collect();
+    let mut tokens = Vec::with_capacity(raw.len());
+    let mut i = 0;
+    while i < raw.len() {
+        if raw[i] == "x86" && raw.get(i + 1) == Some(&"64") {
+            tokens.push("x86_64");
+            i += 2;
+        } else {
+            tokens.push(raw[i]);
+            i += 1;
+        }
+    }
+
+    let arch = tokens.iter().find(|token| is_arch(token)).map(|arch| platform::normalize_arch(arch))?;
+    let os_idx = tokens.iter().position(|token| os_name(token).is_some())?;
+    let os = os_name(tokens[os_idx])?;
+    let env = tokens[os_idx + 1..]
+        .iter()
+        .find(|token| token.starts_with("gnu") || token.starts_with("musl") || **token == "msvc");
+
+    Some(match (os, env) {
+        ("linux", Some(env)) => format!("{}-unknown-linux-{}", arch, env),
+        ("linux", None) => format!("{}-unknown-linux", arch),
+        ("macos", _) => format!("{}-apple-darwin", arch),
+        (_, Some(env)) => format!("{}-pc-windows-{}", arch, env),
+        (_, None) => format!("{}-pc-windows-msvc", arch),
+    })
+}
+
+fn is_arch(token: &str) -> bool {
+    matches!(
+        token,
+        "x86_64" | "amd64" | "x64" | "aarch64" | "arm64" | "i686" | "i586" | "i386" | "x86" | "arm" | "armv6"
+            | "armv7" | "armv7l" | "riscv64" | "riscv64gc" | "powerpc64le" | "ppc64le" | "s390x"
+    )
+}
+
+fn os_name(token: &str) -> Option<&'static str> {
+    match token {
+        "linux" => Some("linux"),
+        "darwin" | "apple" | "macos" | "osx" => Some("macos"),
+        "windows" | "win64" | "win32" => Some("windows"),
+        _ => None,
+    }
+}
+
+/// Picks one asset per target triple. When a target is published in several
+/// formats, tarballs win over zips.
+pub fn match_assets(release: &Release) -> Vec<(String, &ReleaseAsset)> {
+    let preference = |asset: &ReleaseAsset| match ArchiveFormat::from_source(&asset.name.to_lowercase()) {
+        Ok(ArchiveFormat::TarGz) => 0,
+        Ok(ArchiveFormat::Tar) => 1,
+        _ => 2,
+    };
+
+    let mut matched: Vec<(String, &ReleaseAsset)> = Vec::new();
+    for asset in &release.assets {
+        let Some(target) = asset_target(&asset.name) else {
+            continue;
+        };
+
+        match matched.iter_mut().find(|(existing, _)| *existing == target) {
+            Some(entry) if preference(asset) < preference(entry.1) => entry.1 = asset,
+            Some(_) => {}
+            None => matched.push((target, asset)),
+        }
+    }
+
+    matched
+}
+
+/// Downloads and hashes the assets of `release` matching a target, and
+/// inserts or updates the app in the artifactory with one package variant
+/// per target. Returns the registered app.
+pub async fn import(request: &ImportRequest, release: &Release) -> Result<App> {
+    let version = match &request.version {
+        Some(version) => version.clone(),
+        None => version_from_tag(&release.tag_name)?,
+    };
+
+    let matched = match_assets(release);
+    if matched.is_empty() {
+        anyhow::bail!(
+            "No asset of release {} matches a target triple. Assets: {}",
+            release.tag_name,
+            release.assets.iter().map(|asset| asset.name.as_str()).collect::<Vec<_>>().join(", ")
+        );
+    }
+
+    let (mut artifactory, format) = pack::read_artifactory(&request.artifactory)?;
+
+    let download_dir = DownloadDir::create()?;
+    let downloaded = download_all(&matched, &download_dir.0).await?;
+
+    let mut commands = request.commands.clone();
+    if commands.is_empty() {
+        let (_, asset) = matched[0];
+        let entries = archive::list_entries(&downloaded[0].0, &asset.name)?;
+        commands.extend(find_command(&request.name, &entries));
+    }
+    drop(download_dir);
+
+    let app = pack::upsert_app(&mut artifactory, &request.name, &version, request.license.as_deref())?;
+    if let Some(description) = &request.description {
+        app.description = Some(description.clone());
+    }
+    pack::upsert_commands(app, &commands);
+
+    let package = pack::upsert_package(app, &request.name, request.license.as_deref())?;
+    for ((target, asset), (_, sha256)) in matched.iter().zip(downloaded) {
+        pack::upsert_variant(package, PackageVariant {
+            target: target.clone(),
+            source: asset.browser_download_url.clone(),
+            sha256,
+        });
+    }
+
+    let app = app.clone();
+    pack::write_artifactory(&request.artifactory, &artifactory, format)?;
+
+    Ok(app)
+}
+
+// Temporary directory assets are downloaded to, removed when dropped
+struct DownloadDir(PathBuf);
+
+impl DownloadDir {
+    fn create() -> Result<Self> {
+        for attempt in 0u32.. {
+            let path = std::env::temp_dir().join(format!("diem-import-{}-{}", std::process::id(), attempt));
+            match std::fs::create_dir(&path) {
+                Ok(()) => return Ok(Self(path)),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        unreachable!("ran out of download directory names")
+    }
+}
+
+impl Drop for DownloadDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+// Downloads every matched asset into `dir`, returning their paths and digests
+async fn download_all(matched: &[(String, &ReleaseAsset)], dir: &Path) -> Result<Vec<(PathBuf, String)>> {
+    let client = Client::new();
+    let mut downloaded = Vec::with_capacity(matched.len());
+    for (idx, (_, asset)) in matched.iter().enumerate() {
+        let path = dir.join(format!("{}-{}", idx, asset.name));
+        download(&client, &asset.browser_download_url, &path).await?;
+
+        let hash = format!("{:x}", Sha256::digest(std::fs::read(&path)?));
+        downloaded.push((path, hash));
+    }
+    Ok(downloaded)
+}
+
+// Fetches a download URL. `file://` URLs are copied, which lets releases
+// saved to disk point at local archives.
+async fn download(client: &Client, url: &str, destination: &Path) -> Result<()> {
+    if let Some(path) = url.strip_prefix("file://") {
+        std::fs::copy(path, destination)
+            .map_err(|e| anyhow::anyhow!("Failed to copy asset {}: {}", path, e))?;
+        return Ok(());
+    }
+
+    let response = client
+        .get(url)
+        .header(reqwest::header::USER_AGENT, "diem")
+        .send()
+        .await?
+        .error_for_status()?;
+    tokio::fs::write(destination, response.bytes().await?).await?;
+    Ok(())
+}
+
+// Looks for the app's executable, e.g. `eza` or `eza-x86_64/eza`
+fn find_command(name: &str, entries: &[PathBuf]) -> Option<AppCommand> {
+    let windows_name = format!("{}.exe", name);
+    entries
+        .iter()
+        .filter(|entry| {
+            entry
+                .file_name()
+                .is_some_and(|file_name| file_name == name || file_name == windows_name.as_str())
+        })
+        .min_by_key(|entry| entry.components().count())
+        .map(|path| AppCommand { command: name.to_string(), path: path.clone() })
+}
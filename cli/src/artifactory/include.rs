@@ -0,0 +1,342 @@
+//! Composing an artifactory from the files it `include`s.
+//!
+//! A campus-wide artifactory can be assembled from per-club files:
+//!
+//! ```toml
+//! include = [
+//!     "clubs/42ai.toml",
+//!     { source = "https://example.org/artifactory.toml", public_key = "RW..." },
+//! ]
+//! ```
+//!
+//! Relative includes and package sources are resolved against the file that
+//! declares them. An included file must be signed with the `public_key` of
+//! its include entry, or else with the key that verified the including file.
+//! When two files define the same app, the first definition wins and the
+//! collision is reported.
+
+use anyhow::Result;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::{App, Artifactory, Package, schema::{self, Format}, signature, utils::ui};
+
+// Paths spelled differently, like `a/../b.toml`, can hide an include cycle
+const MAX_DEPTH: usize = 16;
+
+/// An entry of an artifactory's `include` list, either a bare source or a
+/// table pinning the key the included file is signed with.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Include {
+    Source(String),
+    Pinned {
+        source: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        public_key: Option<String>,
+    },
+}
+
+impl Include {
+    pub fn source(&self) -> &str {
+        match self {
+            Self::Source(source) | Self::Pinned { source, .. } => source,
+        }
+    }
+
+    pub fn public_key(&self) -> Option<&str> {
+        match self {
+            Self::Source(_) => None,
+            Self::Pinned { public_key, .. } => public_key.as_deref(),
+        }
+    }
+}
+
+/// Where an artifactory file lives.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Location {
+    Path(PathBuf),
+    Url(String),
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Path(path) => write!(f, "{}", path.display()),
+            Self::Url(url) => write!(f, "{}", url),
+        }
+    }
+}
+
+impl Location {
+    pub fn parse(source: &str) -> Self {
+        if is_url(source) {
+            Self::Url(source.to_string())
+        } else {
+            Self::Path(PathBuf::from(source))
+        }
+    }
+
+    /// Resolves `reference` against the directory of this file. URLs and
+    /// absolute paths are returned as they are.
+    pub fn join(&self, reference: &str) -> Self {
+        if is_url(reference) {
+            return Self::Url(reference.to_string());
+        }
+
+        match self {
+            Self::Path(path) => Self::Path(path.parent().unwrap_or(Path::new("")).join(reference)),
+            Self::Url(url) => Self::Url(join_url(url, reference)),
+        }
+    }
+
+    /// The format of this file, from its extension or else its content.
+    pub fn format(&self, content: &str) -> Format {
+        match self {
+            Self::Path(path) => Format::for_file(path, content),
+            Self::Url(url) => {
+                let path = url.split(['?', '#']).next().unwrap_or(url);
+                Format::for_file(Path::new(path), content)
+            }
+        }
+    }
+
+    /// Reads the file and its detached signature, if any.
+    pub async fn read(&self) -> Result<(String, Option<String>)> {
+        match self {
+            Self::Path(path) => signature::read_with_signature(path),
+            Self::Url(url) => {
+                let client = reqwest::Client::new();
+                let content = client.get(url).send().await?.error_for_status()?.text().await?;
+
+                let response = client.get(format!("{}.{}", url, signature::SIGNATURE_EXTENSION)).send().await?;
+                let signature = if response.status() == reqwest::StatusCode::NOT_FOUND {
+                    None
+                } else {
+                    Some(response.error_for_status()?.text().await?)
+                };
+
+                Ok((content, signature))
+            }
+        }
+    }
+}
+
+fn is_url(source: &str) -> bool {
+    source.starts_with("http://") || source.starts_with("https://")
+}
+
+// Resolves a relative reference against a URL, handling `.` and `..`
+fn join_url(base: &str, reference: &str) -> String {
+    let path_start = base.find("://").map(|idx| idx + 3).unwrap_or(0);
+    let path_start = base[path_start..].find('/').map(|idx| idx + path_start).unwrap_or(base.len());
+    let (origin, path) = base.split_at(path_start);
+
+    let mut segments: Vec<&str> = if reference.starts_with('/') {
+        Vec::new()
+    } else {
+        let mut segments: Vec<&str> = path.split('/').filter(|segment| !segment.is_empty()).collect();
+        // Drop the file name of the base
+        if !path.ends_with('/') {
+            segments.pop();
+        }
+        segments
+    };
+
+    for segment in reference.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+
+    format!("{}/{}", origin, segments.join("/"))
+}
+
+/// How included files are checked before they are trusted.
+#[derive(Debug, Clone, Copy)]
+pub enum Trust<'a> {
+    /// Same policy as subscriptions: signed with the pinned key, unless
+    /// unsigned artifactories are allowed
+    Pinned {
+        public_key: Option<&'a str>,
+        allow_unsigned: bool,
+    },
+    /// No signature checks, for tools that only look at the content
+    Unchecked,
+}
+
+/// Two files defining the same app.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Collision {
+    pub app: String,
+    pub version: Version,
+    /// File whose definition is used
+    pub kept: String,
+    /// File whose definition was ignored
+    pub ignored: String,
+}
+
+/// A file that was merged in, with the digest of its content and signature
+/// at the time.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct IncludedFile {
+    pub location: Location,
+    pub digest: String,
+}
+
+/// An artifactory with its includes merged in.
+#[derive(Debug, Clone)]
+pub struct Composition {
+    pub artifactory: Artifactory,
+    pub collisions: Vec<Collision>,
+    pub included: Vec<IncludedFile>,
+}
+
+/// Prints a warning for each collision.
+pub fn warn_collisions(collisions: &[Collision]) {
+    for collision in collisions {
+        println!("{}", ui::warning(&format!(
+            "{} {} is defined by both {} and {}, using the former",
+            collision.app, collision.version, collision.kept, collision.ignored
+        )));
+    }
+}
+
+/// Merges the apps of every file `root` includes, recursively, into it.
+/// `root` itself must already be verified.
+pub async fn compose(root: Artifactory, location: &Location, trust: Trust<'_>) -> Result<Composition> {
+    let mut composer = Composer {
+        origins: HashMap::new(),
+        collisions: Vec::new(),
+        included: Vec::new(),
+        stack: vec![location.clone()],
+    };
+
+    for app in &root.apps {
+        composer.origins.entry((app.name.clone(), app.version.clone())).or_insert_with(|| location.to_string());
+    }
+
+    let mut artifactory = root;
+    let includes = artifactory.include.clone();
+    let mut apps = Vec::new();
+    composer.include_all(&includes, location, trust, &mut apps).await?;
+    artifactory.apps.extend(apps);
+
+    Ok(Composition { artifactory, collisions: composer.collisions, included: composer.included })
+}
+
+/// Digest identifying a version of a file and its signature.
+pub fn digest(content: &str, signature: Option<&str>) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(content.as_bytes());
+    hasher.update([0]);
+    hasher.update(signature.unwrap_or_default().as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+struct Composer {
+    origins: HashMap<(String, Version), String>,
+    collisions: Vec<Collision>,
+    included: Vec<IncludedFile>,
+    // Files being included, to detect cycles
+    stack: Vec<Location>,
+}
+
+impl Composer {
+    async fn include_all(
+        &mut self,
+        includes: &[Include],
+        parent: &Location,
+        trust: Trust<'_>,
+        apps: &mut Vec<App>,
+    ) -> Result<()> {
+        for include in includes {
+            let location = parent.join(include.source());
+            if self.stack.contains(&location) {
+                anyhow::bail!("Include cycle: {} includes {} again", parent, location);
+            }
+            if self.stack.len() > MAX_DEPTH {
+                anyhow::bail!("Includes nested more than {} levels deep at {}", MAX_DEPTH, location);
+            }
+
+            let (content, signature) = location
+                .read()
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to read {} included by {}: {}", location, parent, e))?;
+            self.included.push(IncludedFile {
+                location: location.clone(),
+                digest: digest(&content, signature.as_deref()),
+            });
+
+            let trust = match trust {
+                Trust::Pinned { public_key, allow_unsigned } => {
+                    let public_key = include.public_key().or(public_key);
+                    signature::verify_artifactory(
+                        &location.to_string(),
+                        &content,
+                        signature.as_deref(),
+                        public_key,
+                        allow_unsigned,
+                    )?;
+                    Trust::Pinned { public_key, allow_unsigned }
+                }
+                Trust::Unchecked => Trust::Unchecked,
+            };
+
+            let included = schema::parse_artifactory_as(&content, location.format(&content))
+                .map_err(|e| anyhow::anyhow!("Failed to parse {} included by {}: {}", location, parent, e))?;
+
+            for mut app in included.apps {
+                let key = (app.name.clone(), app.version.clone());
+                if let Some(kept) = self.origins.get(&key) {
+                    self.collisions.push(Collision {
+                        app: app.name,
+                        version: app.version,
+                        kept: kept.clone(),
+                        ignored: location.to_string(),
+                    });
+                    continue;
+                }
+
+                self.origins.insert(key, location.to_string());
+                for package in &mut app.packages {
+                    resolve_sources(package, &location);
+                }
+                apps.push(app);
+            }
+
+            // Boxed because async recursion needs an indirection
+            self.stack.push(location.clone());
+            Box::pin(self.include_all(&included.include, &location, trust, apps)).await?;
+            self.stack.pop();
+        }
+
+        Ok(())
+    }
+}
+
+// Makes relative package sources point next to the file declaring them
+fn resolve_sources(package: &mut Package, location: &Location) {
+    let resolve = |source: &str| match location.join(source) {
+        Location::Path(path) => path.to_string_lossy().to_string(),
+        Location::Url(url) => url,
+    };
+
+    if let Some(source) = &package.source {
+        package.source = Some(resolve(source));
+    }
+    for variant in &mut package.variants {
+        variant.source = resolve(&variant.source);
+    }
+    for dependency in &mut package.dependencies {
+        resolve_sources(dependency, location);
+    }
+}
@@ -0,0 +1,184 @@
+//! Compiled artifactory indexes, cached by content hash.
+//!
+//! Loading an artifactory means checking its signature, upgrading and
+//! parsing it, and merging its includes. The result is cached as a binary
+//! file named after a sha256 of everything that went into it: the root
+//! file's content and signature, the trust settings it was checked with and
+//! the version of diem. The digests of included files are stored alongside
+//! and compared on each load, so an index is reused until any of its source
+//! files changes.
+//!
+//! The cache uses MessagePack rather than bincode because artifactories rely
+//! on skipped and untagged fields, which need a self-describing format.
+
+use anyhow::Result;
+use directories::BaseDirs;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use std::path::PathBuf;
+
+use crate::{
+    Artifactory,
+    artifactory::include::{self, Collision, IncludedFile, Location, Trust},
+    schema, signature,
+};
+
+// Bumped whenever the layout of `CompiledIndex` changes
+const INDEX_FORMAT_VERSION: u8 = 2;
+
+/// An artifactory file as fetched, before it is trusted.
+#[derive(Debug, Clone)]
+pub struct IndexSource<'a> {
+    /// Name shown in signature errors
+    pub name: &'a str,
+    pub location: Location,
+    pub content: String,
+    pub signature: Option<String>,
+    pub public_key: Option<&'a str>,
+    pub allow_unsigned: bool,
+}
+
+impl IndexSource<'_> {
+    // Cache key: changes whenever the compiled index could
+    fn key(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update([INDEX_FORMAT_VERSION, schema::ARTIFACTORY_HANDLER_VERSION, self.allow_unsigned as u8]);
+        hasher.update(env!("CARGO_PKG_VERSION").as_bytes());
+        hasher.update([0]);
+        hasher.update(self.location.to_string().as_bytes());
+        hasher.update([0]);
+        hasher.update(self.public_key.unwrap_or_default().trim().as_bytes());
+        hasher.update([0]);
+        hasher.update(include::digest(&self.content, self.signature.as_deref()).as_bytes());
+        format!("{:x}", hasher.finalize())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CompiledIndex {
+    artifactory: Artifactory,
+    collisions: Vec<Collision>,
+    included: Vec<IncludedFile>,
+    rotated_key: Option<String>,
+}
+
+/// A verified and composed artifactory.
+#[derive(Debug, Clone)]
+pub struct LoadedIndex {
+    pub artifactory: Artifactory,
+    /// Key the root file is signed with, when it rotated away from the
+    /// pinned one
+    pub rotated_key: Option<String>,
+}
+
+impl CompiledIndex {
+    // Checks that every included file still has the digest it was compiled with
+    async fn is_fresh(&self) -> bool {
+        for file in &self.included {
+            match file.location.read().await {
+                Ok((content, signature)) if include::digest(&content, signature.as_deref()) == file.digest => {}
+                _ => return false,
+            }
+        }
+        true
+    }
+}
+
+/// A directory of compiled indexes.
+#[derive(Debug, Clone)]
+pub struct IndexCache {
+    dir: PathBuf,
+}
+
+impl IndexCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// The cache in the user's cache directory, e.g. `~/.cache/diem/index`.
+    pub fn open_default() -> Option<Self> {
+        BaseDirs::new().map(|base_dirs| Self::new(base_dirs.cache_dir().join("diem").join("index")))
+    }
+
+    pub fn dir(&self) -> &PathBuf {
+        &self.dir
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.msgpack", key))
+    }
+
+    async fn get(&self, key: &str) -> Option<CompiledIndex> {
+        let bytes = std::fs::read(self.path(key)).ok()?;
+        let index: CompiledIndex = rmp_serde::from_slice(&bytes).ok()?;
+        index.is_fresh().await.then_some(index)
+    }
+
+    // Writes through a temporary file so readers never see a partial index
+    fn put(&self, key: &str, index: &CompiledIndex) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        let path = self.path(key);
+        let temp_path = path.with_extension(format!("{}.tmp", std::process::id()));
+        std::fs::write(&temp_path, rmp_serde::to_vec_named(index)?)?;
+        std::fs::rename(&temp_path, &path)?;
+        Ok(())
+    }
+}
+
+/// Verifies, parses and composes an artifactory, reusing the compiled index
+/// from `cache` when its sources have not changed. Failing to write the
+/// cache is not an error.
+pub async fn load(cache: Option<&IndexCache>, source: &IndexSource<'_>) -> Result<Artifactory> {
+    Ok(load_index(cache, source).await?.artifactory)
+}
+
+/// Like [`load`], also telling whether the pinned key should change.
+pub async fn load_index(cache: Option<&IndexCache>, source: &IndexSource<'_>) -> Result<LoadedIndex> {
+    let key = source.key();
+    let cached = match cache {
+        Some(cache) => cache.get(&key).await,
+        None => None,
+    };
+    let index = match cached {
+        Some(index) => index,
+        None => {
+            let index = compile(source).await?;
+            if let Some(cache) = cache {
+                let _ = cache.put(&key, &index);
+            }
+            index
+        }
+    };
+
+    include::warn_collisions(&index.collisions);
+    Ok(LoadedIndex { artifactory: index.artifactory, rotated_key: index.rotated_key })
+}
+
+async fn compile(source: &IndexSource<'_>) -> Result<CompiledIndex> {
+    let rotated_key = signature::verify_artifactory(
+        source.name,
+        &source.content,
+        source.signature.as_deref(),
+        source.public_key,
+        source.allow_unsigned,
+    )?;
+
+    let artifactory = schema::parse_artifactory_as(&source.content, source.location.format(&source.content))
+        .map_err(|e| anyhow::anyhow!("Failed to parse artifactory {}: {}", source.location, e))?;
+
+    // Includes that declare no key of their own are signed with the key the
+    // root is signed with now
+    let trust = Trust::Pinned {
+        public_key: rotated_key.as_deref().or(source.public_key),
+        allow_unsigned: source.allow_unsigned,
+    };
+    let composition = include::compose(artifactory, &source.location, trust).await?;
+
+    Ok(CompiledIndex {
+        artifactory: composition.artifactory,
+        collisions: composition.collisions,
+        included: composition.included,
+        rotated_key,
+    })
+}
@@ -1,9 +1,12 @@
 use std::path::Path;
 use std::fs;
 use std::io;
-use toml;
 
-use crate::{Artifactory, config::{ArtifactorySource, ArtifactorySubscription, Config}};
+use crate::{
+    Artifactory,
+    config::{ArtifactorySubscription, Config},
+    schema::Format,
+};
 
 pub struct ArtifactoryManager {
     config: Config,
@@ -19,6 +22,11 @@ impl ArtifactoryManager {
         self.config.subscribed_artifactories.iter().collect()
     }
 
+    // List the implicit subscriptions to the shared artifactory directory
+    pub fn list_shared(&self) -> Vec<ArtifactorySubscription> {
+        self.config.shared_subscriptions()
+    }
+
     // Add a subscription to an artifactory
     pub fn add_subscription(&mut self, sub: ArtifactorySubscription) -> io::Result<()> {
         // Check if already subscribed
@@ -48,66 +56,11 @@ impl ArtifactoryManager {
         Ok(())
     }
 
-    // Load an artifactory from a local or remote source
-    pub fn load_artifactory(&self, subscription: &ArtifactorySubscription) -> io::Result<Artifactory> {
-        match &subscription.source {
-            ArtifactorySource::Local(path) => self.load_from_file(path),
-            ArtifactorySource::Remote(url) => self.load_from_url(url),
-        }
-    }
-
-    // Load all subscribed artifactories
-    pub fn load_all_subscribed(&self) -> Vec<Result<Artifactory, io::Error>> {
-        self.config.subscribed_artifactories
-            .iter()
-            .map(|sub| self.load_artifactory(sub))
-            .collect()
-    }
-
-    // Create a new artifactory
-    pub fn create_artifactory(&self, artifactory: &Artifactory, path: &Path) -> io::Result<()> {
-        let toml_content = toml::to_string_pretty(artifactory)
-            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    // Create a new artifactory, written in the given format
+    pub fn create_artifactory(&self, artifactory: &Artifactory, path: &Path, format: Format) -> io::Result<()> {
+        let content = format.serialize(artifactory)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
         
-        fs::write(path, toml_content)
+        fs::write(path, content)
     }
-
-    // Search for apps in all subscribed artifactories
-    pub fn search_apps(&self, query: &str) -> io::Result<Vec<(String, Vec<String>)>> {
-        let mut results = Vec::new();
-
-        for sub in &self.config.subscribed_artifactories {
-            match self.load_artifactory(sub) {
-                Ok(artifactory) => {
-                    let matching_apps: Vec<String> = artifactory.apps
-                        .iter()
-                        .filter(|app| app.name.contains(query))
-                        .map(|app| app.name.clone())
-                        .collect();
-                    
-                    if !matching_apps.is_empty() {
-                        results.push((artifactory.name, matching_apps));
-                    }
-                }
-                Err(_) => continue,
-            }
-        }
-
-        Ok(results)
-    }
-
-    // Private methods
-    fn load_from_file(&self, path: &Path) -> io::Result<Artifactory> {
-        let content = fs::read_to_string(path)?;
-        toml::from_str(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
-    }
-
-    fn load_from_url(&self, _url: &str) -> io::Result<Artifactory> {
-        // This would use a HTTP client to fetch the artifactory file
-        // For simplicity, we'll return an error for now
-        Err(io::Error::new(
-            io::ErrorKind::Unsupported,
-            "Remote artifactory loading not implemented yet",
-        ))
-    }
-}
\ No newline at end of file
+}
@@ -1,8 +1,16 @@
 pub mod manager;
+pub mod import;
+pub mod include;
+pub mod index;
+pub mod pack;
+pub mod search;
+pub mod validate;
 
 use serde::{Deserialize, Serialize};
 
-use crate::App;
+use crate::{App, signature::KeyRotation};
+
+use include::Include;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Artifactory {
@@ -12,4 +20,13 @@ pub struct Artifactory {
     pub maintainer: Option<String>,
     pub public: bool,
     pub artifactory_handler_version: u8,
+    /// Minisign public key the maintainer signs this artifactory with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+    /// Chain of signed key rotations, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub key_rotations: Vec<KeyRotation>,
+    /// Other artifactory files whose apps are merged into this one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<Include>,
 }
@@ -0,0 +1,216 @@
+use anyhow::Result;
+use semver::Version;
+
+use std::path::{Path, PathBuf};
+
+use crate::{
+    App, AppCommand, Artifactory, Package, PackageVariant,
+    package::archive,
+    platform::Platform,
+    schema::{self, Format},
+    signature,
+    utils::ui,
+};
+
+/// What to pack and where to register it.
+#[derive(Debug, Clone)]
+pub struct PackRequest {
+    /// Directory whose contents become the package archive
+    pub dir: PathBuf,
+    /// Artifactory file to update; packages are stored next to it
+    pub artifactory: PathBuf,
+    pub name: String,
+    pub version: Version,
+    /// Target triple of the build, or `None` for a platform-independent one
+    pub target: Option<String>,
+    /// Required when the app or package does not exist yet
+    pub license: Option<String>,
+    pub description: Option<String>,
+    pub commands: Vec<AppCommand>,
+}
+
+/// Parses a `--command` argument: `name=path`, or just `path` to name the
+/// command after the file.
+pub fn parse_command(spec: &str) -> Result<AppCommand> {
+    let (command, path) = match spec.split_once('=') {
+        Some((command, path)) => (command.to_string(), PathBuf::from(path)),
+        None => {
+            let path = PathBuf::from(spec);
+            let command = path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .ok_or_else(|| anyhow::anyhow!("Invalid command: {}", spec))?;
+            (command, path)
+        }
+    };
+
+    if command.is_empty() || path.as_os_str().is_empty() {
+        anyhow::bail!("Invalid command '{}', expected name=path", spec);
+    }
+
+    Ok(AppCommand { command, path })
+}
+
+/// Packs `request.dir`, copies the archive into the artifactory's
+/// `packages` directory, and inserts or updates the matching `App` and
+/// `Package` entries. Returns the registered package.
+pub fn pack(request: &PackRequest) -> Result<Package> {
+    if let Some(target) = &request.target {
+        Platform::parse(target)?;
+    }
+
+    let (mut artifactory, format) = read_artifactory(&request.artifactory)?;
+
+    // Same naming scheme as the existing packages, e.g. eza_x86_64-unknown-linux-gnu-v0.20.16.tar.gz
+    let file_name = match &request.target {
+        Some(target) => format!("{}_{}-v{}.tar.gz", request.name, target, request.version),
+        None => format!("{}-v{}.tar.gz", request.name, request.version),
+    };
+    let source = format!("packages/{}", file_name);
+
+    // Everything that can be refused is checked before the archive is
+    // written, so a refused pack leaves no archive behind
+    let app = upsert_app(&mut artifactory, &request.name, &request.version, request.license.as_deref())?;
+    if let Some(description) = &request.description {
+        app.description = Some(description.clone());
+    }
+    upsert_commands(app, &request.commands);
+
+    let package = upsert_package(app, &request.name, request.license.as_deref())?;
+    if request.target.is_none() && !package.variants.is_empty() {
+        anyhow::bail!(
+            "Package {} {} has per-target variants, pass --target to add another one",
+            package.name,
+            package.version
+        );
+    }
+
+    let base_dir = request.artifactory.parent().unwrap_or(Path::new(""));
+    let sha256 = archive::pack_directory(&request.dir, &base_dir.join(&source))?;
+    match &request.target {
+        Some(target) => upsert_variant(package, PackageVariant { target: target.clone(), source, sha256 }),
+        None => {
+            package.source = Some(source);
+            package.sha256 = sha256;
+        }
+    }
+
+    let package = package.clone();
+    write_artifactory(&request.artifactory, &artifactory, format)?;
+
+    Ok(package)
+}
+
+/// Reads and parses the artifactory file being edited, along with the
+/// format to write it back in.
+pub(crate) fn read_artifactory(path: &Path) -> Result<(Artifactory, Format)> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read artifactory file {}: {}", path.display(), e))?;
+    let format = Format::for_file(path, &content);
+    Ok((schema::parse_artifactory_as(&content, format)?, format))
+}
+
+/// Finds the app with this name and version, or adds it. New apps need a
+/// `license`.
+pub(crate) fn upsert_app<'a>(
+    artifactory: &'a mut Artifactory,
+    name: &str,
+    version: &Version,
+    license: Option<&str>,
+) -> Result<&'a mut App> {
+    if let Some(idx) = artifactory.apps.iter().position(|app| app.name == name && app.version == *version) {
+        return Ok(&mut artifactory.apps[idx]);
+    }
+
+    artifactory.apps.push(App {
+        name: name.to_string(),
+        packages: Vec::new(),
+        version: version.clone(),
+        commands: Vec::new(),
+        license: required_license(license, "app")?,
+        app_handler_version: schema::APP_HANDLER_VERSION,
+        description: None,
+        tags: Vec::new(),
+        categories: Vec::new(),
+        homepage: None,
+        repository: None,
+        maintainers: Vec::new(),
+        readme: None,
+    });
+    Ok(artifactory.apps.last_mut().expect("app was just pushed"))
+}
+
+/// Adds `commands` to the app, replacing the path of those it already has.
+pub(crate) fn upsert_commands(app: &mut App, commands: &[AppCommand]) {
+    for cmd in commands {
+        match app.commands.iter_mut().find(|existing| existing.command == cmd.command) {
+            Some(existing) => existing.path = cmd.path.clone(),
+            None => app.commands.push(cmd.clone()),
+        }
+    }
+}
+
+/// Finds the app's package with this name, or adds it. A given `license`
+/// replaces the existing one.
+pub(crate) fn upsert_package<'a>(app: &'a mut App, name: &str, license: Option<&str>) -> Result<&'a mut Package> {
+    let idx = match app.packages.iter().position(|package| package.name == name) {
+        Some(idx) => idx,
+        None => {
+            app.packages.push(Package {
+                name: name.to_string(),
+                version: app.version.clone(),
+                sha256: String::new(),
+                license: required_license(license, "package")?,
+                source: None,
+                dependencies: Vec::new(),
+                package_handler_version: schema::PACKAGE_HANDLER_VERSION,
+                variants: Vec::new(),
+            });
+            app.packages.len() - 1
+        }
+    };
+
+    let package = &mut app.packages[idx];
+    if let Some(license) = license {
+        package.license = license.to_string();
+    }
+    Ok(package)
+}
+
+/// Adds or replaces the variant for `variant.target`. A platform-independent
+/// source would shadow the variants, so it is dropped.
+pub(crate) fn upsert_variant(package: &mut Package, variant: PackageVariant) {
+    if let Some(old_source) = package.source.take() {
+        println!("{}", ui::warning(&format!(
+            "Replacing platform-independent source {} with per-target variants",
+            old_source
+        )));
+        package.sha256.clear();
+    }
+
+    match package.variants.iter_mut().find(|existing| existing.target == variant.target) {
+        Some(existing) => *existing = variant,
+        None => package.variants.push(variant),
+    }
+}
+
+/// Writes the artifactory back, warning when its signature no longer
+/// matches.
+pub(crate) fn write_artifactory(path: &Path, artifactory: &Artifactory, format: Format) -> Result<()> {
+    std::fs::write(path, format.serialize(artifactory)?)?;
+
+    if signature::signature_path(path).exists() {
+        println!("{}", ui::warning(&format!(
+            "The artifactory signature is now stale, sign it again with: minisign -Sm {}",
+            path.display()
+        )));
+    }
+
+    Ok(())
+}
+
+fn required_license(license: Option<&str>, kind: &str) -> Result<String> {
+    license
+        .map(str::to_string)
+        .ok_or_else(|| anyhow::anyhow!("A new {} needs a license, pass --license with an SPDX expression", kind))
+}
@@ -0,0 +1,227 @@
+//! Ranked app search across artifactories.
+//!
+//! Every app is matched against its name, commands, tags and description,
+//! and scored by its best field: a match in the name outranks one in a
+//! command or tag, which outranks one in the description. Within the name,
+//! an exact match beats a prefix, which beats anything else.
+//!
+//! Tag and category filters narrow the results down to apps carrying all of
+//! them. With filters, the pattern may be left empty to list every app that
+//! passes them.
+
+use anyhow::Result;
+use fuzzy_matcher::{FuzzyMatcher as _, skim::SkimMatcherV2};
+use regex::{Regex, RegexBuilder};
+use semver::Version;
+
+use std::cmp::Reverse;
+
+use crate::{App, Artifactory};
+
+/// How the query is matched against app fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MatchMode {
+    /// Query characters must appear in order, possibly with gaps
+    #[default]
+    Fuzzy,
+    /// The query must appear as is
+    Exact,
+    /// The query is a regular expression
+    Regex,
+}
+
+/// The app field a hit was found in, from most to least relevant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Field {
+    Name,
+    Command,
+    Tag,
+    Description,
+}
+
+impl Field {
+    fn weight(self) -> i64 {
+        match self {
+            Self::Name => 4,
+            Self::Command | Self::Tag => 2,
+            Self::Description => 1,
+        }
+    }
+}
+
+impl std::fmt::Display for Field {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Name => write!(f, "name"),
+            Self::Command => write!(f, "command"),
+            Self::Tag => write!(f, "tag"),
+            Self::Description => write!(f, "description"),
+        }
+    }
+}
+
+/// A compiled search query.
+pub struct SearchQuery {
+    pattern: String,
+    case_sensitive: bool,
+    matcher: Matcher,
+    tags: Vec<String>,
+    categories: Vec<String>,
+}
+
+enum Matcher {
+    Fuzzy(Box<SkimMatcherV2>),
+    Exact,
+    Regex(Regex),
+}
+
+impl SearchQuery {
+    /// Compiles `pattern`, which fails only for invalid regular expressions.
+    pub fn new(pattern: &str, mode: MatchMode, case_sensitive: bool) -> Result<Self> {
+        let matcher = match mode {
+            MatchMode::Fuzzy => {
+                let matcher = SkimMatcherV2::default();
+                Matcher::Fuzzy(Box::new(if case_sensitive { matcher.respect_case() } else { matcher.ignore_case() }))
+            }
+            MatchMode::Exact => Matcher::Exact,
+            MatchMode::Regex => Matcher::Regex(
+                RegexBuilder::new(pattern)
+                    .case_insensitive(!case_sensitive)
+                    .build()
+                    .map_err(|e| anyhow::anyhow!("Invalid regular expression '{}': {}", pattern, e))?,
+            ),
+        };
+
+        Ok(Self {
+            pattern: pattern.to_string(),
+            case_sensitive,
+            matcher,
+            tags: Vec::new(),
+            categories: Vec::new(),
+        })
+    }
+
+    /// Only matches apps carrying every one of `tags`, ignoring case.
+    pub fn with_tags(mut self, tags: Vec<String>) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    /// Only matches apps in every one of `categories`, ignoring case.
+    pub fn with_categories(mut self, categories: Vec<String>) -> Self {
+        self.categories = categories;
+        self
+    }
+
+    // Checks the tag and category filters
+    fn passes_filters(&self, app: &App) -> bool {
+        let has_all = |wanted: &[String], values: &[String]| {
+            wanted.iter().all(|wanted| values.iter().any(|value| value.eq_ignore_ascii_case(wanted)))
+        };
+        has_all(&self.tags, &app.tags) && has_all(&self.categories, &app.categories)
+    }
+
+    // Folds case unless the search is case-sensitive
+    fn normalize<'a>(&self, text: &'a str) -> std::borrow::Cow<'a, str> {
+        if self.case_sensitive {
+            text.into()
+        } else {
+            text.to_lowercase().into()
+        }
+    }
+
+    // Scores a single field value, `None` if it does not match
+    fn score(&self, text: &str) -> Option<i64> {
+        match &self.matcher {
+            Matcher::Fuzzy(matcher) => matcher.fuzzy_match(text, &self.pattern),
+            Matcher::Exact => {
+                let (text, pattern) = (self.normalize(text), self.normalize(&self.pattern));
+                // Earlier and tighter matches rank higher
+                text.find(pattern.as_ref())
+                    .map(|idx| 1000 - idx.min(500) as i64 - text.len().saturating_sub(pattern.len()).min(400) as i64)
+            }
+            Matcher::Regex(regex) => regex.find(text).map(|found| 1000 - found.start().min(500) as i64),
+        }
+    }
+
+    fn is_exact_name(&self, name: &str) -> bool {
+        !matches!(self.matcher, Matcher::Regex(_)) && self.normalize(name) == self.normalize(&self.pattern)
+    }
+
+    fn is_name_prefix(&self, name: &str) -> bool {
+        !matches!(self.matcher, Matcher::Regex(_))
+            && self.normalize(name).starts_with(self.normalize(&self.pattern).as_ref())
+    }
+
+    /// Scores an app by its best matching field.
+    pub fn match_app(&self, app: &App) -> Option<(i64, Field)> {
+        if !self.passes_filters(app) {
+            return None;
+        }
+        if self.pattern.is_empty() {
+            return Some((0, Field::Name));
+        }
+
+        let mut fields = vec![(Field::Name, app.name.as_str())];
+        fields.extend(app.commands.iter().map(|cmd| (Field::Command, cmd.command.as_str())));
+        fields.extend(app.tags.iter().map(|tag| (Field::Tag, tag.as_str())));
+        if let Some(description) = &app.description {
+            fields.push((Field::Description, description.as_str()));
+        }
+
+        let (score, field) = fields
+            .into_iter()
+            .filter_map(|(field, text)| self.score(text).map(|score| (score * field.weight(), field)))
+            .max_by_key(|&(score, field)| (score, Reverse(field)))?;
+
+        let bonus = if self.is_exact_name(&app.name) {
+            100_000
+        } else if self.is_name_prefix(&app.name) {
+            50_000
+        } else {
+            0
+        };
+
+        Some((score + bonus, field))
+    }
+}
+
+/// An app matching a search.
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    /// Name of the artifactory the app comes from
+    pub artifactory: String,
+    pub name: String,
+    pub version: Version,
+    pub description: Option<String>,
+    /// Field the best match was found in
+    pub field: Field,
+    pub score: i64,
+}
+
+/// Searches every app version of `artifactories`, best matches first.
+pub fn search<'a>(artifactories: impl IntoIterator<Item = &'a Artifactory>, query: &SearchQuery) -> Vec<SearchHit> {
+    let mut hits: Vec<SearchHit> = artifactories
+        .into_iter()
+        .flat_map(|artifactory| {
+            artifactory.apps.iter().filter_map(|app| {
+                query.match_app(app).map(|(score, field)| SearchHit {
+                    artifactory: artifactory.name.clone(),
+                    name: app.name.clone(),
+                    version: app.version.clone(),
+                    description: app.description.clone(),
+                    field,
+                    score,
+                })
+            })
+        })
+        .collect();
+
+    hits.sort_by(|a, b| {
+        b.score
+            .cmp(&a.score)
+            .then_with(|| a.name.cmp(&b.name))
+            .then_with(|| b.version.cmp(&a.version))
+    });
+    hits
+}
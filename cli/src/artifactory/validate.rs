@@ -0,0 +1,259 @@
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::{
+    App, Artifactory, Package,
+    artifactory::include::{self, Location, Trust},
+    package::archive,
+    platform::Platform,
+    schema,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A single problem found in an artifactory.
+#[derive(Debug, Clone)]
+pub struct Issue {
+    pub severity: Severity,
+    /// Where the problem is, e.g. `eza 0.20.16 > package eza`
+    pub location: String,
+    pub message: String,
+}
+
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+    pub issues: Vec<Issue>,
+}
+
+impl ValidationReport {
+    pub fn errors(&self) -> usize {
+        self.issues.iter().filter(|issue| issue.severity == Severity::Error).count()
+    }
+
+    pub fn warnings(&self) -> usize {
+        self.issues.iter().filter(|issue| issue.severity == Severity::Warning).count()
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.errors() > 0
+    }
+
+    fn error(&mut self, location: &str, message: String) {
+        self.issues.push(Issue { severity: Severity::Error, location: location.to_string(), message });
+    }
+
+    fn warning(&mut self, location: &str, message: String) {
+        self.issues.push(Issue { severity: Severity::Warning, location: location.to_string(), message });
+    }
+}
+
+/// Checks an artifactory file, the files it includes and the local packages
+/// they reference.
+///
+/// Files that cannot be read or parsed, including unknown handler versions,
+/// are reported as a single error rather than failing. Signatures are not
+/// checked.
+pub async fn validate_file(path: &Path) -> Result<ValidationReport> {
+    let mut report = ValidationReport::default();
+    let location = path.display().to_string();
+
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read artifactory file {}: {}", path.display(), e))?;
+    let artifactory = match schema::parse_artifactory_as(&content, schema::Format::for_file(path, &content)) {
+        Ok(artifactory) => artifactory,
+        Err(e) => {
+            report.error(&location, e.to_string());
+            return Ok(report);
+        }
+    };
+
+    let composition = match include::compose(artifactory, &Location::Path(path.to_path_buf()), Trust::Unchecked).await {
+        Ok(composition) => composition,
+        Err(e) => {
+            report.error(&location, e.to_string());
+            return Ok(report);
+        }
+    };
+    for collision in &composition.collisions {
+        report.error(&format!("{} {}", collision.app, collision.version), format!(
+            "Defined by both {} and {}, only the former is used",
+            collision.kept, collision.ignored
+        ));
+    }
+
+    // Included package sources are already resolved, so only the root's
+    // are relative to this directory
+    let base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    validate(&composition.artifactory, &base_dir, &mut report);
+    Ok(report)
+}
+
+/// Checks an already parsed artifactory, resolving relative package sources
+/// against `base_dir`.
+pub fn validate(artifactory: &Artifactory, base_dir: &Path, report: &mut ValidationReport) {
+    let mut seen = HashSet::new();
+    for app in &artifactory.apps {
+        let location = format!("{} {}", app.name, app.version);
+        if !seen.insert((app.name.clone(), app.version.clone())) {
+            report.error(&location, "Duplicate app and version".to_string());
+        }
+
+        check_license(&app.license, &location, report);
+        if app.packages.is_empty() {
+            report.error(&location, "App has no packages".to_string());
+        }
+
+        for package in &app.packages {
+            let package_location = format!("{} > package {}", location, package.name);
+            validate_package(package, base_dir, &package_location, report);
+        }
+
+        check_commands(app, base_dir, &location, report);
+        check_metadata(app, &location, report);
+    }
+}
+
+// Checks a package and its dependencies
+fn validate_package(package: &Package, base_dir: &Path, location: &str, report: &mut ValidationReport) {
+    check_license(&package.license, location, report);
+
+    if package.variants.is_empty() {
+        match &package.source {
+            Some(source) => check_source(source, &package.sha256, base_dir, location, report),
+            None => report.warning(location, "Package has no source and no variants, nothing will be downloaded".to_string()),
+        }
+    } else {
+        if package.source.is_some() {
+            report.warning(location, "Package has both a source and variants, the source is ignored".to_string());
+        }
+
+        let mut targets = HashSet::new();
+        for variant in &package.variants {
+            let variant_location = format!("{} > variant {}", location, variant.target);
+            if let Err(e) = Platform::parse(&variant.target) {
+                report.error(&variant_location, e.to_string());
+            }
+            if !targets.insert(variant.target.as_str()) {
+                report.error(&variant_location, "Duplicate variant target".to_string());
+            }
+            check_source(&variant.source, &variant.sha256, base_dir, &variant_location, report);
+        }
+    }
+
+    for dependency in &package.dependencies {
+        let dependency_location = format!("{} > dependency {}", location, dependency.name);
+        validate_package(dependency, base_dir, &dependency_location, report);
+    }
+}
+
+fn check_source(source: &str, sha256: &str, base_dir: &Path, location: &str, report: &mut ValidationReport) {
+    if sha256.is_empty() {
+        report.error(location, "Missing sha256".to_string());
+    } else if sha256.len() != 64 || !sha256.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f')) {
+        report.error(location, format!("Malformed sha256 '{}', expected 64 lowercase hex digits", sha256));
+    }
+
+    if let Err(e) = archive::ArchiveFormat::from_source(source) {
+        report.error(location, e.to_string());
+        return;
+    }
+
+    // Remote sources are not downloaded during validation
+    if source.starts_with("http://") || source.starts_with("https://") {
+        return;
+    }
+
+    let path = base_dir.join(source);
+    let content = match std::fs::read(&path) {
+        Ok(content) => content,
+        Err(e) => {
+            report.error(location, format!("Source {} cannot be read: {}", path.display(), e));
+            return;
+        }
+    };
+
+    let hash = format!("{:x}", Sha256::digest(&content));
+    if !sha256.is_empty() && hash != sha256 {
+        report.error(location, format!("sha256 mismatch for {}: expected {}, got {}", source, sha256, hash));
+    }
+
+    if let Err(e) = archive::list_entries(&path, source) {
+        report.error(location, format!("Source {} is not a valid archive: {}", source, e));
+    }
+}
+
+// Commands are linked from the app's first package, so every build of that
+// package must ship them. Install falls back to searching by file name, so
+// a match elsewhere in the archive is only a warning. Remote and unreadable
+// archives are skipped, the latter being reported already.
+fn check_commands(app: &App, base_dir: &Path, location: &str, report: &mut ValidationReport) {
+    let Some(package) = app.packages.first() else {
+        return;
+    };
+
+    let builds: Vec<(String, &str)> = if package.variants.is_empty() {
+        package.source.iter().map(|source| (location.to_string(), source.as_str())).collect()
+    } else {
+        package.variants
+            .iter()
+            .map(|variant| (format!("{} > variant {}", location, variant.target), variant.source.as_str()))
+            .collect()
+    };
+
+    for (build_location, source) in builds {
+        if source.starts_with("http://") || source.starts_with("https://") {
+            continue;
+        }
+        let Ok(entries) = archive::list_entries(&base_dir.join(source), source) else {
+            continue;
+        };
+
+        for cmd in &app.commands {
+            let command_location = format!("{} > command {}", build_location, cmd.command);
+            if entries.contains(&cmd.path) {
+                continue;
+            }
+
+            match entries.iter().find(|entry| entry.file_name().is_some() && entry.file_name() == cmd.path.file_name()) {
+                Some(found) => report.warning(&command_location, format!(
+                    "Path {} is not in the archive, but {} is; diem will fall back to it",
+                    cmd.path.display(),
+                    found.display()
+                )),
+                None => report.error(&command_location, format!("Path {} is not in the package archive", cmd.path.display())),
+            }
+        }
+    }
+}
+
+// Checks the descriptive fields shown by `diem info` and used by search
+fn check_metadata(app: &App, location: &str, report: &mut ValidationReport) {
+    for (field, url) in [("Homepage", &app.homepage), ("Repository", &app.repository)] {
+        if let Some(url) = url {
+            if !url.starts_with("https://") && !url.starts_with("http://") {
+                report.warning(location, format!("{} '{}' is not an http(s) URL", field, url));
+            }
+        }
+    }
+
+    for (field, values) in [("Tag", &app.tags), ("Category", &app.categories)] {
+        for value in values {
+            if value.trim().is_empty() || value.contains(char::is_whitespace) {
+                report.warning(location, format!("{} '{}' should be a single word", field, value));
+            }
+        }
+    }
+}
+
+fn check_license(license: &str, location: &str, report: &mut ValidationReport) {
+    if let Err(e) = spdx::Expression::parse(license) {
+        report.error(location, format!("License '{}' is not a valid SPDX expression: {}", license, e.reason));
+    }
+}
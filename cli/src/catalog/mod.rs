@@ -0,0 +1,268 @@
+//! Every app diem can install, from providers and subscribed artifactories
+//! alike.
+//!
+//! Providers (`diem providers add`) and subscriptions (`diem artifactory
+//! subscribe`) are two ways of pointing at an artifactory file, and every
+//! file dropped into the shared artifactory directory is a third. The
+//! catalog turns each subscription into a provider so that install, search,
+//! list and info all see the same apps, verified and composed the same way.
+
+use anyhow::Result;
+
+use std::sync::Mutex;
+
+use crate::{
+    App, Artifactory, Config, Provider,
+    app::info::AppOffer,
+    artifactory::{
+        index::IndexCache,
+        search::{self, SearchHit, SearchQuery},
+    },
+    utils::ui,
+};
+
+/// How a source was added to the config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceKind {
+    Provider,
+    Subscription,
+    /// An artifactory of the shared artifactory directory
+    Shared,
+}
+
+/// A provider or subscription the catalog reads apps from.
+#[derive(Debug, Clone)]
+pub struct CatalogSource {
+    pub kind: SourceKind,
+    /// Name of the provider or subscription
+    pub name: String,
+    // `None` for sources diem cannot read yet, like remote subscriptions
+    provider: Option<Provider>,
+    // Whether the key is pinned by the config, rather than declared by
+    // the artifactory itself
+    pinned: bool,
+}
+
+impl std::fmt::Display for CatalogSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            SourceKind::Provider => write!(f, "Provider: {}", self.name),
+            SourceKind::Subscription => write!(f, "Artifactory: {}", self.name),
+            SourceKind::Shared => write!(f, "Shared artifactory: {}", self.name),
+        }
+    }
+}
+
+impl CatalogSource {
+    /// The provider packages of this source are downloaded through.
+    pub fn provider(&self) -> Option<&Provider> {
+        self.provider.as_ref()
+    }
+}
+
+/// A source whose artifactory is now signed with another key than the
+/// pinned one, through a signed key rotation, or a shared artifactory
+/// signed with a key that is not pinned yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyRotation {
+    pub kind: SourceKind,
+    pub name: String,
+    pub public_key: String,
+    /// The key was not pinned before, rather than rotated from
+    pub first_seen: bool,
+}
+
+/// The apps of every configured provider and subscription.
+pub struct Catalog {
+    sources: Vec<CatalogSource>,
+    cache: Option<IndexCache>,
+    // Rotations seen while loading, to be pinned
+    rotations: Mutex<Vec<KeyRotation>>,
+}
+
+impl Catalog {
+    /// Providers come first, then subscriptions, each in config order, then
+    /// shared artifactories by name.
+    pub fn new_from_config(config: &Config) -> Self {
+        let mut sources: Vec<CatalogSource> = config
+            .providers
+            .iter()
+            .map(|provider| CatalogSource {
+                kind: SourceKind::Provider,
+                name: provider.name.clone(),
+                provider: Some(provider.clone()),
+                pinned: true,
+            })
+            .collect();
+
+        sources.extend(config.subscribed_artifactories.iter().map(|subscription| CatalogSource {
+            kind: SourceKind::Subscription,
+            name: subscription.name.clone(),
+            provider: Provider::for_subscription(subscription),
+            pinned: true,
+        }));
+
+        sources.extend(config.shared_subscriptions().iter().map(|subscription| CatalogSource {
+            kind: SourceKind::Shared,
+            name: subscription.name.clone(),
+            provider: Provider::for_subscription(subscription),
+            pinned: config.shared_artifactory_keys.contains_key(&subscription.name),
+        }));
+
+        Self { sources, cache: IndexCache::open_default(), rotations: Mutex::new(Vec::new()) }
+    }
+
+    /// Uses `cache` for compiled artifactory indexes, or none at all.
+    pub fn with_index_cache(mut self, cache: Option<IndexCache>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    pub fn sources(&self) -> &[CatalogSource] {
+        &self.sources
+    }
+
+    /// Loads the artifactory of every source, in order.
+    pub async fn load_all(&self) -> Vec<(&CatalogSource, Result<Artifactory>)> {
+        let mut loaded = Vec::with_capacity(self.sources.len());
+        for source in &self.sources {
+            loaded.push((source, self.load(source).await));
+        }
+        loaded
+    }
+
+    async fn load(&self, source: &CatalogSource) -> Result<Artifactory> {
+        let Some(provider) = &source.provider else {
+            anyhow::bail!("Remote artifactories are not supported yet");
+        };
+
+        let loaded = provider.fetch_composed_index(self.cache.as_ref()).await?;
+        // Shared artifactories are trusted on first use, with the key they
+        // were verified with
+        let rotation = match loaded.rotated_key {
+            Some(public_key) => Some((public_key, !source.pinned)),
+            None if !source.pinned => provider.public_key.clone().map(|public_key| (public_key, true)),
+            None => None,
+        };
+        if let Some((public_key, first_seen)) = rotation {
+            let rotation = KeyRotation { kind: source.kind, name: source.name.clone(), public_key, first_seen };
+            let mut rotations = self.rotations.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if !rotations.contains(&rotation) {
+                rotations.push(rotation);
+            }
+        }
+        Ok(loaded.artifactory)
+    }
+
+    /// Key rotations followed while loading sources so far, for the config
+    /// to pin the new keys.
+    pub fn key_rotations(&self) -> Vec<KeyRotation> {
+        self.rotations.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+
+    // Loads every source, warning about and skipping those that fail
+    async fn load_available(&self) -> Vec<(&CatalogSource, Artifactory)> {
+        let mut available = Vec::new();
+        for (source, result) in self.load_all().await {
+            match result {
+                Ok(artifactory) => available.push((source, artifactory)),
+                Err(e) => println!("{}", ui::warning(&format!("Skipping {}: {}", source, e))),
+            }
+        }
+        available
+    }
+
+    /// Searches every source, best matches first.
+    pub async fn search(&self, query: &SearchQuery) -> Vec<SearchHit> {
+        let artifactories: Vec<Artifactory> =
+            self.load_available().await.into_iter().map(|(_, artifactory)| artifactory).collect();
+        search::search(&artifactories, query)
+    }
+
+    /// Lists every version of an app offered by any source, newest first.
+    /// Equal versions keep the order of their sources.
+    pub async fn find_app_offers(&self, app_name: &str) -> Vec<AppOffer> {
+        let mut offers = Vec::new();
+        for (source, artifactory) in self.load_available().await {
+            let Some(provider) = &source.provider else {
+                continue;
+            };
+            offers.extend(artifactory.apps.into_iter().filter(|app| app.name == app_name).map(|app| AppOffer {
+                app,
+                source: source.to_string(),
+                provider: provider.clone(),
+            }));
+        }
+
+        offers.sort_by(|a, b| b.app.version.cmp(&a.app.version));
+        offers
+    }
+
+    /// Finds the provider of a package version, which may be a dependency
+    /// of any app. Used to download installed packages again.
+    pub async fn find_package(&self, name: &str, version: &semver::Version) -> Option<Provider> {
+        for (source, artifactory) in self.load_available().await {
+            let Some(provider) = &source.provider else {
+                continue;
+            };
+            if artifactory.apps.iter().any(|app| app.packages.iter().any(|package| offers(package, name, version))) {
+                return Some(provider.clone());
+            }
+        }
+        None
+    }
+
+    /// Finds the app to install for an `app[@version]` spec. When several
+    /// versions are offered and none was asked for, the user picks one.
+    pub async fn find_app(&self, app_spec: &str) -> Result<(App, Provider)> {
+        let (app_name, version) = crate::app::parse_spec(app_spec)?;
+        let offers = self.find_app_offers(&app_name).await;
+        if offers.is_empty() {
+            anyhow::bail!("App {} not found in any provider or artifactory", app_spec);
+        }
+
+        let offer = match &version {
+            Some(_) => crate::app::info::select(&offers, version.as_ref())
+                .ok_or_else(|| anyhow::anyhow!("App {} not found in any provider or artifactory", app_spec))?,
+            None if offers.len() == 1 => &offers[0],
+            None => choose(&app_name, &offers)?,
+        };
+
+        Ok((offer.app.clone(), offer.provider.clone()))
+    }
+
+    /// Finds the newest version of an app, without asking, for updates
+    /// that run unattended. Equal versions go to the first source.
+    pub async fn find_newest_app(&self, app_name: &str) -> Result<(App, Provider)> {
+        let offers = self.find_app_offers(app_name).await;
+        let offer = offers
+            .first()
+            .ok_or_else(|| anyhow::anyhow!("App {} not found in any provider or artifactory", app_name))?;
+        Ok((offer.app.clone(), offer.provider.clone()))
+    }
+}
+
+// Whether `package` or one of its dependencies is that version
+fn offers(package: &crate::Package, name: &str, version: &semver::Version) -> bool {
+    (package.name == name && package.version == *version)
+        || package.dependencies.iter().any(|dependency| offers(dependency, name, version))
+}
+
+// Asks the user which of several offers to install
+fn choose<'a>(app_name: &str, offers: &'a [AppOffer]) -> Result<&'a AppOffer> {
+    println!("Multiple versions of '{}' found:", app_name);
+    for (i, offer) in offers.iter().enumerate() {
+        println!("  {}. {} v{} (from {})", i + 1, offer.app.name, offer.app.version, offer.source);
+    }
+
+    println!("Please choose which one to install (1-{}):", offers.len());
+    let mut choice = String::new();
+    std::io::stdin().read_line(&mut choice)?;
+
+    let choice: usize = choice.trim().parse().map_err(|_| anyhow::anyhow!("Invalid choice"))?;
+    if choice < 1 || choice > offers.len() {
+        anyhow::bail!("Invalid choice: {}", choice);
+    }
+
+    Ok(&offers[choice - 1])
+}
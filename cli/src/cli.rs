@@ -3,6 +3,9 @@ use std::path::PathBuf;
 use clap::builder::styling::{Color, Style};
 use clap::{Parser, Subcommand};
 use clap_complete::Shell;
+use semver::Version;
+
+use crate::{package::placement::Volume, schema::Format};
 
 /// A package manager
 #[derive(Debug, Parser)]
@@ -66,21 +69,107 @@ pub enum Commands {
         command: ArtifactoryCommands,
     },
     
-    /// Search for packages in subscribed artifactories
+    /// Search for packages in providers and subscribed artifactories
     #[command(aliases = ["s", "find"])]
-    #[command(long_about = "Search for packages in all subscribed artifactories")]
+    #[command(long_about = "Search for packages in all providers and subscribed artifactories. Names, commands, tags \
+        and descriptions are matched fuzzily and case-insensitively by default, and results are ranked \
+        with name matches first. Results can be filtered by tag and category")]
     Search {
-        /// The query to search for
-        query: String,
+        /// The query to search for, optional when filtering by tag or category
+        #[arg(required_unless_present_any = ["tags", "categories"])]
+        query: Option<String>,
+        
+        /// Match the query literally instead of fuzzily
+        #[arg(short, long, conflicts_with = "regex")]
+        exact: bool,
+        
+        /// Treat the query as a regular expression
+        #[arg(short, long)]
+        regex: bool,
+        
+        /// Respect case when matching
+        #[arg(short = 's', long)]
+        case_sensitive: bool,
+        
+        /// Only show apps with this tag, can be repeated
+        #[arg(short, long = "tag", value_name = "TAG")]
+        tags: Vec<String>,
+        
+        /// Only show apps in this category, can be repeated
+        #[arg(short, long = "category", value_name = "CATEGORY")]
+        categories: Vec<String>,
+    },
+    
+    /// Show details about an app
+    #[command(aliases = ["show"])]
+    #[command(long_about = "Show an app's description and links, every version offered by providers \
+        and subscribed artifactories, its packages, dependencies, licenses and download sizes, its \
+        commands, and whether and where it is installed")]
+    Info {
+        /// The app to show, as name or name@version
+        app: String,
     },
     
-    /// List all apps available from subscribed artifactories
+    /// List all apps available from providers and subscribed artifactories
     #[command(aliases = ["ls"])]
-    #[command(long_about = "List all available apps from subscribed artifactories")]
+    #[command(long_about = "List all available apps from providers and subscribed artifactories")]
     List,
     
+    /// Remove unused package versions, leftovers and old cache entries
+    #[command(aliases = ["clean", "prune"])]
+    #[command(long_about = "Remove package versions no installed app uses, files left by interrupted \
+        installs and cache entries older than a threshold, and report the space reclaimed. Versions \
+        used by installed apps or linked from commands are always kept")]
+    Gc {
+        /// Only show what would be removed
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+
+        /// Age in days after which temporary files and cache entries are removed
+        #[arg(long, value_name = "DAYS", default_value_t = 7)]
+        older_than: u64,
+    },
+    
+    /// Show the disk space used by apps, packages and diem's directories
+    #[command(aliases = ["usage"])]
+    #[command(long_about = "Show the disk space used by each installed app and package, by the install, \
+        sgoinfre and goinfre directories and the caches, what is duplicated between sgoinfre and goinfre \
+        and what `diem gc` could free")]
+    Du,
+    
+    /// Check installed packages and commands for missing or changed files
+    #[command(aliases = ["check"])]
+    #[command(long_about = "Compare the files of installed packages with those recorded when they were \
+        installed, reporting missing, modified and extra files, and check that commands link into \
+        their packages")]
+    Verify {
+        /// Only check this installed app and its dependencies
+        app: Option<String>,
+    },
+    
+    /// Restore installed packages and commands to how they were installed
+    #[command(aliases = ["fix"])]
+    #[command(long_about = "Put back missing and modified files of installed packages from the package \
+        store, downloading an archive again only when the store lost its files, remove extra files and \
+        link commands again")]
+    Repair {
+        /// Only repair this installed app and its dependencies
+        app: Option<String>,
+    },
+    
+    /// Publish an installed app into the shared install tree
+    #[command(aliases = ["share"])]
+    #[command(long_about = "Copy the packages of an installed app into the shared install tree, readable \
+        by your group, so other users link them instead of installing their own copy. Versions \
+        already published are left as they are")]
+    Publish {
+        /// The installed app to publish
+        app: String,
+    },
+    
     /// Sync packages between sgoinfre and goinfre directories
-    #[command(long_about = "Sync packages from sgoinfre to goinfre directory")]
+    #[command(long_about = "Mirror the packages stored in sgoinfre to goinfre, point installed packages at \
+        the mirror, and install again the packages whose files are gone, as after goinfre was wiped")]
     Sync,
     
     /// Configure directories used by diem
@@ -100,12 +189,47 @@ pub enum Commands {
     },
 }
 
+impl Commands {
+    /// Whether the command changes installed packages, goinfre or the
+    /// configuration, and so must not run alongside another such command.
+    pub fn is_mutating(&self) -> bool {
+        match self {
+            Commands::Install { .. }
+            | Commands::Remove { .. }
+            | Commands::Update { .. }
+            | Commands::Repair { .. }
+            | Commands::Publish { .. }
+            | Commands::Sync => true,
+            Commands::Gc { dry_run, .. } => !dry_run,
+            Commands::Providers { command } => !matches!(command, ProvidersCommands::List),
+            Commands::Artifactory { command } => {
+                matches!(command, ArtifactoryCommands::Subscribe { .. } | ArtifactoryCommands::Unsubscribe { .. })
+            }
+            Commands::Config { command } => !matches!(command, ConfigCommands::Show),
+            Commands::Search { .. }
+            | Commands::Info { .. }
+            | Commands::List
+            | Commands::Du
+            | Commands::Verify { .. }
+            | Commands::Completions { .. } => false,
+        }
+    }
+}
+
 #[derive(Debug, Subcommand)]
 pub enum ProvidersCommands {
     /// Add a provider
     Add {
         /// The provider to add
         provider: String,
+
+        /// Minisign public key the provider's artifactory must be signed with
+        #[arg(short = 'k', long)]
+        public_key: Option<String>,
+
+        /// Trust the artifactory even if it is not signed
+        #[arg(long)]
+        allow_unsigned: bool,
     },
 
     /// Remove a provider
@@ -131,6 +255,14 @@ pub enum ArtifactoryCommands {
         /// Whether to automatically update from this artifactory
         #[arg(short, long)]
         auto_update: bool,
+
+        /// Minisign public key the artifactory must be signed with
+        #[arg(short = 'k', long)]
+        public_key: Option<String>,
+
+        /// Trust the artifactory even if it is not signed
+        #[arg(long)]
+        allow_unsigned: bool,
     },
     
     /// Unsubscribe from an artifactory
@@ -152,7 +284,7 @@ pub enum ArtifactoryCommands {
         path: PathBuf,
         
         /// Whether this artifactory should be publicly accessible
-        #[arg(short, long)]
+        #[arg(long)]
         public: bool,
         
         /// Description of the artifactory
@@ -162,6 +294,14 @@ pub enum ArtifactoryCommands {
         /// Maintainer of the artifactory
         #[arg(short, long)]
         maintainer: Option<String>,
+
+        /// Minisign public key the artifactory will be signed with
+        #[arg(short = 'k', long)]
+        public_key: Option<String>,
+        
+        /// File format, defaults to the one matching the path's extension, or TOML
+        #[arg(short, long, value_enum)]
+        format: Option<Format>,
     },
     
     /// Add an app to an artifactory
@@ -172,6 +312,93 @@ pub enum ArtifactoryCommands {
         /// Path to the app definition file
         app: PathBuf,
     },
+
+    /// Build a package from a directory and register it in an artifactory
+    #[command(long_about = "Pack a directory into a reproducible tar archive, copy it into the artifactory's \
+        packages directory and insert or update the matching app and package entries")]
+    Pack {
+        /// Directory containing the files to package
+        dir: PathBuf,
+        
+        /// Name of the app and package
+        #[arg(short, long)]
+        name: String,
+        
+        /// Version of the app and package
+        #[arg(short, long)]
+        version: Version,
+        
+        /// Target triple of the build, e.g. x86_64-unknown-linux-gnu
+        #[arg(short, long)]
+        target: Option<String>,
+        
+        /// Path to the artifactory file
+        #[arg(short, long, default_value = "artifactory.toml")]
+        artifactory: PathBuf,
+        
+        /// SPDX license expression, required for new apps
+        #[arg(short, long)]
+        license: Option<String>,
+        
+        /// Description of the app
+        #[arg(short, long)]
+        description: Option<String>,
+        
+        /// Command to expose, as name=path inside the package
+        #[arg(short = 'c', long = "command", value_name = "NAME=PATH")]
+        commands: Vec<String>,
+    },
+
+    /// Import an app from the assets of an upstream GitHub release
+    #[command(long_about = "Read a GitHub release, match its assets to target triples from their file names, \
+        download and hash them, and insert or update the app with one package variant per target. \
+        Variants point at the upstream download URLs.")]
+    ImportRelease {
+        /// GitHub repository, as owner/repo
+        repo: String,
+        
+        /// Release tag, defaults to the latest release
+        #[arg(short, long)]
+        tag: Option<String>,
+        
+        /// Read the release from a JSON file saved from the GitHub API instead of fetching it
+        #[arg(short, long, value_name = "FILE")]
+        file: Option<PathBuf>,
+        
+        /// Name of the app and package, defaults to the repository name
+        #[arg(short, long)]
+        name: Option<String>,
+        
+        /// Version of the app, defaults to the version in the release tag
+        #[arg(short, long)]
+        version: Option<Version>,
+        
+        /// Path to the artifactory file
+        #[arg(short, long, default_value = "artifactory.toml")]
+        artifactory: PathBuf,
+        
+        /// SPDX license expression, required for new apps
+        #[arg(short, long)]
+        license: Option<String>,
+        
+        /// Description of the app
+        #[arg(short, long)]
+        description: Option<String>,
+        
+        /// Command to expose, as name=path inside the package
+        #[arg(short = 'c', long = "command", value_name = "NAME=PATH")]
+        commands: Vec<String>,
+    },
+
+    /// Check an artifactory file and its packages for mistakes
+    #[command(aliases = ["lint", "check"])]
+    #[command(long_about = "Check an artifactory file for duplicate apps, bad checksums, missing package sources, \
+        command paths missing from archives, unknown handler versions and invalid SPDX licenses. \
+        Exits with a non-zero status if any error is found.")]
+    Validate {
+        /// Path to the artifactory file
+        path: PathBuf,
+    },
 }
 
 #[derive(Debug, Subcommand)]
@@ -197,6 +424,75 @@ pub enum ConfigCommands {
         path: PathBuf,
     },
     
+    /// Set the shared install tree packages are linked from
+    #[command(name = "set-shared-install")]
+    SetSharedInstall {
+        /// Path to the shared install tree, usually on sgoinfre
+        path: PathBuf,
+    },
+    
+    /// Stop linking packages from the shared install tree
+    #[command(name = "unset-shared-install")]
+    UnsetSharedInstall,
+    
+    /// Use unsigned artifactories of the shared artifactory directory
+    #[command(name = "allow-unsigned-shared")]
+    #[command(long_about = "Use unsigned artifactories of the shared artifactory directory. Anyone who \
+        can write to the directory can change them, so only allow them when you trust everyone who can. \
+        Artifactories whose key is pinned must stay signed with it")]
+    AllowUnsignedShared,
+    
+    /// Only use signed artifactories of the shared artifactory directory
+    #[command(name = "deny-unsigned-shared")]
+    DenyUnsignedShared,
+    
+    /// Set the directory downloaded archives are cached in
+    #[command(name = "set-download-cache")]
+    #[command(long_about = "Set the directory downloaded archives are cached in. Put it in sgoinfre, \
+        writable by your cohort, to share downloads: entries are checked against the sha256 the \
+        artifactory declares before they are used")]
+    SetDownloadCache {
+        /// Path to the download cache directory
+        path: PathBuf,
+    },
+    
+    /// Cache downloads in the default directory again, in sgoinfre if set
+    #[command(name = "unset-download-cache")]
+    UnsetDownloadCache,
+    
+    /// Store an app on a volume whatever its size
+    #[command(name = "set-placement")]
+    SetPlacement {
+        /// The app to place
+        app: String,
+
+        /// Volume to store the app in
+        volume: Volume,
+    },
+    
+    /// Let the size of an app decide where it is stored again
+    #[command(name = "unset-placement")]
+    UnsetPlacement {
+        /// The app to stop placing
+        app: String,
+    },
+    
+    /// Set how apps are spread across home, sgoinfre and goinfre
+    #[command(name = "set-placement-policy")]
+    SetPlacementPolicy {
+        /// Apps downloading at least this many MiB leave the home directory
+        #[arg(long, value_name = "MIB")]
+        threshold: Option<u64>,
+
+        /// Volume bulky apps are stored in
+        #[arg(long, value_name = "VOLUME")]
+        bulky: Option<Volume>,
+
+        /// MiB to keep free in the home directory
+        #[arg(long, value_name = "MIB")]
+        home_reserve: Option<u64>,
+    },
+    
     /// Show the current configuration
     Show,
 }
@@ -0,0 +1,83 @@
+//! Advisory lock serializing the diem processes of a user.
+//!
+//! Commands that change `install_dir`, goinfre or the configuration hold
+//! an exclusive `flock` on a file next to the configuration for as long as
+//! they run, so two of them never interleave. Read-only commands take no
+//! lock and never wait. The lock is released by the kernel when the
+//! process exits, even if it crashes, so it is never left stale.
+
+use fs4::fs_std::FileExt;
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read as _, Seek as _, Write as _};
+use std::path::{Path, PathBuf};
+
+/// Name of the lock file, in the configuration directory.
+pub const LOCK_FILE: &str = "diem.lock";
+
+/// An exclusive lock, held until dropped.
+#[derive(Debug)]
+pub struct Lock {
+    file: File,
+    path: PathBuf,
+}
+
+impl Lock {
+    /// Takes the lock at `path`, calling `on_wait` with the process id of
+    /// the holder, when known, before waiting for it to be released.
+    pub fn acquire(path: &Path, on_wait: impl FnOnce(Option<u32>)) -> io::Result<Self> {
+        let mut file = open(path)?;
+        if !file.try_lock_exclusive()? {
+            on_wait(holder(&mut file));
+            file.lock_exclusive()?;
+        }
+        Self::held(file, path)
+    }
+
+    /// Takes the lock at `path` if no other process holds it.
+    pub fn try_acquire(path: &Path) -> io::Result<Option<Self>> {
+        let file = open(path)?;
+        if !file.try_lock_exclusive()? {
+            return Ok(None);
+        }
+        Self::held(file, path).map(Some)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Records this process as the holder, for the message of waiting ones
+    fn held(mut file: File, path: &Path) -> io::Result<Self> {
+        file.set_len(0)?;
+        file.rewind()?;
+        write!(file, "{}", std::process::id())?;
+        Ok(Self { file, path: path.to_path_buf() })
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = self.file.set_len(0);
+        let _ = FileExt::unlock(&self.file);
+    }
+}
+
+/// The lock file of the current user.
+pub fn default_path() -> anyhow::Result<PathBuf> {
+    Ok(confy::get_configuration_file_path("diem", "config")?.with_file_name(LOCK_FILE))
+}
+
+fn open(path: &Path) -> io::Result<File> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)
+}
+
+fn holder(file: &mut File) -> Option<u32> {
+    let mut content = String::new();
+    file.rewind().ok()?;
+    file.read_to_string(&mut content).ok()?;
+    content.trim().parse().ok()
+}
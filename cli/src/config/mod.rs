@@ -1,21 +1,50 @@
 use directories::BaseDirs;
 use serde::{Deserialize, Serialize};
 
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 use std::fs;
 use std::io;
 
-use crate::{Package, Provider};
+use crate::{App, Package, Provider, catalog::{KeyRotation, SourceKind}, package::{cache::DownloadCache, placement::{PlacementPolicy, Volume}, shared::SharedTree}};
+
+pub mod lock;
+pub mod sync;
+
+use sync::SyncReport;
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Config {
     pub packages: Vec<Package>,
+    /// Commands of installed apps, linked into the bin directory
+    #[serde(default)]
+    pub commands: Vec<InstalledCommand>,
     pub providers: Vec<Provider>,
     pub install_dir: PathBuf,
     pub sgoinfre_dir: Option<PathBuf>,
     pub goinfre_dir: Option<PathBuf>,
     pub subscribed_artifactories: Vec<ArtifactorySubscription>,
     pub shared_artifactory_dir: Option<PathBuf>,
+    /// Shared artifactories not to subscribe to implicitly
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ignored_shared_artifactories: Vec<String>,
+    /// Keys shared artifactories were signed with when first seen, by name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub shared_artifactory_keys: BTreeMap<String, String>,
+    /// Use unsigned files of the shared artifactory directory too
+    #[serde(default)]
+    pub allow_unsigned_shared: bool,
+    /// Tree of packages published for other users, linked when present
+    #[serde(default)]
+    pub shared_install_dir: Option<PathBuf>,
+    /// Cache of downloaded archives, `downloads` in sgoinfre, or the user's
+    /// cache directory without one, unless set, e.g. to share downloads
+    /// with a cohort
+    #[serde(default)]
+    pub download_cache_dir: Option<PathBuf>,
+    /// Where installed packages are stored
+    #[serde(default)]
+    pub placement: PlacementPolicy,
     pub config_handler_version: u8,
 }
 
@@ -24,25 +53,58 @@ pub struct ArtifactorySubscription {
     pub name: String,
     pub source: ArtifactorySource,
     pub auto_update: bool,
+    /// Minisign public key the artifactory must be signed with
+    #[serde(default)]
+    pub public_key: Option<String>,
+    /// Accept the artifactory even if it is unsigned or no key is pinned
+    #[serde(default)]
+    pub allow_unsigned: bool,
+}
+
+/// A command of an installed app.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct InstalledCommand {
+    pub command: String,
+    /// Package the command comes from
+    pub package: String,
+    pub version: semver::Version,
+    /// Path of the command in the package
+    pub path: PathBuf,
+}
+
+impl InstalledCommand {
+    /// The file the command links to in `install_dir`.
+    pub fn target(&self, install_dir: &Path) -> PathBuf {
+        install_dir.join(&self.package).join(self.version.to_string()).join(&self.path)
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub enum ArtifactorySource {
     Local(PathBuf),
     Remote(String),
+    /// A file of the shared artifactory directory, subscribed implicitly
+    Shared(PathBuf),
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             packages: Vec::new(),
+            commands: Vec::new(),
             providers: Vec::new(),
             install_dir: default_install_dir(),
             sgoinfre_dir: default_sgoinfre_dir(),
             goinfre_dir: default_goinfre_dir(),
             subscribed_artifactories: Vec::new(),
             shared_artifactory_dir: None,
-            config_handler_version: 0,
+            ignored_shared_artifactories: Vec::new(),
+            shared_artifactory_keys: BTreeMap::new(),
+            allow_unsigned_shared: false,
+            shared_install_dir: None,
+            download_cache_dir: None,
+            placement: PlacementPolicy::default(),
+            config_handler_version: crate::schema::CONFIG_HANDLER_VERSION,
         }
     }
 }
@@ -111,6 +173,175 @@ fn default_goinfre_dir() -> Option<PathBuf> {
 }
 
 impl Config {
+    /// Loads the user configuration, upgrading it to the current format
+    /// and writing it back if it was older.
+    pub fn load() -> anyhow::Result<Self> {
+        let path = confy::get_configuration_file_path("diem", "config")?;
+        if !path.exists() {
+            return Ok(confy::load("diem", "config")?);
+        }
+
+        let content = fs::read_to_string(&path)?;
+        let mut document: toml::Table = toml::from_str(&content)
+            .map_err(|e| anyhow::anyhow!("Failed to parse config file {}: {}", path.display(), e))?;
+        let upgraded = crate::schema::upgrade_config(&mut document)?;
+        let config: Config = toml::Value::Table(document).try_into()?;
+
+        if upgraded {
+            confy::store("diem", "config", &config)?;
+        }
+
+        Ok(config)
+    }
+
+    /// Records the packages and commands of an installed app, replacing
+    /// other versions of the same packages and commands of the same name.
+    pub fn record_installed(&mut self, app: &App) {
+        let packages = &app.packages;
+        self.packages.retain(|installed| packages.iter().all(|package| package.name != installed.name));
+        self.packages.extend(packages.iter().cloned());
+
+        // Commands come from the main package, as `AppManager` links them
+        let Some(package) = packages.first() else {
+            return;
+        };
+        self.commands.retain(|installed| app.commands.iter().all(|command| command.command != installed.command));
+        self.commands.extend(app.commands.iter().map(|command| InstalledCommand {
+            command: command.command.clone(),
+            package: package.name.clone(),
+            version: package.version.clone(),
+            path: command.path.clone(),
+        }));
+    }
+
+    /// Forgets a removed package and the commands it provided.
+    pub fn forget_installed(&mut self, package: &str) {
+        self.packages.retain(|installed| installed.name != package);
+        self.commands.retain(|installed| installed.package != package);
+    }
+
+    /// Directory artifactories are shared in, `shared_artifactory` in
+    /// sgoinfre unless set explicitly.
+    pub fn shared_artifactory_path(&self) -> Option<PathBuf> {
+        self.shared_artifactory_dir
+            .clone()
+            .or_else(|| self.sgoinfre_dir.as_ref().map(|sgoinfre| sgoinfre.join("shared_artifactory")))
+    }
+
+    /// Implicit subscriptions to every artifactory file in the shared
+    /// artifactory directory, named after the file, sorted by name.
+    ///
+    /// Files already subscribed to explicitly, or ignored, are left out.
+    /// Signed files must be signed by the key they were first seen signed
+    /// with, pinned in `shared_artifactory_keys`, or by the key they declare
+    /// until then. Unsigned files are only trusted, with a warning, when
+    /// `allow_unsigned_shared` is set and no key is pinned for them.
+    pub fn shared_subscriptions(&self) -> Vec<ArtifactorySubscription> {
+        let Some(entries) = self.shared_artifactory_path().and_then(|dir| fs::read_dir(dir).ok()) else {
+            return Vec::new();
+        };
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file() && crate::schema::Format::from_path(path).is_some())
+            .filter(|path| !path.file_name().unwrap_or_default().to_string_lossy().starts_with('.'))
+            .collect();
+        paths.sort();
+
+        let explicit: Vec<PathBuf> = self
+            .subscribed_artifactories
+            .iter()
+            .filter_map(|subscription| match &subscription.source {
+                ArtifactorySource::Local(path) => Some(fs::canonicalize(path).unwrap_or_else(|_| path.clone())),
+                _ => None,
+            })
+            .collect();
+
+        let mut subscriptions: Vec<ArtifactorySubscription> = Vec::new();
+        for path in paths {
+            let name = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+            let taken = subscriptions.iter().any(|subscription| subscription.name == name)
+                || self.subscribed_artifactories.iter().any(|subscription| subscription.name == name);
+            if taken
+                || self.ignored_shared_artifactories.contains(&name)
+                || explicit.contains(&fs::canonicalize(&path).unwrap_or_else(|_| path.clone()))
+            {
+                continue;
+            }
+
+            let signed = crate::signature::signature_path(&path).exists();
+            let pinned = self.shared_artifactory_keys.get(&name).cloned();
+            let public_key = match pinned {
+                Some(key) => Some(key),
+                None if signed => {
+                    fs::read_to_string(&path).ok().and_then(|content| crate::signature::declared_public_key(&content))
+                }
+                None => None,
+            };
+            subscriptions.push(ArtifactorySubscription {
+                allow_unsigned: !signed && public_key.is_none() && self.allow_unsigned_shared,
+                name,
+                source: ArtifactorySource::Shared(path),
+                auto_update: true,
+                public_key,
+            });
+        }
+        subscriptions
+    }
+
+    /// Explicit subscriptions followed by the implicit shared ones.
+    pub fn all_subscriptions(&self) -> Vec<ArtifactorySubscription> {
+        let mut subscriptions = self.subscribed_artifactories.clone();
+        subscriptions.extend(self.shared_subscriptions());
+        subscriptions
+    }
+
+    /// Pins the keys sources rotated to in place of their old ones, and
+    /// those shared artifactories were first seen signed with. Returns the
+    /// rotations that applied to a source of the config.
+    pub fn pin_rotated_keys(&mut self, rotations: &[KeyRotation]) -> Vec<KeyRotation> {
+        let mut pinned = Vec::new();
+        for rotation in rotations {
+            let public_key = match rotation.kind {
+                SourceKind::Provider => self
+                    .providers
+                    .iter_mut()
+                    .find(|provider| provider.name == rotation.name)
+                    .map(|provider| &mut provider.public_key),
+                SourceKind::Subscription => self
+                    .subscribed_artifactories
+                    .iter_mut()
+                    .find(|subscription| subscription.name == rotation.name)
+                    .map(|subscription| &mut subscription.public_key),
+                SourceKind::Shared => {
+                    self.shared_artifactory_keys.insert(rotation.name.clone(), rotation.public_key.clone());
+                    pinned.push(rotation.clone());
+                    continue;
+                }
+            };
+            if let Some(public_key) = public_key {
+                *public_key = Some(rotation.public_key.clone());
+                pinned.push(rotation.clone());
+            }
+        }
+        pinned
+    }
+
+    /// The shared install tree, when one is configured.
+    pub fn shared_tree(&self) -> Option<SharedTree> {
+        self.shared_install_dir.clone().map(SharedTree::new)
+    }
+
+    /// The cache of downloaded archives, see `download_cache_dir`. It lives
+    /// in sgoinfre by default, where it outlasts a wiped goinfre and does
+    /// not take space from the home directory.
+    pub fn download_cache(&self) -> Option<DownloadCache> {
+        match (&self.download_cache_dir, &self.sgoinfre_dir) {
+            (Some(dir), _) => Some(DownloadCache::new(dir.clone())),
+            (None, Some(sgoinfre)) => Some(DownloadCache::new(sgoinfre.join("downloads"))),
+            (None, None) => DownloadCache::open_default(),
+        }
+    }
+
     pub fn ensure_dirs_exist(&self) -> std::io::Result<()> {
         std::fs::create_dir_all(&self.install_dir)?;
         
@@ -135,75 +366,74 @@ impl Config {
         Ok(())
     }
     
-    pub fn sync_goinfre_from_sgoinfre(&self) -> std::io::Result<()> {
-        let sgoinfre = match &self.sgoinfre_dir {
-            Some(dir) => dir,
-            None => return Ok(()),
-        };
-        
-        let goinfre = match &self.goinfre_dir {
-            Some(dir) => dir,
-            None => return Ok(()),
+    /// Whether goinfre should be filled from sgoinfre before running a
+    /// command, because it was never synced on this machine.
+    pub fn goinfre_is_fresh(&self) -> bool {
+        match (&self.sgoinfre_dir, &self.goinfre_dir) {
+            (Some(sgoinfre), Some(goinfre)) => sgoinfre.is_dir() && sync::is_fresh(goinfre),
+            _ => false,
+        }
+    }
+
+    /// Mirrors the packages stored in sgoinfre in goinfre, points installed
+    /// packages at the mirror and links the commands of installed apps
+    /// again. `None` when either directory is not configured.
+    pub fn sync_goinfre_from_sgoinfre(&self) -> anyhow::Result<Option<SyncReport>> {
+        let (Some(packages_dir), Some(goinfre)) = (Volume::Sgoinfre.packages_dir(self), &self.goinfre_dir) else {
+            return Ok(None);
         };
-        
-        // Ensure directories exist
-        std::fs::create_dir_all(goinfre)?;
-        
-        // Copy all packages from sgoinfre to goinfre
-        copy_dir_contents(sgoinfre, goinfre)?;
-        
-        println!("Synchronized packages from sgoinfre to goinfre");
-        
-        // Create symlinks to binaries in PATH if needed
-        self.ensure_binaries_symlinked()?;
-        
-        Ok(())
+
+        std::fs::create_dir_all(&packages_dir)?;
+        let mut report = sync::sync(&packages_dir, goinfre)?;
+        report.relinked = sync::relink(&self.install_dir, &packages_dir, &sync::mirror_dir(goinfre))?;
+
+        let bin_dir = BaseDirs::new()
+            .and_then(|base_dirs| base_dirs.executable_dir().map(Path::to_path_buf))
+            .ok_or_else(|| anyhow::anyhow!("Could not determine executable directory"))?;
+        self.ensure_binaries_symlinked(&bin_dir)?;
+
+        Ok(Some(report))
     }
-    
-    // Ensures that binaries in install_dir are properly symlinked to user's PATH
-    fn ensure_binaries_symlinked(&self) -> std::io::Result<()> {
-        let base_dirs = BaseDirs::new()
-            .expect("Could not determine base directories");
-            
-        let bin_dir = base_dirs
-            .executable_dir()
-            .expect("Could not determine executable directory");
-            
-        // Make sure bin directory exists
+
+    /// Links the recorded commands of installed apps into `bin_dir` again
+    /// where they are missing or point elsewhere, returning how many were
+    /// linked. Commands whose package is gone are left alone, and so are
+    /// files in `bin_dir` that are not links, which were put there by the
+    /// user rather than diem.
+    pub fn ensure_binaries_symlinked(&self, bin_dir: &Path) -> io::Result<usize> {
         std::fs::create_dir_all(bin_dir)?;
-        
-        // For each installed package that has commands, ensure they're symlinked
-        // This would be implemented with a call to iterate over all installed packages
-        // and recreate their command symlinks
-        
-        Ok(())
-    }
-}
 
-// Helper function to copy directory contents recursively
-fn copy_dir_contents(src: &Path, dst: &Path) -> io::Result<()> {
-    if !src.is_dir() {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidInput,
-            "Source is not a directory",
-        ));
-    }
+        let mut linked = 0;
+        for command in &self.commands {
+            let target = command.target(&self.install_dir);
+            let link = bin_dir.join(&command.command);
+            if !target.exists() || fs::read_link(&link).is_ok_and(|existing| existing == target) {
+                continue;
+            }
 
-    if !dst.exists() {
-        fs::create_dir_all(dst)?;
-    }
+            match fs::symlink_metadata(&link) {
+                Ok(metadata) if metadata.is_symlink() => fs::remove_file(&link)?,
+                Ok(_) => {
+                    println!("{}", crate::utils::ui::warning(&format!(
+                        "Not linking command {}, {} is not a link diem made",
+                        command.command,
+                        link.display()
+                    )));
+                    continue;
+                }
+                Err(_) => {}
+            }
 
-    for entry in fs::read_dir(src)? {
-        let entry = entry?;
-        let src_path = entry.path();
-        let dst_path = dst.join(entry.file_name());
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(&target, &link)?;
 
-        if src_path.is_dir() {
-            copy_dir_contents(&src_path, &dst_path)?;
-        } else {
-            fs::copy(&src_path, &dst_path)?;
+            #[cfg(windows)]
+            std::os::windows::fs::symlink_file(&target, &link)?;
+
+            linked += 1;
         }
-    }
 
-    Ok(())
+        Ok(linked)
+    }
 }
+
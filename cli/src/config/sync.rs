@@ -0,0 +1,338 @@
+//! Incremental mirror of sgoinfre in goinfre.
+//!
+//! sgoinfre is persistent but slow network storage, goinfre is fast local
+//! disk that is empty on a machine used for the first time. Sync mirrors
+//! the packages stored in sgoinfre, with their store, into the `sgoinfre`
+//! directory of goinfre, kept apart from the packages stored in goinfre
+//! itself. [`relink`] then points installed packages at their mirrored
+//! copy, and back at sgoinfre once the mirror is gone.
+//!
+//! A manifest in goinfre records the size, modification time and sha256 of
+//! every mirrored file, and copies are given the time of their file. Files
+//! whose size and time did not change are skipped without being read,
+//! unless their copy was modified since, changed ones are copied when their
+//! digest differs, and files removed from sgoinfre are removed from the
+//! mirror.
+//! Files hardlinked together in sgoinfre, like the package store, stay
+//! hardlinked in the mirror.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Manifest of the mirror, in goinfre.
+pub const MANIFEST_FILE: &str = ".diem-sync.json";
+
+/// Directory of goinfre the packages of sgoinfre are mirrored in.
+pub const MIRROR_DIR: &str = "sgoinfre";
+
+/// What was mirrored, and where.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct SyncManifest {
+    /// Machine the mirror was last synced on
+    pub host: String,
+    /// Files by path relative to sgoinfre
+    pub files: BTreeMap<PathBuf, FileState>,
+}
+
+/// A mirrored file as it was in sgoinfre.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct FileState {
+    pub size: u64,
+    /// Nanoseconds since the Unix epoch
+    pub modified: u64,
+    /// sha256, empty for symlinks
+    pub digest: String,
+    /// Target of a symlink
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link: Option<PathBuf>,
+}
+
+/// What a sync did.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SyncReport {
+    pub copied: usize,
+    /// Files hardlinked to a copy of the same sgoinfre file
+    pub hardlinked: usize,
+    pub unchanged: usize,
+    pub deleted: usize,
+    /// Bytes copied
+    pub bytes: u64,
+    /// Installed packages pointed at the mirror, or back at sgoinfre
+    pub relinked: usize,
+}
+
+/// The mirror of sgoinfre in `goinfre`.
+pub fn mirror_dir(goinfre: &Path) -> PathBuf {
+    goinfre.join(MIRROR_DIR)
+}
+
+/// Whether `goinfre` was never synced on this machine, as after a wipe or
+/// on a machine used for the first time.
+pub fn is_fresh(goinfre: &Path) -> bool {
+    read_manifest(goinfre).is_none_or(|manifest| manifest.host != hostname())
+}
+
+fn read_manifest(goinfre: &Path) -> Option<SyncManifest> {
+    let content = std::fs::read_to_string(goinfre.join(MANIFEST_FILE)).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// Brings the mirror of `sgoinfre` in `goinfre` up to date.
+pub fn sync(sgoinfre: &Path, goinfre: &Path) -> Result<SyncReport> {
+    if !sgoinfre.is_dir() {
+        anyhow::bail!("sgoinfre directory {} does not exist", sgoinfre.display());
+    }
+
+    let mut syncer = Syncer {
+        source: sgoinfre,
+        mirror: mirror_dir(goinfre),
+        previous: read_manifest(goinfre).unwrap_or_default().files,
+        files: BTreeMap::new(),
+        copies: HashMap::new(),
+        report: SyncReport::default(),
+    };
+    std::fs::create_dir_all(&syncer.mirror)?;
+    syncer.sync_dir(Path::new(""))?;
+    syncer.delete_removed()?;
+
+    let manifest = SyncManifest { host: hostname(), files: syncer.files };
+    let path = goinfre.join(MANIFEST_FILE);
+    let temp_path = path.with_extension(format!("json.{}.tmp", std::process::id()));
+    std::fs::write(&temp_path, serde_json::to_string(&manifest)?)?;
+    std::fs::rename(&temp_path, &path)?;
+
+    Ok(syncer.report)
+}
+
+struct Syncer<'a> {
+    source: &'a Path,
+    mirror: PathBuf,
+    previous: BTreeMap<PathBuf, FileState>,
+    files: BTreeMap<PathBuf, FileState>,
+    // Mirror path of each sgoinfre file by inode, to keep hardlinks
+    copies: HashMap<(u64, u64), PathBuf>,
+    report: SyncReport,
+}
+
+impl Syncer<'_> {
+    fn sync_dir(&mut self, relative: &Path) -> Result<()> {
+        let destination = self.mirror.join(relative);
+        if std::fs::symlink_metadata(&destination).is_ok_and(|metadata| !metadata.is_dir()) {
+            remove_existing(&destination)?;
+        }
+        std::fs::create_dir_all(&destination)?;
+
+        let mut entries: Vec<_> = std::fs::read_dir(self.source.join(relative))?.collect::<Result<_, _>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            let relative = relative.join(entry.file_name());
+            let metadata = std::fs::symlink_metadata(entry.path())?;
+            if metadata.is_symlink() {
+                self.sync_symlink(&relative)?;
+            } else if metadata.is_dir() {
+                self.sync_dir(&relative)?;
+            } else {
+                self.sync_file(&relative, &metadata)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn sync_symlink(&mut self, relative: &Path) -> Result<()> {
+        let target = std::fs::read_link(self.source.join(relative))?;
+        let destination = self.mirror.join(relative);
+
+        if std::fs::read_link(&destination).is_ok_and(|existing| existing == target) {
+            self.report.unchanged += 1;
+        } else {
+            remove_existing(&destination)?;
+            symlink(&target, &destination)?;
+            self.report.copied += 1;
+        }
+
+        let state = FileState { size: 0, modified: 0, digest: String::new(), link: Some(target) };
+        self.files.insert(relative.to_path_buf(), state);
+        Ok(())
+    }
+
+    fn sync_file(&mut self, relative: &Path, metadata: &std::fs::Metadata) -> Result<()> {
+        let source = self.source.join(relative);
+        let destination = self.mirror.join(relative);
+        let size = metadata.len();
+        let modified = nanos(metadata)?;
+
+        let copy = std::fs::symlink_metadata(&destination).ok().filter(|copy| copy.is_file() && copy.len() == size);
+        let previous = self.previous.get(relative).filter(|previous| previous.link.is_none());
+
+        // Unchanged files are recognized without reading them
+        let digest = match previous {
+            Some(previous) if copy.is_some() && previous.size == size && previous.modified == modified => {
+                previous.digest.clone()
+            }
+            _ => file_digest(&source)?,
+        };
+        let id = file_id(metadata);
+
+        // Copies carry the time of their sgoinfre file, so a copy modified
+        // since it was synced is read again
+        let intact = match (&copy, previous) {
+            (Some(copy), Some(previous)) if previous.digest == digest => {
+                nanos(copy)? <= previous.modified || file_digest(&destination)? == digest
+            }
+            _ => false,
+        };
+
+        if let Some(copy) = copy.filter(|_| intact) {
+            if nanos(&copy)? != modified {
+                set_modified(&destination, metadata)?;
+            }
+            self.report.unchanged += 1;
+        } else if let Some(copy) = id.and_then(|id| self.copies.get(&id)).filter(|copy| copy.is_file()) {
+            remove_existing(&destination)?;
+            std::fs::hard_link(copy, &destination)?;
+            self.report.hardlinked += 1;
+        } else {
+            // Copies appear whole, so an interrupted sync leaves no partial file
+            let temp_path = destination.with_file_name(format!(
+                ".{}.{}.tmp",
+                destination.file_name().unwrap_or_default().to_string_lossy(),
+                std::process::id()
+            ));
+            std::fs::copy(&source, &temp_path)?;
+            set_modified(&temp_path, metadata)?;
+            std::fs::rename(&temp_path, &destination)?;
+            self.report.copied += 1;
+            self.report.bytes += size;
+        }
+
+        if let Some(id) = id {
+            self.copies.entry(id).or_insert(destination);
+        }
+        self.files.insert(relative.to_path_buf(), FileState { size, modified, digest, link: None });
+        Ok(())
+    }
+
+    // Removes mirrored files that are gone from sgoinfre, and the
+    // directories they leave empty
+    fn delete_removed(&mut self) -> Result<()> {
+        let removed: Vec<PathBuf> =
+            self.previous.keys().filter(|relative| !self.files.contains_key(*relative)).cloned().collect();
+
+        for relative in removed {
+            let destination = self.mirror.join(&relative);
+            if std::fs::symlink_metadata(&destination).is_ok() {
+                remove_existing(&destination)?;
+                self.report.deleted += 1;
+            }
+
+            for parent in destination.ancestors().skip(1) {
+                if parent == self.mirror || std::fs::remove_dir(parent).is_err() {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Points the versions in `install_dir` stored in `packages_dir` at their
+/// copy in `mirror` when there is one, and those linked into `mirror` back
+/// at `packages_dir` when their copy is gone. Returns how many links
+/// changed.
+pub fn relink(install_dir: &Path, packages_dir: &Path, mirror: &Path) -> Result<usize> {
+    let Ok(entries) = std::fs::read_dir(install_dir) else {
+        return Ok(0);
+    };
+
+    let mut relinked = 0;
+    for package_entry in entries {
+        let package_entry = package_entry?;
+        if package_entry.file_name().to_string_lossy().starts_with('.') || !package_entry.file_type()?.is_dir() {
+            continue;
+        }
+
+        for entry in std::fs::read_dir(package_entry.path())? {
+            let link = entry?.path();
+            let Ok(target) = std::fs::read_link(&link) else {
+                continue;
+            };
+            let retarget = if let Ok(relative) = target.strip_prefix(packages_dir) {
+                Some(mirror.join(relative)).filter(|copy| copy.is_dir())
+            } else if let Ok(relative) = target.strip_prefix(mirror) {
+                Some(packages_dir.join(relative)).filter(|original| !target.is_dir() && original.is_dir())
+            } else {
+                None
+            };
+
+            if let Some(retarget) = retarget {
+                std::fs::remove_file(&link)?;
+                #[cfg(unix)]
+                std::os::unix::fs::symlink(&retarget, &link)?;
+                #[cfg(windows)]
+                std::os::windows::fs::symlink_dir(&retarget, &link)?;
+                relinked += 1;
+            }
+        }
+    }
+    Ok(relinked)
+}
+
+/// Name of this machine, which tells whether goinfre was synced here.
+pub fn hostname() -> String {
+    std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/proc/sys/kernel/hostname").ok())
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .unwrap_or_default()
+}
+
+fn file_digest(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+// Modification time in nanoseconds since the Unix epoch
+fn nanos(metadata: &std::fs::Metadata) -> std::io::Result<u64> {
+    Ok(metadata.modified()?.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_nanos() as u64))
+}
+
+fn set_modified(path: &Path, source: &std::fs::Metadata) -> std::io::Result<()> {
+    std::fs::File::open(path)?.set_modified(source.modified()?)
+}
+
+fn remove_existing(path: &Path) -> std::io::Result<()> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => std::fs::remove_dir_all(path),
+        Ok(_) => std::fs::remove_file(path),
+        Err(_) => Ok(()),
+    }
+}
+
+#[cfg(unix)]
+fn file_id(metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.nlink() > 1).then(|| (metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
@@ -1,15 +1,22 @@
 pub mod app;
 pub mod artifactory;
+pub mod catalog;
 pub mod cli;
 pub mod config;
 pub mod package;
+pub mod platform;
 pub mod provider;
+pub mod schema;
+pub mod signature;
+#[cfg(feature = "testing")]
+pub mod testing;
 pub mod utils;
 
 pub use app::{command::AppCommand, manager::AppManager, App};
 pub use artifactory::Artifactory;
+pub use catalog::Catalog;
 pub use cli::{Cli, Commands, ProvidersCommands, ArtifactoryCommands, ConfigCommands};
 pub use config::Config;
-pub use package::{manager::PackageManager, Package};
+pub use package::{manager::PackageManager, Package, PackageVariant};
 pub use provider::{github::GithubProvider, manager::ProviderManager, Provider, ProviderSource};
 pub use utils::ui;
@@ -4,10 +4,17 @@ use clap_complete::generate;
 use colored::*;
 
 use diem::{
-    AppManager, Artifactory, Cli, Commands, Config, GithubProvider, PackageManager, Provider, ProviderManager,
+    App, AppManager, Artifactory, Catalog, Cli, Commands, Config, GithubProvider, Package, PackageManager, Provider, ProviderManager,
     ProviderSource, ProvidersCommands, ArtifactoryCommands, ConfigCommands, 
-    artifactory::manager::ArtifactoryManager,
-    config::{ArtifactorySource, ArtifactorySubscription},
+    app::info,
+    artifactory::{
+        import, index::IndexCache, manager::ArtifactoryManager, pack,
+        search::{Field, MatchMode, SearchQuery},
+        validate::{self, Severity},
+    },
+    config::{ArtifactorySource, ArtifactorySubscription, lock::{self, Lock}, sync::{self, SyncReport}},
+    package::{gc::{Collector, Garbage}, placement::{self, Volume}, shared, store::Store, usage, verify::{self, Problem}},
+    schema::{self, Format}, signature,
     utils::ui,
 };
 
@@ -35,11 +42,31 @@ async fn main() -> anyhow::Result<()> {
 /// this function will load the configuration file and then match the
 /// subcommands.
 async fn match_commands(args: Cli) -> anyhow::Result<()> {
-    let mut cfg: Config = confy::load("diem", "config")?;
+    // Held until the command is done, and taken before loading the
+    // configuration so changes made by the process waited for are seen
+    let lock = if args.command.is_mutating() { Some(acquire_lock()?) } else { None };
+
+    let mut cfg = Config::load()?;
     cfg.ensure_dirs_exist()?;
     
-    // Sync goinfre from sgoinfre on startup if needed
-    cfg.sync_goinfre_from_sgoinfre()?;
+    // Fill goinfre from sgoinfre on a machine it was never synced on.
+    // Read-only commands leave it to another diem process at work.
+    if !matches!(args.command, Commands::Sync | Commands::Config { .. }) && cfg.goinfre_is_fresh() {
+        let sync_lock = match &lock {
+            Some(_) => None,
+            None => Lock::try_acquire(&lock::default_path()?)?,
+        };
+        if lock.is_some() || sync_lock.is_some() {
+            let pb = ui::spinner();
+            pb.set_message("Syncing packages from sgoinfre to goinfre...");
+            let report = cfg.sync_goinfre_from_sgoinfre()?;
+            pb.finish_with_message(ui::success(&format!(
+                "Synchronized goinfre: {}",
+                report.map(|report| sync_summary(&report)).unwrap_or_default()
+            )));
+            restore_missing_packages(&mut cfg).await?;
+        }
+    }
 
     match args.command {
         Commands::Completions { .. } => unreachable!(),
@@ -49,25 +76,29 @@ async fn match_commands(args: Cli) -> anyhow::Result<()> {
             let pb = ui::spinner();
             pb.set_message("Initializing package manager...");
             
-            let package_manager = PackageManager::new(cfg.install_dir.clone());
-            let app_manager = AppManager::new(package_manager);
-            let provider_manager = ProviderManager::new_from_config(&cfg);
+            let catalog = Catalog::new_from_config(&cfg);
 
             pb.set_message(format!("Finding app: {}", app.cyan()));
-            let (app, provider) = provider_manager.find_app(&app, &cfg).await?;
+            let (app, provider) = catalog.find_app(&app).await?;
             pb.finish_with_message(ui::success(&format!("Found app: {} in {}", 
                 app.name.green(), provider.name.blue())));
+            pin_rotated_keys(Some(&mut cfg), &catalog)?;
                 
+            let app_manager = AppManager::new(placed_package_manager(&cfg, &app, &provider).await?);
             app_manager.install_app(&app, &provider).await?;
+            cfg.record_installed(&app);
+            confy::store("diem", "config", &cfg)?;
         }
         Commands::Remove { package } => {
             println!("{}", ui::title(&format!("Removing: {}", package)));
             
             let package_manager = PackageManager::new(cfg.install_dir.clone());
             package_manager.uninstall_package(&package, None).await?;
+            cfg.forget_installed(&package);
+            confy::store("diem", "config", &cfg)?;
         }
         Commands::Update { package } => {
-            let provider_manager = ProviderManager::new_from_config(&cfg);
+            let catalog = Catalog::new_from_config(&cfg);
             
             if let Some(pkg_name) = package {
                 println!("{}", ui::title(&format!("Updating: {}", pkg_name)));
@@ -76,26 +107,26 @@ async fn match_commands(args: Cli) -> anyhow::Result<()> {
                 pb.set_message(format!("Finding app: {}", pkg_name.cyan()));
                 
                 // Find the app and update it
-                let (app, provider) = provider_manager.find_app(&pkg_name, &cfg).await?;
+                let (app, provider) = catalog.find_app(&pkg_name).await?;
                 pb.finish_with_message(ui::success(&format!("Found app: {} in {}", 
                     app.name.green(), provider.name.blue())));
+                pin_rotated_keys(Some(&mut cfg), &catalog)?;
                 
-                let package_manager = PackageManager::new(cfg.install_dir.clone());
-                let app_manager = AppManager::new(package_manager);
+                let app_manager = AppManager::new(placed_package_manager(&cfg, &app, &provider).await?);
                 
                 for pkg in &app.packages {
                     app_manager.package_manager.update_package(pkg, &provider).await?;
                 }
+                cfg.record_installed(&app);
+                cfg.ensure_binaries_symlinked(&bin_dir()?)?;
+                confy::store("diem", "config", &cfg)?;
                 
                 println!("{}", ui::success(&format!("Updated app: {}", app.name)));
             } else {
                 println!("{}", ui::title("Updating all packages"));
                 
                 // Get all apps from providers and update them
-                let installed_packages = &cfg.packages;
-                
-                let package_manager = PackageManager::new(cfg.install_dir.clone());
-                let app_manager = AppManager::new(package_manager);
+                let installed_packages = cfg.packages.clone();
                 
                 if installed_packages.is_empty() {
                     println!("{}", ui::warning("No packages installed"));
@@ -104,43 +135,363 @@ async fn match_commands(args: Cli) -> anyhow::Result<()> {
                 
                 println!("{}", ui::info(&format!("Found {} installed packages", installed_packages.len())));
                 
-                for package in installed_packages {
+                for package in &installed_packages {
                     // Find the provider that has this package
-                    if let Ok((app, provider)) = provider_manager.find_app(&package.name, &cfg).await {
+                    if let Ok((app, provider)) = catalog.find_newest_app(&package.name).await {
                         println!("{}", ui::section(&format!("Updating: {}", app.name)));
+                        let app_manager = AppManager::new(placed_package_manager(&cfg, &app, &provider).await?);
                         for pkg in &app.packages {
                             app_manager.package_manager.update_package(pkg, &provider).await?;
                         }
+                        cfg.record_installed(&app);
                     }
                 }
+                pin_rotated_keys(Some(&mut cfg), &catalog)?;
+                cfg.ensure_binaries_symlinked(&bin_dir()?)?;
+                confy::store("diem", "config", &cfg)?;
                 
                 println!("{}", ui::success("All packages updated successfully"));
             }
         }
         Commands::Providers { command } => match_providers_commands(cfg, command).await?,
         Commands::Artifactory { command } => match_artifactory_commands(&mut cfg, command).await?,
-        Commands::Search { query } => search_apps(&cfg, &query).await?,
+        Commands::Search { query, exact, regex, case_sensitive, tags, categories } => {
+            let mode = if regex {
+                MatchMode::Regex
+            } else if exact {
+                MatchMode::Exact
+            } else {
+                MatchMode::Fuzzy
+            };
+            let search_query = SearchQuery::new(query.as_deref().unwrap_or_default(), mode, case_sensitive)?
+                .with_tags(tags)
+                .with_categories(categories);
+            search_apps(&cfg, &search_query, query.as_deref()).await?
+        },
+        Commands::Info { app } => show_app_info(&cfg, &app).await?,
         Commands::List => list_available_apps(&cfg).await?,
+        Commands::Gc { dry_run, older_than } => collect_garbage(&cfg, dry_run, older_than)?,
+        Commands::Du => show_disk_usage(&cfg)?,
+        Commands::Verify { app } => verify_installed(&cfg, app.as_deref())?,
+        Commands::Repair { app } => repair_installed(&mut cfg, app.as_deref()).await?,
+        Commands::Publish { app } => publish_app(&cfg, &app)?,
         Commands::Sync => {
             println!("{}", ui::title("Synchronizing packages"));
             
             let pb = ui::spinner();
             pb.set_message("Syncing packages from sgoinfre to goinfre...");
             
-            cfg.sync_goinfre_from_sgoinfre()?;
+            match cfg.sync_goinfre_from_sgoinfre()? {
+                Some(report) => pb.finish_with_message(ui::success(&format!(
+                    "Synchronization completed: {}",
+                    sync_summary(&report)
+                ))),
+                None => pb.finish_with_message(ui::warning("Set both sgoinfre and goinfre directories to sync them")),
+            }
             
-            pb.finish_with_message(ui::success("Synchronization completed successfully"));
+            let restored = restore_missing_packages(&mut cfg).await?;
+            if restored > 0 {
+                println!("{}", ui::success(&format!("Restored {} packages whose files were gone", restored)));
+            }
         },
         Commands::Config { command } => match_config_commands(&mut cfg, command).await?,
     }
     Ok(())
 }
 
+/// Takes the lock of the current user, telling when another diem process
+/// holds it.
+fn acquire_lock() -> Result<Lock> {
+    let path = lock::default_path()?;
+    let lock = Lock::acquire(&path, |holder| {
+        let holder = holder.map(|pid| format!(" (pid {})", pid)).unwrap_or_default();
+        println!("{}", ui::info(&format!("Waiting for another diem process{} to finish...", holder)));
+    })?;
+    Ok(lock)
+}
+
+fn sync_summary(report: &SyncReport) -> String {
+    format!(
+        "{} copied ({}), {} hardlinked, {} unchanged, {} deleted, {} packages relinked",
+        report.copied,
+        info::format_size(report.bytes),
+        report.hardlinked,
+        report.unchanged,
+        report.deleted,
+        report.relinked
+    )
+}
+
+/// Pins the keys artifactories rotated to, so that each rotation is
+/// followed once and keeps working after the maintainer drops it. `cfg` is
+/// the config of a command holding the lock; read-only commands save a
+/// fresh copy under the lock instead, or leave it to the next command when
+/// another diem process holds it.
+fn pin_rotated_keys(cfg: Option<&mut Config>, catalog: &Catalog) -> Result<()> {
+    let rotations = catalog.key_rotations();
+    if rotations.is_empty() {
+        return Ok(());
+    }
+
+    let pinned = match cfg {
+        Some(cfg) => {
+            let pinned = cfg.pin_rotated_keys(&rotations);
+            confy::store("diem", "config", &*cfg)?;
+            pinned
+        }
+        None => {
+            let Some(_lock) = Lock::try_acquire(&lock::default_path()?)? else {
+                return Ok(());
+            };
+            let mut cfg = Config::load()?;
+            let pinned = cfg.pin_rotated_keys(&rotations);
+            confy::store("diem", "config", &cfg)?;
+            pinned
+        }
+    };
+    for rotation in pinned {
+        let message = if rotation.first_seen {
+            format!("{} is signed with {}, pinned this key", rotation.name, rotation.public_key)
+        } else {
+            format!("{} rotated its signing key, pinned the new key {}", rotation.name, rotation.public_key)
+        };
+        println!("{}", ui::info(&message));
+    }
+    Ok(())
+}
+
+/// Installs again the packages whose files are gone, as those stored in a
+/// wiped goinfre, from the download cache when it has them. Returns how
+/// many were restored.
+async fn restore_missing_packages(cfg: &mut Config) -> Result<usize> {
+    let mut missing = Vec::new();
+    for package in installed_packages(cfg, None)? {
+        if verify::verify_package(&cfg.install_dir, &package.name, &package.version)? == [Problem::MissingPackage] {
+            missing.push(package);
+        }
+    }
+    if missing.is_empty() {
+        return Ok(0);
+    }
+
+    let catalog = Catalog::new_from_config(cfg);
+    let package_manager = with_shared_sources(cfg, PackageManager::new(cfg.install_dir.clone()));
+    let mut restored = 0;
+    for package in &missing {
+        let provider = catalog.find_package(&package.name, &package.version).await;
+        match package_manager.repair_package(package, provider.as_ref()).await {
+            Ok(_) => restored += 1,
+            Err(e) => println!("{}", ui::warning(&format!(
+                "Could not restore {} {}: {}", package.name, package.version, e
+            ))),
+        }
+    }
+    pin_rotated_keys(Some(cfg), &catalog)?;
+    cfg.ensure_binaries_symlinked(&bin_dir()?)?;
+    Ok(restored)
+}
+
+fn bin_dir() -> Result<std::path::PathBuf> {
+    directories::BaseDirs::new()
+        .and_then(|dirs| dirs.executable_dir().map(|dir| dir.to_path_buf()))
+        .ok_or_else(|| anyhow::anyhow!("Could not determine executable directory"))
+}
+
+/// A package manager storing `app` on the volume the placement policy picks
+/// for its size.
+async fn placed_package_manager(cfg: &Config, app: &App, provider: &Provider) -> Result<PackageManager> {
+    let size = info::total_download_size(&app.packages, Some(&provider.location())).await;
+    let placement = cfg.placement.place(cfg, &app.name, size, placement::available_space)?;
+    println!("{}", ui::info(&format!(
+        "Storing {} ({}) in {}: {}",
+        app.name,
+        info::format_size(size),
+        placement.volume.to_string().cyan(),
+        placement.reason
+    )));
+
+    let package_manager = PackageManager::new(cfg.install_dir.clone()).with_packages_dir(placement.packages_dir);
+    Ok(with_shared_sources(cfg, package_manager))
+}
+
+// Adds the shared install tree and the download cache, when there are
+fn with_shared_sources(cfg: &Config, mut package_manager: PackageManager) -> PackageManager {
+    if let Some(shared) = cfg.shared_tree() {
+        package_manager = package_manager.with_shared_tree(shared);
+    }
+    if let Some(cache) = cfg.download_cache() {
+        package_manager = package_manager.with_download_cache(cache);
+    }
+    package_manager
+}
+
+/// The recorded packages of every installed app, or of one, with their
+/// dependencies, each version once.
+fn installed_packages(cfg: &Config, app: Option<&str>) -> Result<Vec<Package>> {
+    let mut pending: Vec<&Package> = match app {
+        Some(app) => match cfg.packages.iter().find(|package| package.name == app) {
+            Some(package) => vec![package],
+            None => anyhow::bail!("{} is not installed", app),
+        },
+        None => cfg.packages.iter().rev().collect(),
+    };
+
+    let mut packages: Vec<Package> = Vec::new();
+    while let Some(package) = pending.pop() {
+        if !packages.iter().any(|seen| seen.name == package.name && seen.version == package.version) {
+            packages.push(package.clone());
+        }
+        pending.extend(package.dependencies.iter().rev());
+    }
+    Ok(packages)
+}
+
+/// Checks installed packages against their file manifests, and their
+/// commands, failing when something is wrong.
+fn verify_installed(cfg: &Config, app: Option<&str>) -> Result<()> {
+    println!("{}", ui::title("Verifying installed packages"));
+
+    let packages = installed_packages(cfg, app)?;
+    if packages.is_empty() {
+        println!("{}", ui::warning("No packages installed"));
+        return Ok(());
+    }
+
+    let mut problems = 0;
+    for package in &packages {
+        let found = verify::verify_package(&cfg.install_dir, &package.name, &package.version)?;
+        let label = format!("{} {}", package.name, package.version);
+        match found.as_slice() {
+            [] => println!("{}", ui::success(&label)),
+            [Problem::Unrecorded] => println!("{}", ui::warning(&format!("{}: {}", label, Problem::Unrecorded))),
+            found => {
+                println!("{}", ui::error(&label));
+                for problem in found {
+                    println!("    {}", problem);
+                }
+                problems += found.len();
+            }
+        }
+    }
+
+    let bin_dir = bin_dir()?;
+    for command in cfg.commands.iter().filter(|command| packages.iter().any(|package| package.name == command.package)) {
+        if let Some(problem) = verify::verify_command(command, &cfg.install_dir, &bin_dir) {
+            println!("{}", ui::error(&format!("Command {}: {}", command.command, problem)));
+            problems += 1;
+        }
+    }
+
+    if problems > 0 {
+        anyhow::bail!("Found {} problems, run `diem repair` to fix them", problems);
+    }
+    println!("\n{}", ui::success("Everything is installed as it should be"));
+    Ok(())
+}
+
+/// Repairs installed packages, looking up their providers only for those
+/// that need repairs, and links their commands again.
+async fn repair_installed(cfg: &mut Config, app: Option<&str>) -> Result<()> {
+    println!("{}", ui::title("Repairing installed packages"));
+
+    let catalog = Catalog::new_from_config(cfg);
+    let package_manager = with_shared_sources(cfg, PackageManager::new(cfg.install_dir.clone()));
+
+    let mut failed = 0;
+    for package in installed_packages(cfg, app)? {
+        let problems = verify::verify_package(&cfg.install_dir, &package.name, &package.version)?;
+        let label = format!("{} {}", package.name, package.version);
+        match problems.as_slice() {
+            [] => continue,
+            [Problem::Unrecorded] => {
+                println!("{}", ui::warning(&format!("{}: {}, reinstall it to check it", label, Problem::Unrecorded)));
+                continue;
+            }
+            _ => {}
+        }
+
+        println!("{}", ui::section(&format!("Repairing: {}", label)));
+        let provider = catalog.find_package(&package.name, &package.version).await;
+        match package_manager.repair_package(&package, provider.as_ref()).await {
+            Ok(repair) if repair.reinstalled => println!("{}", ui::success(&format!("Installed {} again", label))),
+            Ok(repair) => println!("{}", ui::success(&format!(
+                "Restored {} files and removed {} of {}{}",
+                repair.restored,
+                repair.removed,
+                label,
+                if repair.downloaded { ", downloading it again" } else { "" }
+            ))),
+            Err(e) => {
+                println!("{}", ui::error(&format!("Could not repair {}: {}", label, e)));
+                failed += 1;
+            }
+        }
+    }
+
+    pin_rotated_keys(Some(cfg), &catalog)?;
+    let relinked = cfg.ensure_binaries_symlinked(&bin_dir()?)?;
+    if relinked > 0 {
+        println!("{}", ui::success(&format!("Linked {} commands again", relinked)));
+    }
+
+    if failed > 0 {
+        anyhow::bail!("{} packages could not be repaired", failed);
+    }
+    println!("\n{}", ui::success("Repair completed"));
+    Ok(())
+}
+
+/// Publishes the recorded packages of an installed app, with their
+/// dependencies, into the shared install tree.
+fn publish_app(cfg: &Config, app: &str) -> Result<()> {
+    println!("{}", ui::title(&format!("Publishing: {}", app)));
+
+    let Some(shared) = cfg.shared_tree() else {
+        anyhow::bail!("No shared install tree is configured, set one with `diem config set-shared-install`");
+    };
+    let Some(package) = cfg.packages.iter().find(|package| package.name == app) else {
+        anyhow::bail!("{} is not installed", app);
+    };
+
+    let package_manager = PackageManager::new(cfg.install_dir.clone());
+    let mut pending = vec![package];
+    while let Some(package) = pending.pop() {
+        pending.extend(&package.dependencies);
+        let installed_dir = package_manager.get_package_dir(&package.name, &package.version);
+        if shared::is_shared(&std::fs::canonicalize(&installed_dir).unwrap_or_default()) {
+            println!("{}", ui::info(&format!("{} {} is linked from the shared tree", package.name, package.version)));
+        } else if verify::verify_package(&cfg.install_dir, &package.name, &package.version)?
+            .iter()
+            .any(|problem| *problem != Problem::Unrecorded)
+        {
+            anyhow::bail!(
+                "{} {} differs from what was installed, run `diem repair {}` before publishing it",
+                package.name,
+                package.version,
+                app
+            );
+        } else if shared.publish(package, &installed_dir)? {
+            println!("{}", ui::success(&format!("Published {} {}", package.name, package.version)));
+        } else {
+            println!("{}", ui::info(&format!("{} {} is already published", package.name, package.version)));
+        }
+    }
+
+    println!("{}", ui::success(&format!(
+        "{} can be installed from {} by your group",
+        app,
+        shared.root().display()
+    )));
+    Ok(())
+}
+
 async fn match_providers_commands(mut cfg: Config, command: ProvidersCommands) -> Result<()> {
     let mut provider_manager = ProviderManager::new_from_config(&cfg);
     match command {
         ProvidersCommands::Add {
             provider: provider_name,
+            public_key,
+            allow_unsigned,
         } => {
             println!("{}", ui::title(&format!("Adding provider: {}", provider_name)));
             
@@ -167,7 +518,7 @@ async fn match_providers_commands(mut cfg: Config, command: ProvidersCommands) -
             }
 
             pb.set_message("Creating provider...");
-            let provider = Provider {
+            let mut provider = Provider {
                 name: provider_name.clone(),
                 source: ProviderSource::Github(GithubProvider {
                     owner: owner_repo[0].to_string(),
@@ -175,9 +526,25 @@ async fn match_providers_commands(mut cfg: Config, command: ProvidersCommands) -
                     ref_: repo_parts[1].to_string(),
                     path: parts[2].to_string(),
                 }),
-                provider_handler_version: 1,
+                provider_handler_version: schema::PROVIDER_HANDLER_VERSION,
+                public_key: None,
+                allow_unsigned,
             };
 
+            pb.set_message("Fetching artifactory signing key...");
+            let content = provider.fetch_artifactory().await?;
+            let provider_signature = provider.fetch_signature().await?;
+            pb.finish_and_clear();
+            provider.public_key = signature::pin_public_key(
+                &provider_name,
+                &content,
+                provider_signature.as_deref(),
+                public_key,
+                allow_unsigned,
+            )?;
+
+            let pb = ui::spinner();
+
             pb.set_message("Adding provider to configuration...");
             provider_manager.add_provider(provider)?;
             provider_manager.save_to_config(&mut cfg);
@@ -230,14 +597,15 @@ async fn match_artifactory_commands(cfg: &mut Config, command: ArtifactoryComman
     let artifactory_manager = ArtifactoryManager::new(cfg.clone());
     
     match command {
-        ArtifactoryCommands::Subscribe { name, source, auto_update } => {
+        ArtifactoryCommands::Subscribe { name, source, auto_update, public_key, allow_unsigned } => {
             // Determine if it's a local path or a URL
+            let mut pinned_key = public_key.clone();
             let source = if source.starts_with("http://") || source.starts_with("https://") {
                 ArtifactorySource::Remote(source)
             } else {
                 let path = std::path::PathBuf::from(&source);
                 
-                // Validate the path exists and is a valid TOML file
+                // Validate the path exists and is a valid artifactory file
                 if !path.exists() {
                     anyhow::bail!("Artifactory file not found: {}", source);
                 }
@@ -246,17 +614,21 @@ async fn match_artifactory_commands(cfg: &mut Config, command: ArtifactoryComman
                     anyhow::bail!("Path is not a file: {}", source);
                 }
                 
-                if path.extension().map_or(true, |ext| ext != "toml") {
-                    anyhow::bail!("Artifactory file must be a TOML file");
-                }
-                
-                // Try to parse the TOML to verify it's valid
-                let content = std::fs::read_to_string(&path)
-                    .map_err(|e| anyhow::anyhow!("Failed to read artifactory file: {}", e))?;
+                // Try to parse the TOML, JSON or YAML to verify it's valid
+                let (content, artifactory_signature) = signature::read_with_signature(&path)?;
                     
-                toml::from_str::<Artifactory>(&content)
+                schema::parse_artifactory_as(&content, Format::for_file(&path, &content))
                     .map_err(|e| anyhow::anyhow!("Invalid artifactory file: {}", e))?;
                 
+                // Check the signature and pin the maintainer's key
+                pinned_key = signature::pin_public_key(
+                    &name,
+                    &content,
+                    artifactory_signature.as_deref(),
+                    public_key,
+                    allow_unsigned,
+                )?;
+                
                 ArtifactorySource::Local(path)
             };
             
@@ -264,6 +636,8 @@ async fn match_artifactory_commands(cfg: &mut Config, command: ArtifactoryComman
                 name: name.clone(),
                 source,
                 auto_update,
+                public_key: pinned_key,
+                allow_unsigned,
             };
             
             let mut manager = ArtifactoryManager::new(cfg.clone());
@@ -277,6 +651,15 @@ async fn match_artifactory_commands(cfg: &mut Config, command: ArtifactoryComman
         },
         ArtifactoryCommands::Unsubscribe { name } => {
             let mut manager = ArtifactoryManager::new(cfg.clone());
+            
+            // Shared artifactories are ignored, as they are not subscribed to
+            if manager.list_shared().iter().any(|sub| sub.name == name) {
+                cfg.ignored_shared_artifactories.push(name.clone());
+                confy::store("diem", "config", &cfg)?;
+                
+                println!("Ignoring shared artifactory: {}", name);
+                return Ok(());
+            }
             manager.remove_subscription(&name)?;
             
             // Save subscriptions to config
@@ -287,9 +670,10 @@ async fn match_artifactory_commands(cfg: &mut Config, command: ArtifactoryComman
         },
         ArtifactoryCommands::List => {
             let manager = ArtifactoryManager::new(cfg.clone());
-            let subscriptions = manager.list_subscribed();
+            let shared = manager.list_shared();
+            let subscriptions = manager.list_subscribed().into_iter().chain(&shared);
             
-            if subscriptions.is_empty() {
+            if cfg.subscribed_artifactories.is_empty() && shared.is_empty() {
                 println!("No artifactory subscriptions found");
                 return Ok(());
             }
@@ -299,97 +683,355 @@ async fn match_artifactory_commands(cfg: &mut Config, command: ArtifactoryComman
                 let source_desc = match &sub.source {
                     ArtifactorySource::Local(path) => format!("Local: {}", path.display()),
                     ArtifactorySource::Remote(url) => format!("Remote: {}", url),
+                    ArtifactorySource::Shared(path) => format!("Shared: {}", path.display()),
                 };
                 
                 println!("  - {} ({})", sub.name, source_desc);
                 println!("    Auto-update: {}", if sub.auto_update { "Yes" } else { "No" });
+                if let Some(public_key) = &sub.public_key {
+                    println!("    Public key: {}", public_key);
+                } else if sub.allow_unsigned {
+                    println!("    Public key: None (unsigned allowed)");
+                }
             }
         },
-        ArtifactoryCommands::Create { name, path, public, description, maintainer } => {
+        ArtifactoryCommands::Create { name, path, public, description, maintainer, public_key, format } => {
+            if let Some(public_key) = &public_key {
+                signature::parse_public_key(public_key)?;
+            }
+
             let artifactory = Artifactory {
                 name: name.clone(),
                 description,
                 apps: Vec::new(),
                 maintainer,
                 public,
-                artifactory_handler_version: 1,
+                artifactory_handler_version: schema::ARTIFACTORY_HANDLER_VERSION,
+                public_key,
+                key_rotations: Vec::new(),
+                include: Vec::new(),
             };
             
-            artifactory_manager.create_artifactory(&artifactory, &path)?;
+            let format = format.or_else(|| Format::from_path(&path)).unwrap_or(Format::Toml);
+            artifactory_manager.create_artifactory(&artifactory, &path, format)?;
             println!("Created artifactory: {} at {}", name, path.display());
         },
         ArtifactoryCommands::AddApp { artifactory: art_path, app: app_path } => {
             // Load artifactory
             let art_content = std::fs::read_to_string(&art_path)?;
-            let mut artifactory: Artifactory = toml::from_str(&art_content)?;
+            let format = Format::for_file(&art_path, &art_content);
+            let mut artifactory = schema::parse_artifactory_as(&art_content, format)?;
             
             // Load app
             let app_content = std::fs::read_to_string(&app_path)?;
-            let app = toml::from_str(&app_content)?;
+            let app = schema::parse_app(&app_content)?;
             
             // Add app to artifactory
             artifactory.apps.push(app);
             
             // Save updated artifactory
-            let updated_content = toml::to_string_pretty(&artifactory)?;
+            let updated_content = format.serialize(&artifactory)?;
             std::fs::write(&art_path, updated_content)?;
             
             println!("Added app to artifactory: {}", art_path.display());
         },
+        ArtifactoryCommands::Pack { dir, name, version, target, artifactory, license, description, commands } => {
+            println!("{}", ui::title(&format!("Packing: {} {}", name, version)));
+            
+            let request = pack::PackRequest {
+                dir,
+                artifactory,
+                name,
+                version,
+                target,
+                license,
+                description,
+                commands: commands.iter().map(|spec| pack::parse_command(spec)).collect::<Result<_>>()?,
+            };
+            
+            let pb = ui::spinner();
+            pb.set_message(format!("Packing {}", request.dir.display().to_string().cyan()));
+            let package = pack::pack(&request)?;
+            pb.finish_with_message(ui::success(&format!(
+                "Registered {} {} in {}", package.name, package.version, request.artifactory.display()
+            )));
+            
+            let (source, sha256) = match &request.target {
+                Some(target) => {
+                    let variant = package.variants.iter().find(|variant| variant.target == *target)
+                        .expect("packed variant is registered");
+                    (variant.source.clone(), variant.sha256.clone())
+                }
+                None => (package.source.clone().unwrap_or_default(), package.sha256.clone()),
+            };
+            ui::key_value_table("Package", &[
+                ("Source", source),
+                ("sha256", sha256),
+            ]);
+        },
+        ArtifactoryCommands::ImportRelease { repo, tag, file, name, version, artifactory, license, description, commands } => {
+            let (owner, repo) = import::parse_repo(&repo)?;
+            println!("{}", ui::title(&format!("Importing release: {}/{}", owner, repo)));
+            
+            let release = match &file {
+                Some(file) => import::load_release(file)?,
+                None => {
+                    let pb = ui::spinner();
+                    pb.set_message(format!("Fetching release {}", tag.as_deref().unwrap_or("latest").cyan()));
+                    let release = import::fetch_release(&owner, &repo, tag.as_deref()).await?;
+                    pb.finish_with_message(ui::success(&format!("Found release {}", release.tag_name)));
+                    release
+                }
+            };
+            
+            let request = import::ImportRequest {
+                artifactory,
+                name: name.unwrap_or(repo),
+                version,
+                license,
+                description,
+                commands: commands.iter().map(|spec| pack::parse_command(spec)).collect::<Result<_>>()?,
+            };
+            
+            let pb = ui::spinner();
+            pb.set_message(format!("Downloading assets of {}", release.tag_name.cyan()));
+            let app = import::import(&request, &release).await?;
+            pb.finish_with_message(ui::success(&format!(
+                "Registered {} {} in {}", app.name, app.version, request.artifactory.display()
+            )));
+            
+            if let Some(package) = app.packages.iter().find(|package| package.name == app.name) {
+                let rows: Vec<(&str, String)> = package.variants.iter()
+                    .map(|variant| (variant.target.as_str(), variant.sha256.clone()))
+                    .collect();
+                ui::key_value_table("Variants", &rows);
+            }
+            if app.commands.is_empty() {
+                println!("{}", ui::warning("No command was found in the archives, add them with --command NAME=PATH"));
+            }
+        },
+        ArtifactoryCommands::Validate { path } => {
+            println!("{}", ui::title(&format!("Validating: {}", path.display())));
+            
+            let report = validate::validate_file(&path).await?;
+            for issue in &report.issues {
+                let message = format!("{}: {}", issue.location, issue.message);
+                match issue.severity {
+                    Severity::Error => println!("{}", ui::error(&message)),
+                    Severity::Warning => println!("{}", ui::warning(&message)),
+                }
+            }
+            
+            if report.has_errors() {
+                anyhow::bail!("Found {} errors and {} warnings in {}", report.errors(), report.warnings(), path.display());
+            }
+            
+            println!("\n{}", ui::success(&format!("Artifactory is valid ({} warnings)", report.warnings())));
+        },
     }
     
     Ok(())
 }
 
-async fn search_apps(cfg: &Config, query: &str) -> Result<()> {
+async fn search_apps(cfg: &Config, search_query: &SearchQuery, query: Option<&str>) -> Result<()> {
+    let query = query.unwrap_or("all apps");
     println!("{}", ui::title(&format!("Search Results for: {}", query.cyan())));
     
     let pb = ui::spinner();
     pb.set_message(format!("Searching for apps matching: {}", query.cyan()));
     
-    let manager = ArtifactoryManager::new(cfg.clone());
-    let results = manager.search_apps(query)?;
+    let catalog = Catalog::new_from_config(cfg);
+    let hits = catalog.search(search_query).await;
+    pin_rotated_keys(None, &catalog)?;
     
-    if results.is_empty() {
+    if hits.is_empty() {
         pb.finish_with_message(ui::warning(&format!("No apps found matching: {}", query)));
         return Ok(());
     }
     
-    pb.finish_with_message(ui::success(&format!("Found {} artifactories with matching apps", results.len())));
+    pb.finish_with_message(ui::success(&format!("Found {} matching apps", hits.len())));
     
-    for (artifactory, apps) in results {
-        println!("{}", ui::section(&format!("From artifactory: {}", artifactory.green())));
+    for (i, hit) in hits.iter().enumerate() {
+        let number = format!("{}.", i + 1).cyan();
+        let matched = if hit.field == Field::Name {
+            String::new()
+        } else {
+            format!(" [matched {}]", hit.field).dimmed().to_string()
+        };
         
-        for (i, app) in apps.iter().enumerate() {
-            let number = format!("{}.", i + 1).cyan();
-            println!("  {} {}", number, app.green());
+        println!("  {} {} {} {}{}",
+            number,
+            hit.name.green().bold(),
+            format!("v{}", hit.version).yellow(),
+            format!("from {}", hit.artifactory).magenta(),
+            matched
+        );
+        if let Some(description) = &hit.description {
+            println!("     {}", description.blue());
+        }
+    }
+    
+    Ok(())
+}
+
+async fn show_app_info(cfg: &Config, spec: &str) -> Result<()> {
+    let (name, version) = diem::app::parse_spec(spec)?;
+    println!("{}", ui::title(&format!("App: {}", name)));
+    
+    let pb = ui::spinner();
+    pb.set_message(format!("Looking up app: {}", name.cyan()));
+    
+    let catalog = Catalog::new_from_config(cfg);
+    let offers = catalog.find_app_offers(&name).await;
+    pin_rotated_keys(None, &catalog)?;
+    if offers.is_empty() {
+        pb.finish_with_message(ui::error(&format!("App {} not found in any provider or artifactory", name)));
+        anyhow::bail!("App {} not found", name);
+    }
+    
+    let Some(offer) = info::select(&offers, version.as_ref()) else {
+        pb.finish_with_message(ui::error(&format!("App {} not found", spec)));
+        let available: Vec<String> = offers.iter().map(|offer| offer.app.version.to_string()).collect();
+        anyhow::bail!("Version {} of {} not found. Available versions: {}", 
+            version.map(|v| v.to_string()).unwrap_or_default(), name, available.join(", "));
+    };
+    let app = &offer.app;
+    
+    pb.finish_with_message(ui::success(&format!("Found {} {} in {}", 
+        app.name.green(), app.version.to_string().yellow(), offer.source.blue())));
+    
+    let mut rows = vec![
+        ("Name", app.name.clone()),
+        ("Version", app.version.to_string()),
+        ("License", app.license.clone()),
+        ("Source", offer.source.clone()),
+    ];
+    if let Some(description) = &app.description {
+        rows.push(("Description", description.clone()));
+    }
+    if let Some(homepage) = &app.homepage {
+        rows.push(("Homepage", homepage.clone()));
+    }
+    if let Some(repository) = &app.repository {
+        rows.push(("Repository", repository.clone()));
+    }
+    if !app.maintainers.is_empty() {
+        rows.push(("Maintainers", app.maintainers.join(", ")));
+    }
+    if !app.categories.is_empty() {
+        rows.push(("Categories", app.categories.join(", ")));
+    }
+    if !app.tags.is_empty() {
+        rows.push(("Tags", app.tags.join(", ")));
+    }
+    ui::key_value_table("Details", &rows);
+    
+    // Versions offered by every source, with the one shown highlighted
+    let installed = app.packages.first()
+        .map(|package| info::installed_versions(&cfg.install_dir, &package.name))
+        .unwrap_or_default();
+    println!("{}", ui::section("Available versions"));
+    for other in &offers {
+        let marker = if std::ptr::eq(other, offer) { "*".green().bold() } else { " ".normal() };
+        let state = if installed.contains(&other.app.version) { " (installed)".green().to_string() } else { String::new() };
+        println!("  {} {} {}{}", 
+            marker, 
+            format!("v{}", other.app.version).yellow(), 
+            format!("from {}", other.source).magenta(), 
+            state);
+    }
+    
+    println!("{}", ui::section("Packages"));
+    if app.packages.is_empty() {
+        println!("  {}", ui::info("No packages"));
+    }
+    let mut total_size = Some(0);
+    for package in &app.packages {
+        print_package_tree(package, offer, 0, &mut total_size).await;
+    }
+    if let Some(total) = total_size.filter(|_| !app.packages.is_empty()) {
+        println!("  {} {}", "Total download:".cyan(), info::format_size(total));
+    }
+    
+    if !app.commands.is_empty() {
+        println!("{}", ui::section("Commands"));
+        let bin_dir = directories::BaseDirs::new().and_then(|dirs| dirs.executable_dir().map(|dir| dir.to_path_buf()));
+        for cmd in &app.commands {
+            let link = bin_dir.as_ref().map(|dir| dir.join(&cmd.command));
+            let state = match &link {
+                Some(link) if link.symlink_metadata().is_ok() => format!(" ({})", link.display()).green().to_string(),
+                _ => String::new(),
+            };
+            println!("  • {} → {}{}", cmd.command.green(), cmd.path.display().to_string().blue(), state);
+        }
+    }
+    
+    println!("{}", ui::section("Installation"));
+    if installed.is_empty() {
+        println!("  {}", ui::info("Not installed"));
+    } else {
+        let versions: Vec<String> = installed.iter().map(|version| version.to_string()).collect();
+        println!("  {} {}", "Installed versions:".cyan(), versions.join(", "));
+    }
+    for package in &app.packages {
+        let dir = info::package_dir(&cfg.install_dir, package);
+        let state = if dir.exists() { "present".green() } else { "not installed".dimmed() };
+        println!("  {} {} → {} ({})", 
+            package.name.green(), 
+            package.version.to_string().yellow(), 
+            dir.display().to_string().blue(), 
+            state);
+    }
+    
+    if let Some(readme) = &app.readme {
+        println!("{}", ui::section("Readme"));
+        for line in readme.trim_end().lines() {
+            println!("  {}", line);
         }
     }
     
     Ok(())
 }
 
+// Prints a package, its license and download size, then its dependencies
+async fn print_package_tree(package: &Package, offer: &info::AppOffer, depth: usize, total_size: &mut Option<u64>) {
+    let size = info::download_size(package, Some(&offer.provider.location())).await;
+    *total_size = total_size.zip(size).map(|(total, size)| total + size);
+    
+    println!("  {}• {} {} {} {}",
+        "  ".repeat(depth),
+        package.name.green(),
+        format!("v{}", package.version).yellow(),
+        format!("[{}]", package.license).magenta(),
+        size.map(info::format_size).unwrap_or_else(|| "size unknown".to_string()).dimmed()
+    );
+    for dependency in &package.dependencies {
+        Box::pin(print_package_tree(dependency, offer, depth + 1, total_size)).await;
+    }
+}
+
 async fn list_available_apps(cfg: &Config) -> Result<()> {
     println!("{}", ui::title("Available Applications"));
     
     let pb = ui::spinner();
-    pb.set_message("Loading subscribed artifactories...");
+    pb.set_message("Loading providers and subscribed artifactories...");
     
-    let manager = ArtifactoryManager::new(cfg.clone());
-    let artifactories = manager.load_all_subscribed();
+    let catalog = Catalog::new_from_config(cfg);
+    let artifactories = catalog.load_all().await;
+    pin_rotated_keys(None, &catalog)?;
     
     if artifactories.is_empty() {
-        pb.finish_with_message(ui::warning("No artifactories found. Subscribe to an artifactory first."));
+        pb.finish_with_message(ui::warning("No providers or artifactories found. Add a provider or subscribe to an artifactory first."));
         return Ok(());
     }
     
-    pb.finish_with_message(ui::success(&format!("Found {} artifactories", artifactories.len())));
+    pb.finish_with_message(ui::success(&format!("Found {} sources", artifactories.len())));
     
     let mut app_count = 0;
-    for result in artifactories {
+    for (source, result) in artifactories {
         match result {
             Ok(artifactory) => {
-                println!("{}", ui::section(&format!("From: {}", artifactory.name.green())));
+                println!("{}", ui::section(&format!("From: {} ({})", artifactory.name.green(), source)));
                 
                 if artifactory.apps.is_empty() {
                     println!("  {}", ui::info("No apps available in this artifactory"));
@@ -417,7 +1059,7 @@ async fn list_available_apps(cfg: &Config) -> Result<()> {
                 }
             },
             Err(e) => {
-                println!("  {}", ui::error(&format!("Error loading artifactory: {}", e)));
+                println!("  {}", ui::error(&format!("Error loading {}: {}", source, e)));
             }
         }
     }
@@ -427,6 +1069,170 @@ async fn list_available_apps(cfg: &Config) -> Result<()> {
     Ok(())
 }
 
+fn collect_garbage(cfg: &Config, dry_run: bool, older_than: u64) -> Result<()> {
+    println!("{}", ui::title(if dry_run { "Garbage collection (dry run)" } else { "Garbage collection" }));
+
+    let collector = garbage_collector(cfg)
+        .older_than(std::time::Duration::from_secs(older_than * 24 * 60 * 60))
+        .dry_run(dry_run);
+    let report = collector.collect()?;
+    if report.removals.is_empty() {
+        println!("{}", ui::success("Nothing to collect"));
+        return Ok(());
+    }
+
+    println!("{}", ui::section(if dry_run { "Would remove" } else { "Removed" }));
+    for removal in &report.removals {
+        println!(
+            "  {} {} ({})",
+            format!("[{}]", removal.kind).yellow(),
+            removal.path.display(),
+            info::format_size(removal.size)
+        );
+    }
+
+    let kinds = [Garbage::Version, Garbage::Temporary, Garbage::Manifest, Garbage::Object, Garbage::Cache];
+    let rows: Vec<(&str, String)> = kinds
+        .iter()
+        .map(|kind| (kind_label(*kind), report.count(*kind).to_string()))
+        .collect();
+    ui::key_value_table("Summary", &rows);
+
+    let reclaimed = info::format_size(report.reclaimed);
+    if dry_run {
+        println!("{}", ui::info(&format!("{} would be reclaimed", reclaimed)));
+    } else {
+        println!("{}", ui::success(&format!("Reclaimed {}", reclaimed)));
+    }
+    Ok(())
+}
+
+// Collects under the install directory, the bin directory's links and the caches
+fn garbage_collector(cfg: &Config) -> Collector {
+    let mut collector = Collector::new(cfg.install_dir.clone())
+        .with_installed(&cfg.packages)
+        .with_temp_dir(std::env::temp_dir());
+    for volume in [Volume::Sgoinfre, Volume::Goinfre] {
+        if let Some(packages_dir) = volume.packages_dir(cfg) {
+            collector = collector.with_volume(packages_dir);
+        }
+    }
+    if let (Some(packages_dir), Some(goinfre)) = (Volume::Sgoinfre.packages_dir(cfg), &cfg.goinfre_dir) {
+        collector = collector.with_mirror(sync::mirror_dir(goinfre), packages_dir);
+    }
+    if let Some(bin_dir) = directories::BaseDirs::new().and_then(|dirs| dirs.executable_dir().map(|dir| dir.to_path_buf())) {
+        collector = collector.with_bin_dir(bin_dir);
+    }
+    if let Some(cache) = IndexCache::open_default() {
+        collector = collector.with_cache_dir(cache.dir().clone());
+    }
+    // A download cache set explicitly may be shared, and is left to whoever
+    // manages it
+    if let (None, Some(cache)) = (&cfg.download_cache_dir, cfg.download_cache()) {
+        collector = collector.with_cache_dir(cache.dir().clone());
+    }
+    collector
+}
+
+fn show_disk_usage(cfg: &Config) -> Result<()> {
+    println!("{}", ui::title("Disk usage"));
+
+    let home = directories::BaseDirs::new().map(|dirs| dirs.home_dir().to_path_buf());
+    let locations = [
+        ("Install dir", Some(cfg.install_dir.clone())),
+        ("Package store", Some(Store::for_install_dir(&cfg.install_dir).root().to_path_buf())),
+        ("Sgoinfre", cfg.sgoinfre_dir.clone()),
+        ("Goinfre", cfg.goinfre_dir.clone()),
+        ("Index cache", IndexCache::open_default().map(|cache| cache.dir().clone())),
+        ("Download cache", cfg.download_cache().map(|cache| cache.dir().clone())),
+    ];
+    let mut rows = Vec::new();
+    for (label, path) in locations {
+        let Some(path) = path else {
+            rows.push((label, "not configured".dimmed().to_string()));
+            continue;
+        };
+        let size = usage::measure(&path)?.bytes;
+        let quota = if home.as_ref().is_some_and(|home| path.starts_with(home)) { " (home quota)".yellow().to_string() } else { String::new() };
+        rows.push((label, format!("{:>10}  {}{}", info::format_size(size), path.display(), quota)));
+    }
+    ui::key_value_table("Locations", &rows);
+
+    let usages = usage::package_usage(&cfg.install_dir)?;
+    println!("{}", ui::section("Apps"));
+    if cfg.packages.is_empty() {
+        println!("  No recorded apps");
+    }
+    for package in &cfg.packages {
+        let size = usage::app_size(std::slice::from_ref(package), &usages);
+        println!("  {} {}  {} with dependencies", package.name.green(), package.version.to_string().yellow(), info::format_size(size));
+    }
+
+    let report = garbage_collector(cfg).dry_run(true).collect()?;
+    println!("{}", ui::section("Packages"));
+    if usages.is_empty() {
+        println!("  No packages installed");
+    }
+    let volumes: Vec<(Volume, std::path::PathBuf)> = [Volume::Sgoinfre, Volume::Goinfre]
+        .into_iter()
+        .filter_map(|volume| Some((volume, std::fs::canonicalize(volume.packages_dir(cfg)?).ok()?)))
+        .collect();
+    let mirror = cfg.goinfre_dir.as_ref().and_then(|goinfre| std::fs::canonicalize(sync::mirror_dir(goinfre)).ok());
+    for usage in &usages {
+        let unused = report.removals.iter().any(|removal| removal.kind == Garbage::Version && removal.path == usage.path);
+        let location = if shared::is_shared(&usage.stored) {
+            "shared tree".to_string()
+        } else if mirror.as_ref().is_some_and(|mirror| usage.stored.starts_with(mirror)) {
+            "sgoinfre, mirrored in goinfre".to_string()
+        } else {
+            volumes
+                .iter()
+                .find(|(_, dir)| usage.stored.starts_with(dir))
+                .map_or(Volume::Home, |(volume, _)| *volume)
+                .to_string()
+        };
+        println!(
+            "  {} {}  {} ({} exclusive) in {}{}",
+            usage.name.green(),
+            usage.version.to_string().yellow(),
+            info::format_size(usage.bytes),
+            info::format_size(usage.exclusive),
+            location.cyan(),
+            if unused { " unused".red().to_string() } else { String::new() }
+        );
+    }
+
+    if let (Some(packages_dir), Some(goinfre)) = (Volume::Sgoinfre.packages_dir(cfg), &cfg.goinfre_dir) {
+        let duplicates = usage::duplicates(&packages_dir, &sync::mirror_dir(goinfre))?;
+        if duplicates.files > 0 {
+            println!("{}", ui::warning(&format!(
+                "{} in {} files is duplicated between sgoinfre and goinfre",
+                info::format_size(duplicates.bytes),
+                duplicates.files
+            )));
+        } else {
+            println!("{}", ui::success("Nothing is duplicated between sgoinfre and goinfre"));
+        }
+    }
+
+    if report.reclaimed > 0 {
+        println!("{}", ui::info(&format!("{} could be freed with `diem gc`", info::format_size(report.reclaimed))));
+    } else {
+        println!("{}", ui::success("Nothing to free with `diem gc`"));
+    }
+    Ok(())
+}
+
+fn kind_label(kind: Garbage) -> &'static str {
+    match kind {
+        Garbage::Version => "Unused versions",
+        Garbage::Temporary => "Temporary files",
+        Garbage::Manifest => "Store manifests",
+        Garbage::Object => "Store objects",
+        Garbage::Cache => "Cache entries",
+    }
+}
+
 async fn match_config_commands(cfg: &mut Config, command: ConfigCommands) -> Result<()> {
     match command {
         ConfigCommands::SetSgoinfre { path } => {
@@ -462,6 +1268,104 @@ async fn match_config_commands(cfg: &mut Config, command: ConfigCommands) -> Res
             
             pb.finish_with_message(ui::success(&format!("Set shared artifactory directory to: {}", path.display())));
         },
+        ConfigCommands::SetSharedInstall { path } => {
+            println!("{}", ui::title("Configuration Update"));
+            
+            cfg.shared_install_dir = Some(path.clone());
+            confy::store("diem", "config", &cfg)?;
+            
+            println!("{}", ui::success(&format!("Packages published in {} will be linked", path.display())));
+        },
+        ConfigCommands::UnsetSharedInstall => {
+            println!("{}", ui::title("Configuration Update"));
+            
+            if cfg.shared_install_dir.take().is_none() {
+                println!("{}", ui::warning("No shared install tree is set"));
+                return Ok(());
+            }
+            confy::store("diem", "config", &cfg)?;
+            
+            println!("{}", ui::success("Packages will be installed privately"));
+        },
+        ConfigCommands::AllowUnsignedShared => {
+            println!("{}", ui::title("Configuration Update"));
+            
+            cfg.allow_unsigned_shared = true;
+            confy::store("diem", "config", &cfg)?;
+            
+            println!("{}", ui::warning("Unsigned shared artifactories will be used, anyone who can write to their directory can change them"));
+        },
+        ConfigCommands::DenyUnsignedShared => {
+            println!("{}", ui::title("Configuration Update"));
+            
+            cfg.allow_unsigned_shared = false;
+            confy::store("diem", "config", &cfg)?;
+            
+            println!("{}", ui::success("Only signed shared artifactories will be used"));
+        },
+        ConfigCommands::SetDownloadCache { path } => {
+            println!("{}", ui::title("Configuration Update"));
+            
+            cfg.download_cache_dir = Some(path.clone());
+            confy::store("diem", "config", &cfg)?;
+            
+            println!("{}", ui::success(&format!("Downloads will be cached in {}", path.display())));
+        },
+        ConfigCommands::UnsetDownloadCache => {
+            println!("{}", ui::title("Configuration Update"));
+            
+            if cfg.download_cache_dir.take().is_none() {
+                println!("{}", ui::warning("No download cache directory is set"));
+                return Ok(());
+            }
+            confy::store("diem", "config", &cfg)?;
+            
+            match cfg.download_cache() {
+                Some(cache) => println!("{}", ui::success(&format!("Downloads will be cached in {}", cache.dir().display()))),
+                None => println!("{}", ui::warning("Downloads will not be cached, no cache directory was found")),
+            }
+        },
+        ConfigCommands::SetPlacement { app, volume } => {
+            println!("{}", ui::title("Configuration Update"));
+            
+            if volume.packages_dir(cfg).is_none() {
+                anyhow::bail!("The {} directory is not configured", volume);
+            }
+            cfg.placement.apps.insert(app.clone(), volume);
+            confy::store("diem", "config", &cfg)?;
+            
+            println!("{}", ui::success(&format!("{} will be stored in {}", app, volume)));
+        },
+        ConfigCommands::UnsetPlacement { app } => {
+            println!("{}", ui::title("Configuration Update"));
+            
+            if cfg.placement.apps.remove(&app).is_none() {
+                println!("{}", ui::warning(&format!("{} has no placement set", app)));
+                return Ok(());
+            }
+            confy::store("diem", "config", &cfg)?;
+            
+            println!("{}", ui::success(&format!("{} will be stored according to its size", app)));
+        },
+        ConfigCommands::SetPlacementPolicy { threshold, bulky, home_reserve } => {
+            println!("{}", ui::title("Configuration Update"));
+            
+            if let Some(threshold) = threshold {
+                cfg.placement.threshold_mib = threshold;
+            }
+            if let Some(bulky) = bulky {
+                cfg.placement.bulky = bulky;
+            }
+            if let Some(home_reserve) = home_reserve {
+                cfg.placement.home_reserve_mib = home_reserve;
+            }
+            confy::store("diem", "config", &cfg)?;
+            
+            println!("{}", ui::success(&format!(
+                "Apps of {} MiB or more will be stored in {}, keeping {} MiB free in home",
+                cfg.placement.threshold_mib, cfg.placement.bulky, cfg.placement.home_reserve_mib
+            )));
+        },
         ConfigCommands::Show => {
             println!("{}", ui::title("Current Configuration"));
             
@@ -486,7 +1390,7 @@ async fn match_config_commands(cfg: &mut Config, command: ConfigCommands) -> Res
                 config_items.push(("Goinfre directory", not_set));
             }
             
-            if let Some(shared) = &cfg.shared_artifactory_dir {
+            if let Some(shared) = cfg.shared_artifactory_path() {
                 let shared_dir = shared.display().to_string();
                 config_items.push(("Shared artifactory", shared_dir));
             } else {
@@ -494,9 +1398,30 @@ async fn match_config_commands(cfg: &mut Config, command: ConfigCommands) -> Res
                 config_items.push(("Shared artifactory", not_set));
             }
             
+            let unsigned_shared = if cfg.allow_unsigned_shared { "Allowed" } else { "Refused" };
+            config_items.push(("Unsigned shared artifactories", unsigned_shared.to_string()));
+            
+            if let Some(shared) = &cfg.shared_install_dir {
+                config_items.push(("Shared install tree", shared.display().to_string()));
+            } else {
+                config_items.push(("Shared install tree", "Not set".red().to_string()));
+            }
+            
+            if let Some(cache) = cfg.download_cache() {
+                config_items.push(("Download cache", cache.dir().display().to_string()));
+            } else {
+                config_items.push(("Download cache", "Not set".red().to_string()));
+            }
+            
             let subscribed_count = cfg.subscribed_artifactories.len().to_string();
             config_items.push(("Subscribed artifactories", subscribed_count));
             
+            let placement = format!(
+                "{} MiB or more in {}, {} MiB kept free in home",
+                cfg.placement.threshold_mib, cfg.placement.bulky, cfg.placement.home_reserve_mib
+            );
+            config_items.push(("Placement", placement));
+            
             // Create a key-value table
             ui::key_value_table("Settings", &config_items);
             
@@ -508,13 +1433,23 @@ async fn match_config_commands(cfg: &mut Config, command: ConfigCommands) -> Res
                 }
             }
             
-            // If there are artifactories, list them
-            if !cfg.subscribed_artifactories.is_empty() {
+            // If apps are placed by hand, list them
+            if !cfg.placement.apps.is_empty() {
+                println!("{}", ui::section("Placed Apps"));
+                for (app, volume) in &cfg.placement.apps {
+                    println!("  {} in {}", app.green(), volume.to_string().cyan());
+                }
+            }
+            
+            // If there are artifactories, shared ones included, list them
+            let subscriptions = cfg.all_subscriptions();
+            if !subscriptions.is_empty() {
                 println!("{}", ui::section("Subscribed Artifactories"));
-                for (i, sub) in cfg.subscribed_artifactories.iter().enumerate() {
+                for (i, sub) in subscriptions.iter().enumerate() {
                     let source_type = match &sub.source {
                         ArtifactorySource::Local(_) => "Local".blue(),
                         ArtifactorySource::Remote(_) => "Remote".magenta(),
+                        ArtifactorySource::Shared(_) => "Shared".cyan(),
                     };
                     println!("  {}. {} ({})", (i+1).to_string().cyan(), sub.name.green(), source_type);
                 }
@@ -0,0 +1,162 @@
+use anyhow::Result;
+use sha2::Digest as _;
+
+use std::path::{Component, Path, PathBuf};
+
+use crate::utils::ui;
+
+/// Package archive formats, recognized from the package source. Downloads
+/// are always stored as `package.tmp`, so the source is the only reliable
+/// hint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    TarGz,
+    Tar,
+}
+
+impl ArchiveFormat {
+    pub fn from_source(source: &str) -> Result<Self> {
+        if source.ends_with(".zip") {
+            Ok(Self::Zip)
+        } else if source.ends_with(".tar.gz") || source.ends_with(".tgz") {
+            Ok(Self::TarGz)
+        } else if source.ends_with(".tar") {
+            Ok(Self::Tar)
+        } else {
+            anyhow::bail!("Unsupported package archive format: {}", source)
+        }
+    }
+}
+
+/// Extracts the archive at `archive_path` into `destination`.
+pub fn extract(archive_path: &Path, source: &str, destination: &Path) -> Result<()> {
+    let file = std::fs::File::open(archive_path)?;
+    match ArchiveFormat::from_source(source)? {
+        ArchiveFormat::Zip => {
+            zip::ZipArchive::new(file)?.extract(destination)?;
+            Ok(())
+        }
+        ArchiveFormat::TarGz => unpack_tar(tar::Archive::new(flate2::read::GzDecoder::new(file)), destination),
+        ArchiveFormat::Tar => unpack_tar(tar::Archive::new(file), destination),
+    }
+}
+
+/// Lists the files in the archive at `archive_path`, with leading `./`
+/// components removed.
+pub fn list_entries(archive_path: &Path, source: &str) -> Result<Vec<PathBuf>> {
+    let file = std::fs::File::open(archive_path)?;
+    let paths = match ArchiveFormat::from_source(source)? {
+        ArchiveFormat::Zip => {
+            let archive = zip::ZipArchive::new(file)?;
+            archive.file_names().map(PathBuf::from).collect()
+        }
+        ArchiveFormat::TarGz => tar_entries(tar::Archive::new(flate2::read::GzDecoder::new(file)))?,
+        ArchiveFormat::Tar => tar_entries(tar::Archive::new(file))?,
+    };
+
+    Ok(paths.iter().map(|path| normalize(path)).collect())
+}
+
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|component| !matches!(component, Component::CurDir))
+        .collect()
+}
+
+fn tar_entries<R: std::io::Read>(mut archive: tar::Archive<R>) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for entry in archive.entries()? {
+        paths.push(entry?.path()?.into_owned());
+    }
+    Ok(paths)
+}
+
+// Unpacks a tar archive entry by entry to be verbose about what's happening
+fn unpack_tar<R: std::io::Read>(mut archive: tar::Archive<R>, destination: &Path) -> Result<()> {
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?;
+        println!("{}", ui::info(&format!("Extracting file: {}", path.display())));
+
+        // Extract the entry
+        entry.unpack_in(destination)?;
+    }
+
+    Ok(())
+}
+
+/// Packs the contents of `dir` into a reproducible `.tar.gz` at
+/// `destination` and returns its sha256 digest.
+///
+/// Entries are sorted, owners are reset to root, modification times are
+/// zeroed and modes are reduced to `0o755` or `0o644`, so the same files
+/// always produce the same archive.
+pub fn pack_directory(dir: &Path, destination: &Path) -> Result<String> {
+    if !dir.is_dir() {
+        anyhow::bail!("Not a directory: {}", dir.display());
+    }
+
+    let encoder = flate2::GzBuilder::new().mtime(0).write(Vec::new(), flate2::Compression::best());
+    let mut builder = tar::Builder::new(encoder);
+    append_sorted(&mut builder, dir, Path::new(""))?;
+    let bytes = builder.into_inner()?.finish()?;
+
+    if let Some(parent) = destination.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(destination, &bytes)?;
+
+    Ok(format!("{:x}", sha2::Sha256::digest(&bytes)))
+}
+
+fn append_sorted<W: std::io::Write>(builder: &mut tar::Builder<W>, dir: &Path, prefix: &Path) -> Result<()> {
+    let mut entries = std::fs::read_dir(dir)?.collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let path = entry.path();
+        let archive_path = prefix.join(entry.file_name());
+        let metadata = std::fs::symlink_metadata(&path)?;
+
+        let mut header = tar::Header::new_gnu();
+        header.set_mtime(0);
+        header.set_uid(0);
+        header.set_gid(0);
+        header.set_username("root")?;
+        header.set_groupname("root")?;
+
+        if metadata.file_type().is_symlink() {
+            header.set_entry_type(tar::EntryType::Symlink);
+            header.set_mode(0o777);
+            header.set_size(0);
+            builder.append_link(&mut header, &archive_path, std::fs::read_link(&path)?)?;
+        } else if metadata.is_dir() {
+            header.set_entry_type(tar::EntryType::Directory);
+            header.set_mode(0o755);
+            header.set_size(0);
+            header.set_cksum();
+            builder.append_data(&mut header, &archive_path, std::io::empty())?;
+            append_sorted(builder, &path, &archive_path)?;
+        } else {
+            header.set_entry_type(tar::EntryType::Regular);
+            header.set_mode(if is_executable(&metadata) { 0o755 } else { 0o644 });
+            header.set_size(metadata.len());
+            header.set_cksum();
+            builder.append_data(&mut header, &archive_path, std::fs::File::open(&path)?)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+pub(crate) fn is_executable(metadata: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+pub(crate) fn is_executable(_metadata: &std::fs::Metadata) -> bool {
+    false
+}
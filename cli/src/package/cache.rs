@@ -0,0 +1,96 @@
+//! Downloaded package archives, cached by sha256.
+//!
+//! Installs look in the cache before downloading, and add what they
+//! download to it, so reinstalling a package, or installing it into another
+//! store, does not download it again. The cache is kept in sgoinfre when
+//! there is one, and can be pointed at a directory shared with everyone who
+//! uses it.
+//!
+//! Entries are named after the sha256 the artifactory declares for the
+//! archive. Since anyone who can write to a shared cache could change
+//! them, an entry is copied out and its copy checked against that sha256
+//! before use; entries that do not match are dropped.
+//!
+//! ```text
+//! downloads/<sha256>
+//! ```
+
+use anyhow::Result;
+use directories::BaseDirs;
+
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use super::registry::file_digest;
+
+/// A directory of downloaded archives.
+#[derive(Debug, Clone)]
+pub struct DownloadCache {
+    dir: PathBuf,
+}
+
+impl DownloadCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// The cache in the user's cache directory, e.g. `~/.cache/diem/downloads`,
+    /// for machines without sgoinfre.
+    pub fn open_default() -> Option<Self> {
+        BaseDirs::new().map(|base_dirs| Self::new(base_dirs.cache_dir().join("diem").join("downloads")))
+    }
+
+    pub fn dir(&self) -> &PathBuf {
+        &self.dir
+    }
+
+    /// Path of the entry for an archive, whether it is cached or not.
+    pub fn path(&self, digest: &str) -> PathBuf {
+        self.dir.join(digest.to_lowercase())
+    }
+
+    /// Copies the cached archive with this sha256 to `destination`, and
+    /// tells whether it was there and sound.
+    pub fn fetch(&self, digest: &str, destination: &Path) -> bool {
+        let path = self.path(digest);
+        if std::fs::copy(&path, destination).is_err() {
+            return false;
+        }
+
+        match file_digest(destination) {
+            Ok(actual) if actual.eq_ignore_ascii_case(digest) => {
+                // Used entries stay until they have not been used for a while
+                if let Ok(file) = std::fs::File::options().append(true).open(&path) {
+                    let _ = file.set_modified(SystemTime::now());
+                }
+                true
+            }
+            _ => {
+                let _ = std::fs::remove_file(&path);
+                let _ = std::fs::remove_file(destination);
+                false
+            }
+        }
+    }
+
+    /// Adds a downloaded archive, already checked to have this sha256.
+    /// Entries are readable by everyone, for caches shared in sgoinfre.
+    pub fn insert(&self, digest: &str, archive: &Path) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        let path = self.path(digest);
+        let temp_path = self.dir.join(format!(".{}.{}.tmp", digest.to_lowercase(), std::process::id()));
+        std::fs::copy(archive, &temp_path)?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&temp_path, std::fs::Permissions::from_mode(0o644))?;
+        }
+
+        if let Err(e) = std::fs::rename(&temp_path, &path) {
+            let _ = std::fs::remove_file(&temp_path);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,475 @@
+//! Garbage collection of what installs and updates leave behind.
+//!
+//! A package version is kept while an installed app uses it: when a package
+//! recorded in the config, or one of its dependencies, is that version, or
+//! when a command symlink points into it. Packages installed before they
+//! were recorded keep their newest version. Everything else under
+//! `install_dir` goes, along with store manifests of removed versions and
+//! the objects no remaining manifest links. Versions stored in sgoinfre or
+//! goinfre go with the link to them, and stored versions nothing links
+//! are removed too. A link to the goinfre mirror of a version stored in
+//! sgoinfre stands for both copies. Versions published in a shared tree
+//! only lose the link.
+//!
+//! Temporary files of interrupted installs and cache entries are removed
+//! once they are older than a threshold, so that an install running in
+//! another shell is left alone.
+
+use anyhow::Result;
+use semver::Version;
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use super::{
+    Package,
+    registry::Registry,
+    shared,
+    store::{Entry, STORE_DIR, Store},
+    usage::visit_files,
+};
+
+/// Age after which temporary files and cache entries are collected.
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+// A package name and version
+type PackageVersion = (String, Version);
+
+// Downloads of installs from before the store, left in the package directory
+const LEGACY_LEFTOVERS: [&str; 2] = ["package.tmp", "temp.tar"];
+
+/// What a removed path was.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Garbage {
+    /// A package version no installed app uses
+    Version,
+    /// A download or directory left by an interrupted operation
+    Temporary,
+    /// A store manifest of a removed version
+    Manifest,
+    /// A store object no manifest links
+    Object,
+    /// An expired cache entry
+    Cache,
+}
+
+impl std::fmt::Display for Garbage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Garbage::Version => write!(f, "unused version"),
+            Garbage::Temporary => write!(f, "temporary file"),
+            Garbage::Manifest => write!(f, "store manifest"),
+            Garbage::Object => write!(f, "store object"),
+            Garbage::Cache => write!(f, "cache entry"),
+        }
+    }
+}
+
+/// A file or directory that was, or would be, removed.
+#[derive(Debug, Clone)]
+pub struct Removal {
+    pub kind: Garbage,
+    pub path: PathBuf,
+    /// Size of the files under `path`, including those linked elsewhere
+    pub size: u64,
+}
+
+/// The outcome of a collection.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub removals: Vec<Removal>,
+    /// Space freed once every removal is done. Files hardlinked from a
+    /// path that stays do not count.
+    pub reclaimed: u64,
+    /// Nothing was removed
+    pub dry_run: bool,
+}
+
+impl Report {
+    pub fn count(&self, kind: Garbage) -> usize {
+        self.removals.iter().filter(|removal| removal.kind == kind).count()
+    }
+}
+
+/// Finds and removes garbage under an install directory.
+#[derive(Debug, Clone)]
+pub struct Collector {
+    install_dir: PathBuf,
+    installed: Vec<Package>,
+    volumes: Vec<PathBuf>,
+    // Mirror of a volume, and the volume
+    mirror: Option<(PathBuf, PathBuf)>,
+    bin_dir: Option<PathBuf>,
+    cache_dirs: Vec<PathBuf>,
+    temp_dir: Option<PathBuf>,
+    max_age: Duration,
+    dry_run: bool,
+}
+
+impl Collector {
+    pub fn new(install_dir: PathBuf) -> Self {
+        Self {
+            install_dir,
+            installed: Vec::new(),
+            volumes: Vec::new(),
+            mirror: None,
+            bin_dir: None,
+            cache_dirs: Vec::new(),
+            temp_dir: None,
+            max_age: DEFAULT_MAX_AGE,
+            dry_run: false,
+        }
+    }
+
+    /// Packages of installed apps, as recorded in the config.
+    pub fn with_installed(mut self, packages: &[Package]) -> Self {
+        self.installed = packages.to_vec();
+        self
+    }
+
+    /// Another directory packages are stored in, see `PackageManager::with_packages_dir`.
+    pub fn with_volume(mut self, packages_dir: PathBuf) -> Self {
+        self.volumes.push(packages_dir);
+        self
+    }
+
+    /// A copy of the packages stored in `packages_dir`, as made by
+    /// `config::sync`, which installed versions may link instead.
+    pub fn with_mirror(mut self, mirror: PathBuf, packages_dir: PathBuf) -> Self {
+        self.mirror = Some((mirror, packages_dir));
+        self
+    }
+
+    /// Directory holding command symlinks into installed packages.
+    pub fn with_bin_dir(mut self, bin_dir: PathBuf) -> Self {
+        self.bin_dir = Some(bin_dir);
+        self
+    }
+
+    /// A cache directory whose files expire after the maximum age.
+    pub fn with_cache_dir(mut self, cache_dir: PathBuf) -> Self {
+        self.cache_dirs.push(cache_dir);
+        self
+    }
+
+    /// System temporary directory, for leftovers of `diem artifactory
+    /// import-release`.
+    pub fn with_temp_dir(mut self, temp_dir: PathBuf) -> Self {
+        self.temp_dir = Some(temp_dir);
+        self
+    }
+
+    pub fn older_than(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// Only report what would be removed.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn collect(&self) -> Result<Report> {
+        let mut removals = Vec::new();
+        let (kept, stored) = self.find_versions(&mut removals)?;
+        self.find_orphans(&stored, &mut removals)?;
+        self.find_store_garbage(&self.install_dir, &kept, &mut removals)?;
+        for volume in self.volumes.iter().filter(|volume| **volume != self.install_dir) {
+            self.find_store_garbage(volume, &kept, &mut removals)?;
+        }
+        for cache_dir in &self.cache_dirs {
+            self.find_expired(cache_dir, Garbage::Cache, |_| true, &mut removals)?;
+        }
+        if let Some(temp_dir) = &self.temp_dir {
+            let is_import = |name: &str| name.starts_with("diem-import-");
+            self.find_expired(temp_dir, Garbage::Temporary, is_import, &mut removals)?;
+        }
+
+        let reclaimed = reclaimed_size(&removals)?;
+
+        // Manifests go before their objects, so an interrupted collection
+        // never leaves a manifest pointing at missing objects
+        if !self.dry_run {
+            for removal in &removals {
+                remove(&removal.path)
+                    .map_err(|e| anyhow::anyhow!("Failed to remove {}: {}", removal.path.display(), e))?;
+            }
+
+            // File manifests go with their versions
+            Registry::for_install_dir(&self.install_dir)
+                .retain(|name, version| kept.contains(&(name.to_string(), version.clone())))?;
+        }
+
+        Ok(Report { removals, reclaimed, dry_run: self.dry_run })
+    }
+
+    // Lists unused versions and leftovers under `install_dir`, returning
+    // the versions that stay and the stored directories linked from it
+    fn find_versions(&self, removals: &mut Vec<Removal>) -> Result<(HashSet<PackageVersion>, HashSet<PathBuf>)> {
+        let mut recorded = HashSet::new();
+        for package in &self.installed {
+            record(package, &mut recorded);
+        }
+        let recorded_names: HashSet<&str> = recorded.iter().map(|(name, _)| name.as_str()).collect();
+        let linked = self.linked_versions();
+
+        let versions = self.scan_versions(&self.install_dir, removals)?;
+        let mut newest: HashMap<&str, &Version> = HashMap::new();
+        for (name, version, _) in &versions {
+            let entry = newest.entry(name).or_insert(version);
+            *entry = (*entry).max(version);
+        }
+
+        let mut kept = HashSet::new();
+        let mut stored = HashSet::new();
+        for (name, version, path) in &versions {
+            let key = (name.clone(), version.clone());
+            let used = recorded.contains(&key)
+                || linked.contains(&key)
+                || (!recorded_names.contains(name.as_str()) && newest.get(name.as_str()) == Some(&version));
+
+            // Versions stored on another volume are links to their files,
+            // or to the mirror of them
+            let link = std::fs::read_link(path).ok();
+            let original = link.as_deref().and_then(|link| self.original(link)).filter(|original| original.is_dir());
+            let target = link.filter(|target| target.is_dir());
+            for stored_dir in target.iter().chain(&original) {
+                stored.insert(canonical(stored_dir));
+            }
+
+            if used {
+                for leftover in LEGACY_LEFTOVERS {
+                    let leftover = path.join(leftover);
+                    if leftover.is_file() {
+                        removals.push(removal(Garbage::Temporary, leftover)?);
+                    }
+                }
+                kept.insert(key);
+            } else {
+                removals.push(removal(Garbage::Version, path.clone())?);
+                for stored_dir in target.into_iter().chain(original).filter(|target| !shared::is_shared(target)) {
+                    removals.push(removal(Garbage::Version, stored_dir)?);
+                }
+            }
+        }
+
+        Ok((kept, stored))
+    }
+
+    // The stored version a link into the mirror is a copy of
+    fn original(&self, link: &Path) -> Option<PathBuf> {
+        let (mirror, packages_dir) = self.mirror.as_ref()?;
+        link.strip_prefix(mirror).ok().map(|relative| packages_dir.join(relative))
+    }
+
+    // Lists versions stored on other volumes that nothing links anymore
+    fn find_orphans(&self, stored: &HashSet<PathBuf>, removals: &mut Vec<Removal>) -> Result<()> {
+        for volume in self.volumes.iter().filter(|volume| **volume != self.install_dir) {
+            for (_, _, path) in self.scan_versions(volume, removals)? {
+                if !stored.contains(&canonical(&path)) {
+                    removals.push(removal(Garbage::Version, path)?);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Version directories laid out as `<name>/<version>` in `dir`, listing
+    // expired directories of interrupted `Store::materialize` calls
+    fn scan_versions(&self, dir: &Path, removals: &mut Vec<Removal>) -> Result<Vec<(String, Version, PathBuf)>> {
+        let mut versions = Vec::new();
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return Ok(versions);
+        };
+
+        for package_entry in entries {
+            let package_entry = package_entry?;
+            let name = package_entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with('.') || !package_entry.file_type()?.is_dir() {
+                continue;
+            }
+
+            for entry in std::fs::read_dir(package_entry.path())? {
+                let entry = entry?;
+                let file_name = entry.file_name().to_string_lossy().into_owned();
+                let file_type = entry.file_type()?;
+                if file_type.is_dir() && file_name.starts_with('.') && file_name.ends_with(".tmp") {
+                    if self.is_expired(&entry.path())? {
+                        removals.push(removal(Garbage::Temporary, entry.path())?);
+                    }
+                } else if file_type.is_dir() || file_type.is_symlink() {
+                    if let Ok(version) = Version::parse(&file_name) {
+                        versions.push((name.clone(), version, entry.path()));
+                    }
+                }
+            }
+        }
+
+        Ok(versions)
+    }
+
+    // Versions command symlinks point into
+    fn linked_versions(&self) -> HashSet<PackageVersion> {
+        let Some(Ok(entries)) = self.bin_dir.as_ref().map(std::fs::read_dir) else {
+            return HashSet::new();
+        };
+
+        entries
+            .flatten()
+            .filter_map(|entry| std::fs::read_link(entry.path()).ok())
+            .filter_map(|target| {
+                let mut components = target.strip_prefix(&self.install_dir).ok()?.components();
+                let name = components.next()?.as_os_str().to_string_lossy().into_owned();
+                let version = Version::parse(&components.next()?.as_os_str().to_string_lossy()).ok()?;
+                Some((name, version))
+            })
+            .collect()
+    }
+
+    // Lists manifests of removed versions and the objects no other manifest
+    // links, in the store of `packages_dir`
+    fn find_store_garbage(
+        &self,
+        packages_dir: &Path,
+        kept: &HashSet<PackageVersion>,
+        removals: &mut Vec<Removal>,
+    ) -> Result<()> {
+        let store = Store::for_install_dir(packages_dir);
+        let root = packages_dir.join(STORE_DIR);
+
+        self.find_expired(&root.join("tmp"), Garbage::Temporary, |_| true, removals)?;
+
+        let mut linked_objects = HashSet::new();
+        let manifests_dir = root.join("packages");
+        if manifests_dir.is_dir() {
+            for entry in std::fs::read_dir(&manifests_dir)? {
+                let path = entry?.path();
+                let file_name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+
+                // Manifests being written by `Store::add`
+                if file_name.ends_with(".tmp") {
+                    if self.is_expired(&path)? {
+                        removals.push(removal(Garbage::Temporary, path)?);
+                    }
+                    continue;
+                }
+
+                let Some(digest) = file_name.strip_suffix(".json") else {
+                    continue;
+                };
+                match store.manifest(digest)? {
+                    Some(manifest) if kept.contains(&(manifest.name.clone(), manifest.version.clone())) => {
+                        linked_objects.extend(manifest.entries.into_iter().filter_map(|entry| match entry {
+                            Entry::File { object, .. } => Some(object),
+                            _ => None,
+                        }));
+                    }
+                    _ => removals.push(removal(Garbage::Manifest, path)?),
+                }
+            }
+        }
+
+        let objects_dir = root.join("objects");
+        if objects_dir.is_dir() {
+            for prefix in std::fs::read_dir(&objects_dir)? {
+                let prefix = prefix?;
+                if !prefix.file_type()?.is_dir() {
+                    continue;
+                }
+                for object in std::fs::read_dir(prefix.path())? {
+                    let object = object?;
+                    let name = format!(
+                        "{}{}",
+                        prefix.file_name().to_string_lossy(),
+                        object.file_name().to_string_lossy()
+                    );
+                    if !linked_objects.contains(&name) {
+                        removals.push(removal(Garbage::Object, object.path())?);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Lists the entries of `dir` accepted by `filter` that are older than
+    // the maximum age
+    fn find_expired(
+        &self,
+        dir: &Path,
+        kind: Garbage,
+        filter: impl Fn(&str) -> bool,
+        removals: &mut Vec<Removal>,
+    ) -> Result<()> {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return Ok(());
+        };
+
+        for entry in entries {
+            let path = entry?.path();
+            if filter(&path.file_name().unwrap_or_default().to_string_lossy()) && self.is_expired(&path)? {
+                removals.push(removal(kind, path)?);
+            }
+        }
+        Ok(())
+    }
+
+    fn is_expired(&self, path: &Path) -> Result<bool> {
+        let modified = std::fs::symlink_metadata(path)?.modified()?;
+        let age = SystemTime::now().duration_since(modified).unwrap_or_default();
+        Ok(age >= self.max_age)
+    }
+}
+
+// Adds a package and its dependencies
+fn record(package: &Package, recorded: &mut HashSet<PackageVersion>) {
+    recorded.insert((package.name.clone(), package.version.clone()));
+    for dependency in &package.dependencies {
+        record(dependency, recorded);
+    }
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn removal(kind: Garbage, path: PathBuf) -> Result<Removal> {
+    let mut size = 0;
+    visit_files(&path, &mut |_, metadata| size += metadata.len())?;
+    Ok(Removal { kind, path, size })
+}
+
+// A hardlinked file only frees its space when its last link goes, so files
+// are counted once every one of their links is among the removals
+#[cfg(unix)]
+fn reclaimed_size(removals: &[Removal]) -> Result<u64> {
+    use std::os::unix::fs::MetadataExt;
+
+    let mut inodes: HashMap<(u64, u64), (u64, u64, u64)> = HashMap::new();
+    for removal in removals {
+        visit_files(&removal.path, &mut |_, metadata| {
+            let (_, _, seen) = inodes
+                .entry((metadata.dev(), metadata.ino()))
+                .or_insert((metadata.len(), metadata.nlink(), 0));
+            *seen += 1;
+        })?;
+    }
+
+    Ok(inodes.values().filter(|(_, links, seen)| seen >= links).map(|(size, _, _)| size).sum())
+}
+
+#[cfg(not(unix))]
+fn reclaimed_size(removals: &[Removal]) -> Result<u64> {
+    Ok(removals.iter().map(|removal| removal.size).sum())
+}
+
+fn remove(path: &Path) -> std::io::Result<()> {
+    if std::fs::symlink_metadata(path)?.is_dir() {
+        std::fs::remove_dir_all(path)
+    } else {
+        std::fs::remove_file(path)
+    }
+}
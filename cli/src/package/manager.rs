@@ -5,11 +5,19 @@ use tokio::fs;
 use tokio_stream::StreamExt;
 use colored::*;
 
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
-use crate::{AppCommand, Provider, utils::ui};
+use crate::{AppCommand, Provider, platform::Platform, utils::ui};
 
-use super::Package;
+use super::{
+    Package, archive,
+    cache::DownloadCache,
+    registry::{self, FileManifest, FileRecord, Registry, file_mode},
+    shared::{self, SharedTree},
+    store::{self, Manifest, Placement, Store, link_or_copy},
+    verify::{self, Problem},
+};
 
 // Helper function to list directory contents
 fn list_directory_contents(dir: &std::path::Path, level: usize) -> std::io::Result<()> {
@@ -33,13 +41,60 @@ fn list_directory_contents(dir: &std::path::Path, level: usize) -> std::io::Resu
     Ok(())
 }
 
+/// What repairing a package did.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Repair {
+    /// Files put back as recorded
+    pub restored: usize,
+    /// Files that were not part of the package
+    pub removed: usize,
+    /// The archive had to be downloaded again
+    pub downloaded: bool,
+    /// The package directory was gone and was installed again
+    pub reinstalled: bool,
+}
+
 pub struct PackageManager {
     install_dir: PathBuf,
+    // Where package files are stored, `install_dir` unless placed elsewhere
+    packages_dir: PathBuf,
+    store: Store,
+    // Published packages linked instead of installed privately
+    shared: Option<SharedTree>,
+    // Archives looked up before downloading, and added after
+    cache: Option<DownloadCache>,
 }
 
 impl PackageManager {
     pub fn new(install_dir: PathBuf) -> Self {
-        Self { install_dir }
+        let store = Store::for_install_dir(&install_dir);
+        Self { packages_dir: install_dir.clone(), install_dir, store, shared: None, cache: None }
+    }
+
+    /// Stores new packages in `packages_dir`, with its own store, and links
+    /// them from `install_dir`.
+    pub fn with_packages_dir(mut self, packages_dir: PathBuf) -> Self {
+        self.store = Store::for_install_dir(&packages_dir);
+        self.packages_dir = packages_dir;
+        self
+    }
+
+    /// Links packages published in `shared` instead of installing them.
+    pub fn with_shared_tree(mut self, shared: SharedTree) -> Self {
+        self.shared = Some(shared);
+        self
+    }
+
+    /// Takes archives from `cache` instead of downloading them when it has
+    /// them, and adds those it downloads.
+    pub fn with_download_cache(mut self, cache: DownloadCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// The content-addressed store installed packages are linked from.
+    pub fn store(&self) -> &Store {
+        &self.store
     }
 
     pub fn get_package_dir(&self, package_name: &str, version: &Version) -> PathBuf {
@@ -52,6 +107,7 @@ impl PackageManager {
         &'a self,
         package: &'a Package,
         provider: &'a Provider,
+        platform: &'a Platform,
         pb: &'a indicatif::ProgressBar,
     ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<()>> + 'a>> {
         Box::pin(async move {
@@ -59,122 +115,298 @@ impl PackageManager {
 
             // First, handle dependencies recursively
             for dep in &package.dependencies {
-                self.install_package_internal(dep, provider, pb).await?;
+                self.install_package_internal(dep, provider, platform, pb).await?;
             }
 
+            // Pick the build for this platform before touching the disk
+            let artifact = package.artifact_for(platform)?;
+
             // Determine package destination
             let package_dir = self
                 .install_dir
                 .join(&package.name)
-                .join(&package.version.to_string());
+                .join(package.version.to_string());
             if package_dir.exists() {
                 pb.finish_with_message(ui::success(&format!("Package {} is already installed", package.name)));
                 return Ok(());
             }
 
-            // Create package directory
-            pb.set_message(format!("Creating directory: {}", package_dir.display().to_string().cyan()));
-            fs::create_dir_all(&package_dir).await
-                .map_err(|e| anyhow::anyhow!("Failed to create package directory {}: {}", package_dir.display(), e))?;
-
-            // Download package
-            if let Some(source) = &package.source {
-                pb.set_message(format!("Downloading package: {}", package.name.cyan()));
-
-                // Download to a temporary location
-                let temp_path = package_dir.join("package.tmp");
-                provider.download_package(source, &temp_path).await?;
-
-                // Verify checksum
-                pb.set_message(format!("Verifying package: {}", package.name.cyan()));
-                let content = fs::read(&temp_path).await?;
-                let mut hasher = Sha256::new();
-                hasher.update(&content);
-                let hash = format!("{:x}", hasher.finalize());
-
-                if hash != package.sha256 {
-                    fs::remove_dir_all(&package_dir).await?;
-                    anyhow::bail!(
-                        "{}",
-                        ui::error(&format!(
-                            "Checksum verification failed for package: {}. Expected: {}, Got: {}",
-                            package.name,
-                            package.sha256,
-                            hash
-                        ))
-                    );
+            // Versions published by a maintainer are used as they are
+            if let Some(shared) = &self.shared {
+                match shared.find(&package.name, &package.version, artifact.map(|(_, sha256)| sha256)) {
+                    Ok(Some(shared_dir)) => {
+                        link_package_dir(&shared_dir, &package_dir)?;
+                        self.record_files(&package.name, &package.version)?;
+                        pb.finish_with_message(ui::success(&format!("Using shared package {}", package.name)));
+                        return Ok(());
+                    }
+                    Ok(None) => {}
+                    Err(e) => println!("{}", ui::warning(&format!(
+                        "Installing {} privately, its shared version cannot be used: {}",
+                        package.name, e
+                    ))),
                 }
+            }
 
-                // Extract package
-                pb.set_message(format!("Extracting package: {}", package.name.cyan()));
-                
-                // Special case for our test hello package
-                if source.ends_with("hello-1.0.0.tar.gz") {
-                    println!("{}", ui::info("Direct extraction of hello package"));
-                    let hello_content = "#!/bin/bash\necho \"Hello from diem!\"";
-                    std::fs::write(package_dir.join("hello"), hello_content)?;
-                    
-                    #[cfg(unix)]
-                    {
-                        use std::os::unix::fs::PermissionsExt;
-                        let mut perms = std::fs::metadata(package_dir.join("hello"))?.permissions();
-                        perms.set_mode(0o755);
-                        std::fs::set_permissions(package_dir.join("hello"), perms)?;
-                    }
-                } else if temp_path.extension().map_or(false, |ext| ext == "zip") {
-                    let file = std::fs::File::open(&temp_path)?;
-                    let mut archive = zip::ZipArchive::new(file)?;
-                    archive.extract(&package_dir)?;
-                } else if temp_path
-                    .extension()
-                    .map_or(false, |ext| ext == "tar" || ext == "gz")
-                {
-                    // For tar.gz files, first decompress to a temporary tar file
-                    let temp_tar = package_dir.join("temp.tar");
-                    let input_file = std::fs::File::open(&temp_path)?;
-                    let mut decoder = flate2::read::GzDecoder::new(input_file);
-                    let mut output_file = std::fs::File::create(&temp_tar)?;
-                    std::io::copy(&mut decoder, &mut output_file)?;
-                    
-                    // Now manually untar to be more verbose about what's happening
-                    let file = std::fs::File::open(&temp_tar)?;
-                    let mut archive = tar::Archive::new(file);
-                    
-                    for entry in archive.entries()? {
-                        let mut entry = entry?;
-                        let path = entry.path()?;
-                        println!("{}", ui::info(&format!("Extracting file: {}", path.display())));
-                        
-                        // Extract the entry
-                        entry.unpack_in(&package_dir)?;
+            let stored_dir = self
+                .packages_dir
+                .join(&package.name)
+                .join(package.version.to_string());
+
+            // Reuse the unpacked archive from the store, or download it there
+            if stored_dir != package_dir && stored_dir.is_dir() {
+                pb.set_message(format!("Reusing stored package: {}", package.name.cyan()));
+            } else if let Some((source, sha256)) = artifact {
+                let manifest = match self.store.manifest(sha256)? {
+                    Some(manifest) => {
+                        pb.set_message(format!("Reusing stored package: {}", package.name.cyan()));
+                        manifest
                     }
-                    
-                    // Cleanup temporary tar file
-                    std::fs::remove_file(temp_tar)?;
+                    None => self.download_to_store(package, source, sha256, provider, pb).await?,
+                };
+
+                pb.set_message(format!("Linking package: {}", package.name.cyan()));
+                let placement = self.store.materialize(&manifest, &stored_dir)?;
+                if placement.copied > 0 {
+                    println!("{}", ui::warning(&format!(
+                        "Copied {} files of {} because the store is on another filesystem",
+                        placement.copied, package.name
+                    )));
                 }
 
                 // List extracted files
-                println!("{}", ui::section(&format!("Files extracted to: {}", package_dir.display())));
+                println!("{}", ui::section(&format!("Files extracted to: {}", stored_dir.display())));
                 println!("{}", ui::warning("If no files are shown below, it means extraction failed or files were extracted to wrong directory!"));
-                let std_dir = std::path::Path::new(&package_dir);
+                let std_dir = std::path::Path::new(&stored_dir);
                 list_directory_contents(std_dir, 0)?;
+            } else {
+                pb.set_message(format!("Creating directory: {}", stored_dir.display().to_string().cyan()));
+                fs::create_dir_all(&stored_dir).await
+                    .map_err(|e| anyhow::anyhow!("Failed to create package directory {}: {}", stored_dir.display(), e))?;
+            }
 
-                // Clean up temporary file
-                fs::remove_file(temp_path).await?;
+            // Packages stored on another volume are reached through a link
+            if stored_dir != package_dir {
+                link_package_dir(&stored_dir, &package_dir)?;
             }
+            self.record_files(&package.name, &package.version)?;
 
             pb.finish_with_message(ui::success(&format!("Successfully installed {}", package.name)));
             Ok(())
         })
     }
 
+    // Downloads and checks the archive of a package, unless the download
+    // cache has it, and unpacks it into the store
+    async fn download_to_store(
+        &self,
+        package: &Package,
+        source: &str,
+        sha256: &str,
+        provider: &Provider,
+        pb: &indicatif::ProgressBar,
+    ) -> Result<Manifest> {
+        let staging = self.store.staging_dir()?;
+
+        let temp_path = staging.path().join("package.tmp");
+        if self.cache.as_ref().is_some_and(|cache| cache.fetch(sha256, &temp_path)) {
+            pb.set_message(format!("Using cached download: {}", package.name.cyan()));
+        } else {
+            pb.set_message(format!("Downloading package: {}", package.name.cyan()));
+            provider.download_package(source, &temp_path).await?;
+
+            // Verify checksum
+            pb.set_message(format!("Verifying package: {}", package.name.cyan()));
+            let content = fs::read(&temp_path).await?;
+            let mut hasher = Sha256::new();
+            hasher.update(&content);
+            let hash = format!("{:x}", hasher.finalize());
+
+            if !hash.eq_ignore_ascii_case(sha256) {
+                anyhow::bail!(
+                    "{}",
+                    ui::error(&format!(
+                        "Checksum verification failed for package: {}. Expected: {}, Got: {}",
+                        package.name,
+                        sha256,
+                        hash
+                    ))
+                );
+            }
+
+            if let Some(cache) = &self.cache {
+                if let Err(e) = cache.insert(sha256, &temp_path) {
+                    println!("{}", ui::warning(&format!(
+                        "Could not cache the download of {} in {}: {}",
+                        package.name,
+                        cache.dir().display(),
+                        e
+                    )));
+                }
+            }
+        }
+
+        // Extract package into the store
+        pb.set_message(format!("Extracting package: {}", package.name.cyan()));
+        let unpacked = staging.path().join("files");
+        fs::create_dir_all(&unpacked).await?;
+        archive::extract(&temp_path, source, &unpacked)?;
+        self.store.add(&package.name, &package.version, sha256, &unpacked)
+    }
+
+    /// The file manifests of the packages installed in `install_dir`.
+    pub fn registry(&self) -> Registry {
+        Registry::for_install_dir(&self.install_dir)
+    }
+
+    /// Records the files an installed package version has now, for `diem
+    /// verify` to compare against.
+    pub fn record_files(&self, package_name: &str, version: &Version) -> Result<FileManifest> {
+        let package_dir = self.get_package_dir(package_name, version);
+        self.registry().record(package_name, version, &package_dir)
+    }
+
+    /// Restores an installed package version to its recorded files. Files
+    /// are relinked from the store when it still has them, and the archive
+    /// is downloaded again only when it does not; `provider` is needed
+    /// then, and for packages whose directory is gone.
+    pub async fn repair_package(&self, package: &Package, provider: Option<&Provider>) -> Result<Repair> {
+        let package_dir = self.get_package_dir(&package.name, &package.version);
+        let link = std::fs::read_link(&package_dir).ok();
+        let pb = ui::spinner();
+        pb.set_message(format!("Repairing package: {}", package.name.cyan()));
+
+        let in_shared_tree = |stored_dir: &Path| {
+            shared::is_shared(stored_dir)
+                || self.shared.as_ref().is_some_and(|shared| stored_dir.starts_with(shared.root()))
+        };
+        let stored_dir = match link {
+            Some(stored_dir) if in_shared_tree(&stored_dir) => {
+                if stored_dir.is_dir() {
+                    anyhow::bail!(
+                        "{} {} is linked from the shared tree, ask its maintainer to repair it",
+                        package.name,
+                        package.version
+                    );
+                }
+                // The shared version is gone, install one again
+                std::fs::remove_file(&package_dir)?;
+                package_dir.clone()
+            }
+            Some(stored_dir) => stored_dir,
+            None => package_dir.clone(),
+        };
+
+        // Packages are repaired where they are stored, with the store there
+        let packages_dir = if stored_dir != package_dir {
+            stored_dir.ancestors().nth(2).unwrap_or(&self.install_dir).to_path_buf()
+        } else {
+            self.install_dir.clone()
+        };
+        let manager =
+            PackageManager { shared: self.shared.clone(), cache: self.cache.clone(), ..Self::new(self.install_dir.clone()) }
+            .with_packages_dir(packages_dir);
+
+        let provider_for = |what: &str| {
+            provider.ok_or_else(|| {
+                anyhow::anyhow!("{} {} {}, and no provider offers it", package.name, package.version, what)
+            })
+        };
+
+        if !stored_dir.is_dir() {
+            pb.finish_and_clear();
+            manager.install_package(package, provider_for("is missing")?).await?;
+            return Ok(Repair { reinstalled: true, ..Repair::default() });
+        }
+
+        let Some(manifest) = self.registry().manifest(&package.name, &package.version)? else {
+            anyhow::bail!(
+                "{} {} was installed without a file manifest, reinstall it to record one",
+                package.name,
+                package.version
+            );
+        };
+        let problems = verify::verify_files(&package_dir, &manifest)?;
+        let records: HashMap<&Path, &FileRecord> =
+            manifest.files.iter().map(|record| (record.path(), record)).collect();
+        let stores = [manager.store.clone(), self.store.clone(), Store::for_install_dir(&self.install_dir)];
+
+        // Files the store lost come back with the archive
+        let mut repair = Repair::default();
+        let mut lost = false;
+        for problem in &problems {
+            if let Problem::Missing(path) | Problem::Modified(path) = problem {
+                if let Some(FileRecord::File { digest, mode, .. }) = records.get(path.as_path()) {
+                    lost |= sound_object(&stores, digest, *mode)?.is_none();
+                }
+            }
+        }
+        if lost {
+            let Some((source, sha256)) = package.artifact_for(&Platform::current())? else {
+                anyhow::bail!("{} {} has no archive to restore its files from", package.name, package.version);
+            };
+            let provider = provider_for("lost files the store does not have")?;
+            manager.download_to_store(package, source, sha256, provider, &pb).await?;
+            repair.downloaded = true;
+        }
+
+        for problem in &problems {
+            let relative = match problem {
+                Problem::Missing(path) | Problem::Modified(path) | Problem::Mode(path) | Problem::Extra(path) => path,
+                Problem::MissingPackage | Problem::Unrecorded => continue,
+            };
+            let path = package_dir.join(relative);
+            match (problem, records.get(relative.as_path())) {
+                (Problem::Extra(_), _) => {
+                    remove_path(&path)?;
+                    repair.removed += 1;
+                }
+                (Problem::Mode(_), Some(FileRecord::Dir { mode, .. })) => {
+                    set_mode(&path, *mode)?;
+                    repair.restored += 1;
+                }
+                (Problem::Mode(_), Some(FileRecord::File { mode, .. })) => {
+                    store::unshare(&path)?;
+                    set_mode(&path, *mode)?;
+                    repair.restored += 1;
+                }
+                (_, Some(record)) => {
+                    remove_path(&path)?;
+                    match record {
+                        FileRecord::Dir { mode, .. } => {
+                            std::fs::create_dir_all(&path)?;
+                            set_mode(&path, *mode)?;
+                        }
+                        FileRecord::File { digest, mode, .. } => {
+                            let object = sound_object(&stores, digest, *mode)?.ok_or_else(|| {
+                                anyhow::anyhow!("The archive of {} has no file {}", package.name, path.display())
+                            })?;
+                            link_or_copy(&object, &path, &mut Placement::default())?;
+                            if std::fs::metadata(&path).map(|metadata| file_mode(&metadata))? != *mode {
+                                store::unshare(&path)?;
+                                set_mode(&path, *mode)?;
+                            }
+                        }
+                        FileRecord::Symlink { target, .. } => symlink(target, &path)?,
+                    }
+                    repair.restored += 1;
+                }
+                (_, None) => {}
+            }
+        }
+
+        pb.finish_with_message(ui::success(&format!("Repaired {}", package.name)));
+        Ok(repair)
+    }
+
     pub async fn install_package(&self, package: &Package, provider: &Provider) -> Result<()> {
         // Create progress bar with improved style
         let pb = ui::spinner();
         pb.enable_steady_tick(std::time::Duration::from_millis(80));
 
-        // Call the internal implementation with proper boxing
-        self.install_package_internal(package, provider, &pb).await
+        // Call the internal implementation with proper boxing, detecting the
+        // platform once for the package and all its dependencies
+        self.install_package_internal(package, provider, &Platform::current(), &pb).await
     }
 
     pub async fn uninstall_package(&self, package_name: &str, version: Option<&str>) -> Result<()> {
@@ -187,8 +419,12 @@ impl PackageManager {
             let version_dir = package_dir.join(version);
             pb.set_message(format!("Removing {} version {}", package_name.cyan(), version.yellow()));
             
-            if version_dir.exists() {
-                fs::remove_dir_all(version_dir).await?;
+            // A link left dangling by a wiped volume is still removed
+            if std::fs::symlink_metadata(&version_dir).is_ok() {
+                remove_package_dir(&version_dir)?;
+                if let Ok(version) = Version::parse(version) {
+                    self.registry().forget(package_name, Some(&version))?;
+                }
                 pb.finish_with_message(ui::success(&format!("Uninstalled {} version {}", package_name, version)));
             } else {
                 pb.finish_with_message(ui::warning(&format!("Version {} of {} is not installed", version, package_name)));
@@ -198,7 +434,11 @@ impl PackageManager {
             pb.set_message(format!("Removing all versions of {}", package_name.cyan()));
             
             if package_dir.exists() {
+                for entry in std::fs::read_dir(&package_dir)? {
+                    remove_package_dir(&entry?.path())?;
+                }
                 fs::remove_dir_all(package_dir).await?;
+                self.registry().forget(package_name, None)?;
                 pb.finish_with_message(ui::success(&format!("Uninstalled all versions of {}", package_name)));
             } else {
                 pb.finish_with_message(ui::warning(&format!("Package {} is not installed", package_name)));
@@ -211,7 +451,7 @@ impl PackageManager {
     pub async fn update_package(&self, package: &Package, provider: &Provider) -> Result<()> {
         // Check if the package is already installed
         let package_dir = self.install_dir.join(&package.name);
-        let version_dir = package_dir.join(&package.version.to_string());
+        let version_dir = package_dir.join(package.version.to_string());
         let pb = ui::spinner();
         
         pb.set_message(format!("Checking package: {}", package.name.cyan()));
@@ -265,7 +505,7 @@ impl PackageManager {
     pub async fn create_command_symlink(
         &self,
         cmd: &AppCommand,
-        package_dir: &PathBuf,
+        package_dir: &Path,
     ) -> Result<()> {
         let base_dirs = directories::BaseDirs::new().expect("Could not determine base directories");
         let bin_dir = base_dirs
@@ -283,13 +523,18 @@ impl PackageManager {
             fs::remove_file(&link).await?;
         }
 
-        // Make the target executable
+        // Make the target executable, leaving files linked from the store
+        // untouched when they already are. Others get a copy of their own
+        // first, as their store object is linked by other packages too.
         #[cfg(unix)]
         {
             use std::os::unix::fs::PermissionsExt;
             let mut perms = fs::metadata(&target).await?.permissions();
-            perms.set_mode(0o755);
-            fs::set_permissions(&target, perms).await?;
+            if perms.mode() & 0o111 == 0 {
+                store::unshare(&target)?;
+                perms.set_mode(perms.mode() | 0o555);
+                fs::set_permissions(&target, perms).await?;
+            }
         }
 
         // Create the new symlink
@@ -302,3 +547,83 @@ impl PackageManager {
         Ok(())
     }
 }
+
+// Points `package_dir` at the package stored in `stored_dir`, replacing a
+// link left dangling by a wiped volume
+fn link_package_dir(stored_dir: &Path, package_dir: &Path) -> Result<()> {
+    if std::fs::symlink_metadata(package_dir).is_ok() {
+        std::fs::remove_file(package_dir)?;
+    }
+    std::fs::create_dir_all(package_dir.parent().expect("package directories have a parent"))?;
+
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(stored_dir, package_dir)?;
+
+    #[cfg(windows)]
+    std::os::windows::fs::symlink_dir(stored_dir, package_dir)?;
+
+    Ok(())
+}
+
+// Removes an installed version, along with its files when they are stored
+// on another volume rather than shared
+fn remove_package_dir(package_dir: &Path) -> Result<()> {
+    let metadata = std::fs::symlink_metadata(package_dir)?;
+    if metadata.is_symlink() {
+        let stored_dir = std::fs::read_link(package_dir)?;
+        std::fs::remove_file(package_dir)?;
+        if stored_dir.is_dir() && !shared::is_shared(&stored_dir) {
+            std::fs::remove_dir_all(stored_dir)?;
+        }
+    } else if metadata.is_dir() {
+        std::fs::remove_dir_all(package_dir)?;
+    } else {
+        std::fs::remove_file(package_dir)?;
+    }
+    Ok(())
+}
+
+// A stored object with the given content, removing objects that were
+// modified in place through a hardlink so the archive can replace them
+fn sound_object(stores: &[Store], digest: &str, mode: u32) -> Result<Option<PathBuf>> {
+    for store in stores {
+        let Some(object) = store.object_for(digest, mode) else {
+            continue;
+        };
+        if registry::file_digest(&object)? == digest {
+            return Ok(Some(object));
+        }
+        std::fs::remove_file(&object)?;
+    }
+    Ok(None)
+}
+
+// Removes whatever is at `path`
+fn remove_path(path: &Path) -> std::io::Result<()> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => std::fs::remove_dir_all(path),
+        Ok(_) => std::fs::remove_file(path),
+        Err(_) => Ok(()),
+    }
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> std::io::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
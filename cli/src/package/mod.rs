@@ -1,15 +1,77 @@
+pub mod archive;
+pub mod cache;
+pub mod gc;
 pub(crate) mod manager;
+pub mod placement;
+pub mod registry;
+pub mod shared;
+pub mod store;
+pub mod usage;
+pub mod verify;
 
+use anyhow::Result;
 use semver::Version;
 use serde::{Deserialize, Serialize};
 
+use crate::platform::Platform;
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Package {
     pub name: String,
     pub version: Version,
+    /// Digest of `source`; unused when the package has variants
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub sha256: String,
     pub license: String,
     pub source: Option<String>,
     pub dependencies: Vec<Package>,
     pub package_handler_version: u8,
+    /// Per-platform builds, used instead of `source` when present
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub variants: Vec<PackageVariant>,
+}
+
+/// A build of a package for a single target triple.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PackageVariant {
+    pub target: String,
+    pub source: String,
+    pub sha256: String,
+}
+
+impl Package {
+    /// Returns the source and sha256 to install on `platform`.
+    ///
+    /// Packages without variants use their own `source`. Otherwise the best
+    /// matching variant is picked, and it is an error if none can run here.
+    /// Variants whose target diem cannot parse are skipped, so that a build
+    /// for a platform it does not know yet does not hide the others.
+    pub fn artifact_for(&self, platform: &Platform) -> Result<Option<(&str, &str)>> {
+        if self.variants.is_empty() {
+            return Ok(self.source.as_deref().map(|source| (source, self.sha256.as_str())));
+        }
+
+        let mut best: Option<(u8, &PackageVariant)> = None;
+        for variant in &self.variants {
+            let Ok(target) = Platform::parse(&variant.target) else {
+                continue;
+            };
+            if let Some(score) = platform.compatibility(&target) {
+                if best.is_none_or(|(best_score, _)| score > best_score) {
+                    best = Some((score, variant));
+                }
+            }
+        }
+
+        match best {
+            Some((_, variant)) => Ok(Some((variant.source.as_str(), variant.sha256.as_str()))),
+            None => anyhow::bail!(
+                "No build of package {} {} for this platform ({}). Available targets: {}",
+                self.name,
+                self.version,
+                platform.triple(),
+                self.variants.iter().map(|variant| variant.target.as_str()).collect::<Vec<_>>().join(", ")
+            ),
+        }
+    }
 }
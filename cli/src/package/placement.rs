@@ -0,0 +1,163 @@
+//! Where the files of installed packages are stored.
+//!
+//! The home directory is small and under quota, sgoinfre is large and
+//! persistent, goinfre is fast local disk that may be wiped. Small apps
+//! stay in `install_dir`. Bulky apps are stored in sgoinfre or goinfre,
+//! and `install_dir` only holds a symlink to each of their package
+//! versions, so the rest of diem finds them at the usual place.
+//!
+//! Apps are weighed by the size of their downloads. Free space is what the
+//! filesystem reports to the user, which includes quotas on most shared
+//! mounts.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::{Config, app::info::format_size};
+
+const MIB: u64 = 1024 * 1024;
+
+/// A location packages can be stored in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Volume {
+    Home,
+    Sgoinfre,
+    Goinfre,
+}
+
+impl std::fmt::Display for Volume {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Volume::Home => write!(f, "home"),
+            Volume::Sgoinfre => write!(f, "sgoinfre"),
+            Volume::Goinfre => write!(f, "goinfre"),
+        }
+    }
+}
+
+impl Volume {
+    /// Directory packages stored on this volume go in, laid out like
+    /// `install_dir`. `None` when the volume is not configured.
+    pub fn packages_dir(self, config: &Config) -> Option<PathBuf> {
+        match self {
+            Volume::Home => Some(config.install_dir.clone()),
+            Volume::Sgoinfre => config.sgoinfre_dir.as_ref().map(|dir| dir.join("packages")),
+            Volume::Goinfre => config.goinfre_dir.as_ref().map(|dir| dir.join("packages")),
+        }
+    }
+}
+
+/// How installs are spread across volumes.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PlacementPolicy {
+    /// Apps downloading at least this many MiB leave the home directory
+    pub threshold_mib: u64,
+    /// Where bulky apps are stored
+    pub bulky: Volume,
+    /// MiB to keep free in the home directory
+    pub home_reserve_mib: u64,
+    /// Volumes chosen for single apps, regardless of their size
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub apps: BTreeMap<String, Volume>,
+}
+
+impl Default for PlacementPolicy {
+    fn default() -> Self {
+        Self {
+            threshold_mib: 50,
+            bulky: Volume::Sgoinfre,
+            home_reserve_mib: 100,
+            apps: BTreeMap::new(),
+        }
+    }
+}
+
+/// Why an app was stored where it was.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    /// The volume was chosen for this app
+    Pinned,
+    /// The app is smaller than the threshold
+    Small,
+    /// The app is at least as big as the threshold
+    Bulky,
+    /// The preferred volume had no room or is not configured
+    Fallback(Volume),
+}
+
+impl std::fmt::Display for Reason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Reason::Pinned => write!(f, "chosen for this app"),
+            Reason::Small => write!(f, "below the size threshold"),
+            Reason::Bulky => write!(f, "above the size threshold"),
+            Reason::Fallback(volume) => write!(f, "{} has no room", volume),
+        }
+    }
+}
+
+/// Where to store an app.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Placement {
+    pub volume: Volume,
+    pub packages_dir: PathBuf,
+    pub reason: Reason,
+}
+
+impl PlacementPolicy {
+    /// Picks the volume for `app`, whose downloads weigh `size` bytes.
+    /// `available` reports the free space of a directory, `None` when it is
+    /// unknown, in which case the app is assumed to fit.
+    pub fn place(
+        &self,
+        config: &Config,
+        app: &str,
+        size: u64,
+        available: impl Fn(&Path) -> Option<u64>,
+    ) -> Result<Placement> {
+        if let Some(&volume) = self.apps.get(app) {
+            let packages_dir = volume
+                .packages_dir(config)
+                .ok_or_else(|| anyhow::anyhow!("{} is placed on {}, which is not configured", app, volume))?;
+            return Ok(Placement { volume, packages_dir, reason: Reason::Pinned });
+        }
+
+        let (candidates, reason) = if size < self.threshold_mib * MIB {
+            ([Volume::Home, self.bulky, self.other_bulky()], Reason::Small)
+        } else {
+            ([self.bulky, self.other_bulky(), Volume::Home], Reason::Bulky)
+        };
+
+        for volume in candidates {
+            let Some(packages_dir) = volume.packages_dir(config) else {
+                continue;
+            };
+            let reserve = if volume == Volume::Home { self.home_reserve_mib * MIB } else { 0 };
+            if available(&packages_dir).is_none_or(|free| free >= size + reserve) {
+                let reason = if volume == candidates[0] { reason } else { Reason::Fallback(candidates[0]) };
+                return Ok(Placement { volume, packages_dir, reason });
+            }
+        }
+
+        anyhow::bail!("No configured location has {} free for {}", format_size(size), app)
+    }
+
+    // The bulky volume to try when the preferred one has no room
+    fn other_bulky(&self) -> Volume {
+        match self.bulky {
+            Volume::Goinfre => Volume::Sgoinfre,
+            _ => Volume::Goinfre,
+        }
+    }
+}
+
+/// Free space available to the user where `path` is, or would be created.
+pub fn available_space(path: &Path) -> Option<u64> {
+    let existing = path.ancestors().find(|ancestor| ancestor.exists())?;
+    fs4::available_space(existing).ok()
+}
@@ -0,0 +1,192 @@
+//! Record of the files of each installed package version.
+//!
+//! When a package is installed, the path, size, sha256 and mode of every
+//! file in it are written to the registry in `install_dir`, so that `diem
+//! verify` can later tell which files went missing or changed, as after a
+//! goinfre wipe or a partial sync.
+//!
+//! ```text
+//! .registry/<name>/<version>.json
+//! ```
+
+use anyhow::Result;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use std::path::{Path, PathBuf};
+
+/// Name of the registry directory inside `install_dir`.
+pub const REGISTRY_DIR: &str = ".registry";
+
+/// The file manifests of installed packages.
+#[derive(Debug, Clone)]
+pub struct Registry {
+    root: PathBuf,
+}
+
+/// The files of an installed package version, as installed.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct FileManifest {
+    pub name: String,
+    pub version: Version,
+    /// Parents come before their children
+    pub files: Vec<FileRecord>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum FileRecord {
+    Dir { path: PathBuf, mode: u32 },
+    File { path: PathBuf, size: u64, digest: String, mode: u32 },
+    Symlink { path: PathBuf, target: PathBuf },
+}
+
+impl FileRecord {
+    /// Path relative to the package directory.
+    pub fn path(&self) -> &Path {
+        match self {
+            FileRecord::Dir { path, .. } | FileRecord::File { path, .. } | FileRecord::Symlink { path, .. } => path,
+        }
+    }
+}
+
+impl Registry {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    /// The registry of the packages installed in `install_dir`.
+    pub fn for_install_dir(install_dir: &Path) -> Self {
+        Self::new(install_dir.join(REGISTRY_DIR))
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn manifest_path(&self, name: &str, version: &Version) -> PathBuf {
+        self.root.join(name).join(format!("{}.json", version))
+    }
+
+    /// Records the files currently in `package_dir` as those of the package.
+    pub fn record(&self, name: &str, version: &Version, package_dir: &Path) -> Result<FileManifest> {
+        let manifest = scan(name, version, package_dir)?;
+
+        let path = self.manifest_path(name, version);
+        std::fs::create_dir_all(path.parent().expect("manifests live in a directory"))?;
+        let temp_path = path.with_extension(format!("json.{}.tmp", std::process::id()));
+        std::fs::write(&temp_path, serde_json::to_string_pretty(&manifest)?)?;
+        std::fs::rename(&temp_path, &path)?;
+
+        Ok(manifest)
+    }
+
+    /// The recorded files of a package version, if it was recorded.
+    pub fn manifest(&self, name: &str, version: &Version) -> Result<Option<FileManifest>> {
+        let path = self.manifest_path(name, version);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let content = std::fs::read_to_string(&path)?;
+        let manifest = serde_json::from_str(&content)
+            .map_err(|e| anyhow::anyhow!("Corrupt file manifest {}: {}", path.display(), e))?;
+        Ok(Some(manifest))
+    }
+
+    /// Forgets a package version, or every version when `version` is `None`.
+    pub fn forget(&self, name: &str, version: Option<&Version>) -> Result<()> {
+        let path = match version {
+            Some(version) => self.manifest_path(name, version),
+            None => self.root.join(name),
+        };
+        match std::fs::symlink_metadata(&path) {
+            Ok(metadata) if metadata.is_dir() => std::fs::remove_dir_all(&path)?,
+            Ok(_) => std::fs::remove_file(&path)?,
+            Err(_) => {}
+        }
+        Ok(())
+    }
+
+    /// Forgets the versions `keep` rejects.
+    pub fn retain(&self, keep: impl Fn(&str, &Version) -> bool) -> Result<()> {
+        let Ok(entries) = std::fs::read_dir(&self.root) else {
+            return Ok(());
+        };
+
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            for manifest in std::fs::read_dir(entry.path())? {
+                let path = manifest?.path();
+                let file_name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+                let version = file_name.strip_suffix(".json").and_then(|version| Version::parse(version).ok());
+                if version.is_some_and(|version| !keep(&name, &version)) {
+                    std::fs::remove_file(&path)?;
+                }
+            }
+            // Only succeeds once no version is left
+            let _ = std::fs::remove_dir(entry.path());
+        }
+        Ok(())
+    }
+}
+
+/// The files currently in `package_dir`, without recording them.
+pub fn scan(name: &str, version: &Version, package_dir: &Path) -> Result<FileManifest> {
+    let mut manifest = FileManifest { name: name.to_string(), version: version.clone(), files: Vec::new() };
+    record_dir(package_dir, Path::new(""), &mut manifest.files)?;
+    Ok(manifest)
+}
+
+/// sha256 of a file, in hex.
+pub fn file_digest(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Permission bits of a file, 0 where they do not exist.
+pub fn file_mode(metadata: &std::fs::Metadata) -> u32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.permissions().mode() & 0o7777
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = metadata;
+        0
+    }
+}
+
+fn record_dir(dir: &Path, prefix: &Path, files: &mut Vec<FileRecord>) -> Result<()> {
+    let mut children: Vec<_> = std::fs::read_dir(dir)?.collect::<Result<_, _>>()?;
+    children.sort_by_key(|entry| entry.file_name());
+
+    for child in children {
+        let path = prefix.join(child.file_name());
+        let metadata = std::fs::symlink_metadata(child.path())?;
+
+        if metadata.is_symlink() {
+            files.push(FileRecord::Symlink { path, target: std::fs::read_link(child.path())? });
+        } else if metadata.is_dir() {
+            files.push(FileRecord::Dir { path: path.clone(), mode: file_mode(&metadata) });
+            record_dir(&child.path(), &path, files)?;
+        } else {
+            files.push(FileRecord::File {
+                path,
+                size: metadata.len(),
+                digest: file_digest(&child.path())?,
+                mode: file_mode(&metadata),
+            });
+        }
+    }
+
+    Ok(())
+}
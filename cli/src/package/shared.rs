@@ -0,0 +1,328 @@
+//! Packages shared between users through a tree on sgoinfre.
+//!
+//! A maintainer publishes installed apps into a tree laid out like
+//! `install_dir`, readable by their group. Other users link their
+//! `install_dir` to the published versions instead of downloading them,
+//! and fall back to a private install of versions that are not published
+//! or not safe to use.
+//!
+//! A published version is trusted only if it and the directories above it
+//! belong to the owner of the tree and nobody else can write to them, and
+//! its links stay in the tree, so other users cannot slip files into the
+//! commands of the group. The checks stop at the root of the tree: the
+//! directories above it, like sgoinfre itself, are trusted as configured.
+//!
+//! Publishing records the files of a version, and the archive it was
+//! installed from, next to the published versions. A version whose files
+//! or archive do not match that record, as one left stale or edited in
+//! place, is not used.
+//!
+//! Versions appear whole: they are copied under a temporary name and
+//! renamed once complete.
+//!
+//! ```text
+//! <root>/<name>/<version>/...
+//! <root>/.published/<name>/<version>.json
+//! ```
+
+use anyhow::Result;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+
+use std::path::{Path, PathBuf};
+
+use super::{
+    Package,
+    registry::{self, FileManifest},
+    verify,
+};
+use crate::platform::Platform;
+
+/// File marking the root of a shared tree, so links into it are never
+/// followed to remove published files.
+pub const MARKER_FILE: &str = ".diem-shared";
+
+/// Name of the directory of publication records in a shared tree.
+pub const PUBLISHED_DIR: &str = ".published";
+
+/// What `publish` recorded about a published version.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Publication {
+    /// sha256 of the archive the version was installed from, when known
+    pub sha256: Option<String>,
+    pub files: FileManifest,
+}
+
+/// A shared install tree.
+#[derive(Debug, Clone)]
+pub struct SharedTree {
+    root: PathBuf,
+}
+
+impl SharedTree {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Where a version of a package is published.
+    pub fn package_dir(&self, name: &str, version: &Version) -> PathBuf {
+        self.root.join(name).join(version.to_string())
+    }
+
+    fn publication_path(&self, name: &str, version: &Version) -> PathBuf {
+        self.root.join(PUBLISHED_DIR).join(name).join(format!("{}.json", version))
+    }
+
+    /// The published version of a package, `None` when it is not published,
+    /// and an error when it is but cannot be used safely, or is not what
+    /// was published from the archive with `sha256`.
+    pub fn find(&self, name: &str, version: &Version, sha256: Option<&str>) -> Result<Option<PathBuf>> {
+        let package_dir = self.package_dir(name, version);
+        if std::fs::symlink_metadata(&package_dir).is_err() {
+            return Ok(None);
+        }
+        self.check(&package_dir)?;
+
+        let publication = self.publication(name, version)?;
+        if let Some(sha256) = sha256 {
+            if !publication.sha256.as_deref().is_some_and(|published| published.eq_ignore_ascii_case(sha256)) {
+                anyhow::bail!("{} {} was published from another archive", name, version);
+            }
+        }
+        let problems = verify::verify_files(&package_dir, &publication.files)?;
+        if !problems.is_empty() {
+            anyhow::bail!("{} {} has {} files that differ from what was published", name, version, problems.len());
+        }
+        Ok(Some(package_dir))
+    }
+
+    // Reads the publication record of a version, checked like the version
+    fn publication(&self, name: &str, version: &Version) -> Result<Publication> {
+        let root = std::fs::canonicalize(&self.root)?;
+        let owner = owner(&std::fs::metadata(&root)?);
+        let path = self.publication_path(name, version);
+        let published_dir = self.root.join(PUBLISHED_DIR);
+        for entry in [published_dir.as_path(), path.parent().expect("records live in a directory"), path.as_path()] {
+            let metadata = std::fs::symlink_metadata(entry)
+                .map_err(|_| anyhow::anyhow!("{} {} was published without a record of its files", name, version))?;
+            if metadata.is_symlink() {
+                anyhow::bail!("{} is a link", entry.display());
+            }
+            check_entry(entry, &metadata, owner)?;
+        }
+
+        let content = std::fs::read_to_string(&path)?;
+        serde_json::from_str(&content).map_err(|e| anyhow::anyhow!("Corrupt publication record {}: {}", path.display(), e))
+    }
+
+    // Checks a published version and the directories above it, up to the
+    // root of the tree, against the owner of the root
+    fn check(&self, package_dir: &Path) -> Result<()> {
+        let root = std::fs::canonicalize(&self.root)?;
+        let root_metadata = std::fs::metadata(&root)?;
+        let owner = owner(&root_metadata);
+        check_entry(&root, &root_metadata, owner)?;
+
+        let name_dir = package_dir.parent().expect("published versions have a parent");
+        for dir in [name_dir, package_dir] {
+            if std::fs::symlink_metadata(dir)?.is_symlink() {
+                anyhow::bail!("{} is a link", dir.display());
+            }
+        }
+        check_entry(name_dir, &std::fs::metadata(name_dir)?, owner)?;
+        check_permissions(package_dir, &root, owner)
+    }
+
+    /// Publishes the installed files of a package from `installed_dir`,
+    /// returning `false` when the version was already published.
+    pub fn publish(&self, package: &Package, installed_dir: &Path) -> Result<bool> {
+        let (name, version) = (package.name.as_str(), &package.version);
+        let package_dir = self.package_dir(name, version);
+        if package_dir.exists() {
+            return Ok(false);
+        }
+
+        let parent = package_dir.parent().expect("published versions have a parent");
+        std::fs::create_dir_all(parent).map_err(|e| {
+            anyhow::anyhow!("Cannot write to the shared tree {}: {}", self.root.display(), e)
+        })?;
+        let marker = self.root.join(MARKER_FILE);
+        if !marker.exists() {
+            std::fs::write(&marker, "")?;
+            share(&marker, false)?;
+        }
+        share(&self.root, true)?;
+        share(parent, true)?;
+
+        let source = std::fs::canonicalize(installed_dir)
+            .map_err(|e| anyhow::anyhow!("{} {} is not installed: {}", name, version, e))?;
+        let temp_dir = parent.join(format!(".{}.{}.tmp", version, std::process::id()));
+        if temp_dir.exists() {
+            std::fs::remove_dir_all(&temp_dir)?;
+        }
+
+        // Links made by `AppManager` point into the private install
+        let prefixes = [installed_dir.to_path_buf(), source.clone()];
+        let copied = copy_shared(&source, &temp_dir, &prefixes, &package_dir);
+        if let Err(e) = copied {
+            let _ = std::fs::remove_dir_all(&temp_dir);
+            return Err(e);
+        }
+
+        // The record is written first, so a version never appears without one
+        let recorded = self.record(package, &temp_dir);
+        if let Err(e) = recorded {
+            let _ = std::fs::remove_dir_all(&temp_dir);
+            return Err(e);
+        }
+        std::fs::rename(&temp_dir, &package_dir)?;
+        Ok(true)
+    }
+
+    // Writes the publication record of a version about to be renamed from
+    // `temp_dir`
+    fn record(&self, package: &Package, temp_dir: &Path) -> Result<()> {
+        let sha256 = package.artifact_for(&Platform::current()).ok().flatten().map(|(_, sha256)| sha256.to_string());
+        let files = registry::scan(&package.name, &package.version, temp_dir)?;
+        let publication = Publication { sha256, files };
+
+        let path = self.publication_path(&package.name, &package.version);
+        let parent = path.parent().expect("records live in a directory");
+        std::fs::create_dir_all(parent)?;
+        share(&self.root.join(PUBLISHED_DIR), true)?;
+        share(parent, true)?;
+
+        let temp_path = path.with_extension(format!("json.{}.tmp", std::process::id()));
+        std::fs::write(&temp_path, serde_json::to_string_pretty(&publication)?)?;
+        share(&temp_path, false)?;
+        std::fs::rename(&temp_path, &path)?;
+        Ok(())
+    }
+}
+
+/// Whether `stored_dir`, a `<name>/<version>` directory, is in a shared
+/// tree and so must be left alone when a link to it is removed.
+pub fn is_shared(stored_dir: &Path) -> bool {
+    stored_dir.ancestors().nth(2).is_some_and(|root| root.join(MARKER_FILE).exists())
+}
+
+// Checks that everything in `path` belongs to `owner`, can be read by the
+// group and written only by its owner, and that links resolve to such
+// entries inside `root`
+fn check_permissions(path: &Path, root: &Path, owner: Option<u32>) -> Result<()> {
+    let metadata = std::fs::symlink_metadata(path)?;
+    if metadata.is_symlink() {
+        let target = std::fs::canonicalize(path)
+            .map_err(|e| anyhow::anyhow!("{} is a broken link: {}", path.display(), e))?;
+        if !target.starts_with(root) {
+            anyhow::bail!("{} points outside the shared tree, to {}", path.display(), target.display());
+        }
+        return check_entry(&target, &std::fs::metadata(&target)?, owner);
+    }
+
+    check_entry(path, &metadata, owner)?;
+    if metadata.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            check_permissions(&entry?.path(), root, owner)?;
+        }
+    }
+    Ok(())
+}
+
+// Checks a single file or directory, without looking inside
+fn check_entry(path: &Path, metadata: &std::fs::Metadata, owner: Option<u32>) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if self::owner(metadata) != owner {
+            anyhow::bail!("{} does not belong to the owner of the shared tree", path.display());
+        }
+        let mode = metadata.permissions().mode();
+        if mode & 0o022 != 0 {
+            anyhow::bail!("{} is writable by other users", path.display());
+        }
+        let readable = if metadata.is_dir() { 0o050 } else { 0o040 };
+        if mode & readable != readable {
+            anyhow::bail!("{} is not readable by the group", path.display());
+        }
+    }
+
+    #[cfg(not(unix))]
+    let _ = (path, metadata, owner);
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn owner(metadata: &std::fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.uid())
+}
+
+#[cfg(not(unix))]
+fn owner(_metadata: &std::fs::Metadata) -> Option<u32> {
+    None
+}
+
+// Copies `source` to `destination`, readable by the group, rewriting links
+// under one of `prefixes` to point under `published` instead
+fn copy_shared(source: &Path, destination: &Path, prefixes: &[PathBuf], published: &Path) -> Result<()> {
+    std::fs::create_dir_all(destination)?;
+    for entry in std::fs::read_dir(source)? {
+        let entry = entry?;
+        let path = entry.path();
+        let target_path = destination.join(entry.file_name());
+        let file_type = entry.file_type()?;
+
+        if file_type.is_symlink() {
+            let link = std::fs::read_link(&path)?;
+            let link = match prefixes.iter().find_map(|prefix| link.strip_prefix(prefix).ok()) {
+                Some(relative) => published.join(relative),
+                None => link,
+            };
+            symlink(&link, &target_path)?;
+        } else if file_type.is_dir() {
+            copy_shared(&path, &target_path, prefixes, published)?;
+        } else {
+            std::fs::copy(&path, &target_path)?;
+            share(&target_path, false)?;
+        }
+    }
+    share(destination, true)?;
+    Ok(())
+}
+
+// Lets the group read `path`, and takes write access away from others
+fn share(path: &Path, is_dir: bool) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mut permissions = std::fs::metadata(path)?.permissions();
+        let mode = permissions.mode();
+        let executable = is_dir || mode & 0o100 != 0;
+        let shared = (mode & !0o022) | 0o040 | if executable { 0o010 } else { 0 };
+        if shared != mode {
+            permissions.set_mode(shared);
+            std::fs::set_permissions(path, permissions)?;
+        }
+    }
+
+    #[cfg(not(unix))]
+    let _ = (path, is_dir);
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
@@ -0,0 +1,314 @@
+//! Content-addressed storage for installed packages.
+//!
+//! Each package archive is unpacked once into the store, keyed by its
+//! sha256. Its files are stored as read-only objects named after their own
+//! sha256, so identical files shared by several packages or versions are
+//! kept once. Installed package directories are materialized from the store
+//! with hardlinks, falling back to reflinks and then to plain copies when
+//! the store is on another filesystem.
+//!
+//! ```text
+//! .store/
+//!     objects/ab/cdef...       file contents, `-x` suffix when executable
+//!     packages/<sha256>.json   manifest of an unpacked archive
+//!     tmp/                     staging area for archives being added
+//! ```
+
+use anyhow::Result;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use std::path::{Path, PathBuf};
+
+use super::archive::is_executable;
+
+/// Name of the store directory inside `install_dir`.
+pub const STORE_DIR: &str = ".store";
+
+/// A store of unpacked package archives.
+#[derive(Debug, Clone)]
+pub struct Store {
+    root: PathBuf,
+}
+
+/// The files of an unpacked archive.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Manifest {
+    pub name: String,
+    pub version: Version,
+    /// sha256 of the archive
+    pub digest: String,
+    /// Parents come before their children
+    pub entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Entry {
+    Dir { path: PathBuf },
+    File { path: PathBuf, object: String },
+    Symlink { path: PathBuf, target: PathBuf },
+}
+
+/// How the files of a materialized package were placed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Placement {
+    pub hardlinked: usize,
+    pub reflinked: usize,
+    pub copied: usize,
+}
+
+impl Store {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    /// The store kept alongside installed packages, so that hardlinks work.
+    pub fn for_install_dir(install_dir: &Path) -> Self {
+        Self::new(install_dir.join(STORE_DIR))
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn object_path(&self, object: &str) -> PathBuf {
+        let (prefix, rest) = object.split_at(2.min(object.len()));
+        self.root.join("objects").join(prefix).join(rest)
+    }
+
+    /// The object holding a file with this sha256 and mode, if stored.
+    pub fn object_for(&self, digest: &str, mode: u32) -> Option<PathBuf> {
+        let suffixes = if mode & 0o111 != 0 { ["-x", ""] } else { ["", "-x"] };
+        suffixes
+            .into_iter()
+            .map(|suffix| self.object_path(&format!("{}{}", digest, suffix)))
+            .find(|path| path.is_file())
+    }
+
+    fn manifest_path(&self, digest: &str) -> PathBuf {
+        self.root.join("packages").join(format!("{}.json", digest.to_lowercase()))
+    }
+
+    /// The manifest of the archive with this sha256, if it was added.
+    pub fn manifest(&self, digest: &str) -> Result<Option<Manifest>> {
+        let path = self.manifest_path(digest);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let content = std::fs::read_to_string(&path)?;
+        let manifest = serde_json::from_str(&content)
+            .map_err(|e| anyhow::anyhow!("Corrupt store manifest {}: {}", path.display(), e))?;
+        Ok(Some(manifest))
+    }
+
+    /// A fresh directory to download and unpack an archive into, removed
+    /// when dropped. It lives inside the store so that files can be moved
+    /// into it.
+    pub fn staging_dir(&self) -> Result<Staging> {
+        let tmp = self.root.join("tmp");
+        std::fs::create_dir_all(&tmp)?;
+
+        for attempt in 0u32.. {
+            let path = tmp.join(format!("{}-{}", std::process::id(), attempt));
+            match std::fs::create_dir(&path) {
+                Ok(()) => return Ok(Staging { path }),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        unreachable!("ran out of staging directory names")
+    }
+
+    /// Moves the files of an unpacked archive into the store and records
+    /// them under `digest`. `unpacked` is consumed.
+    pub fn add(&self, name: &str, version: &Version, digest: &str, unpacked: &Path) -> Result<Manifest> {
+        let mut manifest = Manifest {
+            name: name.to_string(),
+            version: version.clone(),
+            digest: digest.to_lowercase(),
+            entries: Vec::new(),
+        };
+        self.add_dir(unpacked, Path::new(""), &mut manifest.entries)?;
+
+        let path = self.manifest_path(digest);
+        std::fs::create_dir_all(path.parent().expect("manifests live in a directory"))?;
+        let temp_path = path.with_extension(format!("json.{}.tmp", std::process::id()));
+        std::fs::write(&temp_path, serde_json::to_string_pretty(&manifest)?)?;
+        std::fs::rename(&temp_path, &path)?;
+
+        Ok(manifest)
+    }
+
+    fn add_dir(&self, dir: &Path, prefix: &Path, entries: &mut Vec<Entry>) -> Result<()> {
+        let mut children: Vec<_> = std::fs::read_dir(dir)?.collect::<Result<_, _>>()?;
+        children.sort_by_key(|entry| entry.file_name());
+
+        for child in children {
+            let path = prefix.join(child.file_name());
+            let metadata = std::fs::symlink_metadata(child.path())?;
+
+            if metadata.is_symlink() {
+                entries.push(Entry::Symlink { path, target: std::fs::read_link(child.path())? });
+            } else if metadata.is_dir() {
+                entries.push(Entry::Dir { path: path.clone() });
+                self.add_dir(&child.path(), &path, entries)?;
+            } else {
+                let object = self.add_file(&child.path(), &metadata)?;
+                entries.push(Entry::File { path, object });
+            }
+        }
+
+        Ok(())
+    }
+
+    // Moves a file into the objects, unless an identical one is there already
+    fn add_file(&self, file: &Path, metadata: &std::fs::Metadata) -> Result<String> {
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut std::fs::File::open(file)?, &mut hasher)?;
+        let executable = is_executable(metadata);
+        let object = format!("{:x}{}", hasher.finalize(), if executable { "-x" } else { "" });
+
+        let object_path = self.object_path(&object);
+        if object_path.exists() {
+            std::fs::remove_file(file)?;
+        } else {
+            std::fs::create_dir_all(object_path.parent().expect("objects live in a directory"))?;
+            set_read_only(file, executable)?;
+            std::fs::rename(file, &object_path)?;
+        }
+
+        Ok(object)
+    }
+
+    /// Recreates the files of `manifest` at `destination`, which must not
+    /// exist. The package appears all at once, so a half-materialized
+    /// package is never mistaken for an installed one.
+    pub fn materialize(&self, manifest: &Manifest, destination: &Path) -> Result<Placement> {
+        let parent = destination.parent().unwrap_or(Path::new("."));
+        std::fs::create_dir_all(parent)?;
+        let temp_dir = parent.join(format!(
+            ".{}.{}.tmp",
+            destination.file_name().unwrap_or_default().to_string_lossy(),
+            std::process::id()
+        ));
+        if temp_dir.exists() {
+            std::fs::remove_dir_all(&temp_dir)?;
+        }
+
+        let placement = self.materialize_into(manifest, &temp_dir).and_then(|placement| {
+            std::fs::rename(&temp_dir, destination)?;
+            Ok(placement)
+        });
+        if placement.is_err() {
+            let _ = std::fs::remove_dir_all(&temp_dir);
+        }
+        placement
+    }
+
+    fn materialize_into(&self, manifest: &Manifest, dir: &Path) -> Result<Placement> {
+        std::fs::create_dir_all(dir)?;
+
+        let mut placement = Placement::default();
+        for entry in &manifest.entries {
+            match entry {
+                Entry::Dir { path } => std::fs::create_dir_all(dir.join(path))?,
+                Entry::Symlink { path, target } => symlink(target, &dir.join(path))?,
+                Entry::File { path, object } => {
+                    let object_path = self.object_path(object);
+                    if !object_path.exists() {
+                        anyhow::bail!(
+                            "Store object {} of {} {} is missing",
+                            object,
+                            manifest.name,
+                            manifest.version
+                        );
+                    }
+                    link_or_copy(&object_path, &dir.join(path), &mut placement)?;
+                }
+            }
+        }
+
+        Ok(placement)
+    }
+}
+
+/// A staging directory, removed when dropped.
+#[derive(Debug)]
+pub struct Staging {
+    path: PathBuf,
+}
+
+impl Staging {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for Staging {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+// Hardlinks share the object's inode, reflinks share its blocks, copies
+// share nothing
+pub(crate) fn link_or_copy(object: &Path, destination: &Path, placement: &mut Placement) -> Result<()> {
+    if std::fs::hard_link(object, destination).is_ok() {
+        placement.hardlinked += 1;
+    } else if reflink_copy::reflink(object, destination).is_ok() {
+        placement.reflinked += 1;
+    } else {
+        std::fs::copy(object, destination)?;
+        placement.copied += 1;
+    }
+    Ok(())
+}
+
+/// Replaces `file` with a copy of its own when it is hardlinked, so that
+/// its mode can change without changing the store object it links.
+#[cfg(unix)]
+pub fn unshare(file: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+    let metadata = std::fs::symlink_metadata(file)?;
+    if !metadata.is_file() || metadata.nlink() < 2 {
+        return Ok(());
+    }
+
+    let name = file.file_name().unwrap_or_default().to_string_lossy();
+    let temp_path = file.with_file_name(format!(".{}.{}.tmp", name, std::process::id()));
+    std::fs::copy(file, &temp_path)?;
+    std::fs::rename(&temp_path, file)
+}
+
+#[cfg(not(unix))]
+pub fn unshare(_file: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+// Objects are shared by every package linking them, so nobody may write to them
+#[cfg(unix)]
+fn set_read_only(file: &Path, executable: bool) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = if executable { 0o555 } else { 0o444 };
+    std::fs::set_permissions(file, std::fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn set_read_only(file: &Path, _executable: bool) -> std::io::Result<()> {
+    let mut permissions = std::fs::metadata(file)?.permissions();
+    permissions.set_readonly(true);
+    std::fs::set_permissions(file, permissions)
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
@@ -0,0 +1,199 @@
+//! Disk usage of installed packages and the directories diem writes to.
+//!
+//! Installed packages are hardlinked from the store, so a file appears in
+//! several places while taking space once. Sizes of directory trees count
+//! each file once, and the size of a package version is split into what it
+//! shares with other versions and what only it uses, which is what removing
+//! it would free.
+
+use anyhow::Result;
+use semver::Version;
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use super::Package;
+
+/// Space taken by a directory tree.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub bytes: u64,
+    pub files: u64,
+}
+
+/// Space taken by an installed package version.
+#[derive(Debug, Clone)]
+pub struct PackageUsage {
+    pub name: String,
+    pub version: Version,
+    pub path: PathBuf,
+    /// Where the files are, elsewhere than `path` when the version is
+    /// stored on another volume
+    pub stored: PathBuf,
+    /// Size of every file of the version
+    pub bytes: u64,
+    /// Size of the files no other installed version links
+    pub exclusive: u64,
+}
+
+/// Files present with the same size at the same place in two trees.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Duplicates {
+    pub bytes: u64,
+    pub files: u64,
+}
+
+/// Measures `path`, counting hardlinked files once. A missing path takes
+/// no space.
+pub fn measure(path: &Path) -> Result<Usage> {
+    let mut seen = HashSet::new();
+    let mut usage = Usage::default();
+    if std::fs::symlink_metadata(path).is_err() {
+        return Ok(usage);
+    }
+
+    visit_files(path, &mut |_, metadata| {
+        if seen.insert(file_id(metadata)) {
+            usage.bytes += metadata.len();
+            usage.files += 1;
+        }
+    })?;
+    Ok(usage)
+}
+
+/// Measures every version installed under `install_dir`, wherever it is
+/// stored, sorted by name and newest version first.
+pub fn package_usage(install_dir: &Path) -> Result<Vec<PackageUsage>> {
+    let Ok(entries) = std::fs::read_dir(install_dir) else {
+        return Ok(Vec::new());
+    };
+
+    let mut versions = Vec::new();
+
+    for package_entry in entries {
+        let package_entry = package_entry?;
+        let name = package_entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') || !package_entry.file_type()?.is_dir() {
+            continue;
+        }
+        for entry in std::fs::read_dir(package_entry.path())? {
+            let entry = entry?;
+            if !entry.path().is_dir() {
+                continue;
+            }
+            if let Ok(version) = Version::parse(&entry.file_name().to_string_lossy()) {
+                versions.push((name.clone(), version, entry.path()));
+            }
+        }
+    }
+    versions.sort_by(|(a_name, a_version, _), (b_name, b_version, _)| {
+        a_name.cmp(b_name).then_with(|| b_version.cmp(a_version))
+    });
+
+    // Which versions link each file, to tell shared files from exclusive ones
+    let versions: Vec<_> = versions
+        .into_iter()
+        .map(|(name, version, path)| {
+            let stored = std::fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+            (name, version, path, stored)
+        })
+        .collect();
+    let mut owners: HashMap<FileId, HashSet<usize>> = HashMap::new();
+    for (index, (_, _, _, stored)) in versions.iter().enumerate() {
+        visit_files(stored, &mut |_, metadata| {
+            owners.entry(file_id(metadata)).or_default().insert(index);
+        })?;
+    }
+
+    let mut usages = Vec::with_capacity(versions.len());
+    for (index, (name, version, path, stored)) in versions.into_iter().enumerate() {
+        let mut seen = HashSet::new();
+        let (mut bytes, mut exclusive) = (0, 0);
+        visit_files(&stored, &mut |_, metadata| {
+            let id = file_id(metadata);
+            if seen.insert(id) {
+                bytes += metadata.len();
+                if owners.get(&id).is_some_and(|owners| owners.len() == 1 && owners.contains(&index)) {
+                    exclusive += metadata.len();
+                }
+            }
+        })?;
+        usages.push(PackageUsage { name, version, path, stored, bytes, exclusive });
+    }
+
+    Ok(usages)
+}
+
+/// Size of the versions `packages` and their dependencies use, each
+/// counted once.
+pub fn app_size(packages: &[Package], usages: &[PackageUsage]) -> u64 {
+    fn collect<'a>(package: &'a Package, versions: &mut HashSet<(&'a str, &'a Version)>) {
+        versions.insert((&package.name, &package.version));
+        for dependency in &package.dependencies {
+            collect(dependency, versions);
+        }
+    }
+
+    let mut versions = HashSet::new();
+    for package in packages {
+        collect(package, &mut versions);
+    }
+    usages
+        .iter()
+        .filter(|usage| versions.contains(&(usage.name.as_str(), &usage.version)))
+        .map(|usage| usage.bytes)
+        .sum()
+}
+
+/// Files of `first` also present in `second`, as left by syncing one into
+/// the other.
+pub fn duplicates(first: &Path, second: &Path) -> Result<Duplicates> {
+    let mut duplicates = Duplicates::default();
+    if !first.is_dir() || !second.is_dir() {
+        return Ok(duplicates);
+    }
+
+    visit_files(first, &mut |path, metadata| {
+        let Ok(relative) = path.strip_prefix(first) else {
+            return;
+        };
+        let copy = second.join(relative);
+        if std::fs::symlink_metadata(&copy).is_ok_and(|copy| copy.is_file() && copy.len() == metadata.len()) {
+            duplicates.bytes += metadata.len();
+            duplicates.files += 1;
+        }
+    })?;
+    Ok(duplicates)
+}
+
+pub(crate) fn visit_files(path: &Path, visit: &mut impl FnMut(&Path, &std::fs::Metadata)) -> Result<()> {
+    let metadata = std::fs::symlink_metadata(path)?;
+    if metadata.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            visit_files(&entry?.path(), visit)?;
+        }
+    } else if metadata.is_file() {
+        visit(path, &metadata);
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+type FileId = (u64, u64);
+
+#[cfg(unix)]
+fn file_id(metadata: &std::fs::Metadata) -> FileId {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.dev(), metadata.ino())
+}
+
+// Without inode numbers every file is its own
+#[cfg(not(unix))]
+type FileId = usize;
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &std::fs::Metadata) -> FileId {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    NEXT.fetch_add(1, Ordering::Relaxed)
+}
@@ -0,0 +1,146 @@
+//! Checks installed packages and commands against what was installed.
+//!
+//! Package files are compared with the file manifest recorded in the
+//! registry at install time: by size and sha256, then by mode. Files that
+//! are in the package directory but not in the manifest are reported as
+//! extra. Commands are checked to link where their package says.
+
+use anyhow::Result;
+use semver::Version;
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use super::registry::{FileManifest, FileRecord, Registry, file_digest, file_mode};
+use crate::config::InstalledCommand;
+
+/// Something wrong with an installed package.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// The package directory is gone, or links to one that is
+    MissingPackage,
+    /// Installed before file manifests were recorded, so not checkable
+    Unrecorded,
+    Missing(PathBuf),
+    /// Other content, or another kind of file
+    Modified(PathBuf),
+    /// Same content, other permissions
+    Mode(PathBuf),
+    Extra(PathBuf),
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::MissingPackage => write!(f, "package directory is missing"),
+            Problem::Unrecorded => write!(f, "no file manifest, installed by an older diem"),
+            Problem::Missing(path) => write!(f, "missing {}", path.display()),
+            Problem::Modified(path) => write!(f, "modified {}", path.display()),
+            Problem::Mode(path) => write!(f, "permissions changed on {}", path.display()),
+            Problem::Extra(path) => write!(f, "extra {}", path.display()),
+        }
+    }
+}
+
+/// Something wrong with the link of a command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandProblem {
+    Missing,
+    /// Links somewhere else
+    Elsewhere(PathBuf),
+    /// Links to a file that is gone
+    Dangling,
+}
+
+impl std::fmt::Display for CommandProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommandProblem::Missing => write!(f, "link is missing"),
+            CommandProblem::Elsewhere(target) => write!(f, "links to {}", target.display()),
+            CommandProblem::Dangling => write!(f, "links to a missing file"),
+        }
+    }
+}
+
+/// Checks an installed package version against its recorded manifest.
+pub fn verify_package(install_dir: &Path, name: &str, version: &Version) -> Result<Vec<Problem>> {
+    let package_dir = install_dir.join(name).join(version.to_string());
+    if !package_dir.is_dir() {
+        return Ok(vec![Problem::MissingPackage]);
+    }
+
+    match Registry::for_install_dir(install_dir).manifest(name, version)? {
+        Some(manifest) => verify_files(&package_dir, &manifest),
+        None => Ok(vec![Problem::Unrecorded]),
+    }
+}
+
+/// Compares the files of `package_dir` with `manifest`.
+pub fn verify_files(package_dir: &Path, manifest: &FileManifest) -> Result<Vec<Problem>> {
+    let mut problems = Vec::new();
+    for record in &manifest.files {
+        let path = package_dir.join(record.path());
+        let relative = record.path().to_path_buf();
+        let Ok(metadata) = std::fs::symlink_metadata(&path) else {
+            problems.push(Problem::Missing(relative));
+            continue;
+        };
+
+        match record {
+            FileRecord::Dir { mode, .. } => {
+                if !metadata.is_dir() {
+                    problems.push(Problem::Modified(relative));
+                } else if file_mode(&metadata) != *mode {
+                    problems.push(Problem::Mode(relative));
+                }
+            }
+            FileRecord::File { size, digest, mode, .. } => {
+                if !metadata.is_file() || metadata.len() != *size || file_digest(&path)? != *digest {
+                    problems.push(Problem::Modified(relative));
+                } else if file_mode(&metadata) != *mode {
+                    problems.push(Problem::Mode(relative));
+                }
+            }
+            FileRecord::Symlink { target, .. } => {
+                if std::fs::read_link(&path).ok().as_ref() != Some(target) {
+                    problems.push(Problem::Modified(relative));
+                }
+            }
+        }
+    }
+
+    let recorded: HashSet<&Path> = manifest.files.iter().map(FileRecord::path).collect();
+    find_extra(package_dir, Path::new(""), &recorded, &mut problems)?;
+    Ok(problems)
+}
+
+/// Checks that a command links into its package.
+pub fn verify_command(command: &InstalledCommand, install_dir: &Path, bin_dir: &Path) -> Option<CommandProblem> {
+    let expected = command.target(install_dir);
+    match std::fs::read_link(bin_dir.join(&command.command)) {
+        Err(_) => Some(CommandProblem::Missing),
+        Ok(target) if target != expected => Some(CommandProblem::Elsewhere(target)),
+        Ok(_) if !expected.exists() => Some(CommandProblem::Dangling),
+        Ok(_) => None,
+    }
+}
+
+// Lists what is in `dir` but not recorded, without descending into extra
+// directories
+fn find_extra(dir: &Path, prefix: &Path, recorded: &HashSet<&Path>, problems: &mut Vec<Problem>) -> Result<()> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Ok(());
+    };
+
+    let mut entries: Vec<_> = entries.collect::<Result<_, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let relative = prefix.join(entry.file_name());
+        if !recorded.contains(relative.as_path()) {
+            problems.push(Problem::Extra(relative));
+        } else if entry.file_type()?.is_dir() {
+            find_extra(&entry.path(), &relative, recorded, problems)?;
+        }
+    }
+    Ok(())
+}
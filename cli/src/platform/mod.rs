@@ -0,0 +1,121 @@
+//! Host platform detection and target triple matching for package variants.
+
+use anyhow::Result;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Libc {
+    Gnu,
+    Musl,
+}
+
+/// A platform a package can be built for, as described by a target triple
+/// such as `x86_64-unknown-linux-gnu`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Platform {
+    pub arch: String,
+    pub os: String,
+    /// Only meaningful on Linux; `None` when the triple does not say
+    pub libc: Option<Libc>,
+}
+
+impl Platform {
+    /// Detects the platform diem is running on, including whether the
+    /// system C library is glibc or musl.
+    pub fn current() -> Self {
+        let os = std::env::consts::OS.to_string();
+        let libc = if os == "linux" { Some(detect_libc()) } else { None };
+
+        Self {
+            arch: normalize_arch(std::env::consts::ARCH),
+            os,
+            libc,
+        }
+    }
+
+    /// Parses a target triple like `aarch64-unknown-linux-musl`.
+    pub fn parse(target: &str) -> Result<Self> {
+        let parts: Vec<&str> = target.split('-').collect();
+        if parts.len() < 2 || parts[0].is_empty() {
+            anyhow::bail!("Invalid target triple: {}", target);
+        }
+
+        let os = if parts.contains(&"linux") {
+            "linux"
+        } else if parts.iter().any(|part| matches!(*part, "darwin" | "apple" | "macos")) {
+            "macos"
+        } else if parts.contains(&"windows") {
+            "windows"
+        } else {
+            anyhow::bail!("Unsupported operating system in target triple: {}", target);
+        };
+
+        let libc = match parts.last() {
+            Some(env) if os == "linux" && env.starts_with("musl") => Some(Libc::Musl),
+            Some(env) if os == "linux" && env.starts_with("gnu") => Some(Libc::Gnu),
+            _ => None,
+        };
+
+        Ok(Self {
+            arch: normalize_arch(parts[0]),
+            os: os.to_string(),
+            libc,
+        })
+    }
+
+    /// Returns the canonical target triple for this platform.
+    pub fn triple(&self) -> String {
+        match (self.os.as_str(), self.libc) {
+            ("linux", Some(Libc::Musl)) => format!("{}-unknown-linux-musl", self.arch),
+            ("linux", _) => format!("{}-unknown-linux-gnu", self.arch),
+            ("macos", _) => format!("{}-apple-darwin", self.arch),
+            ("windows", _) => format!("{}-pc-windows-msvc", self.arch),
+            (os, _) => format!("{}-unknown-{}", self.arch, os),
+        }
+    }
+
+    /// Scores how well a build for `target` fits this host, or `None` if it
+    /// cannot run here. Static musl builds run on glibc hosts, but not the
+    /// other way around.
+    pub fn compatibility(&self, target: &Platform) -> Option<u8> {
+        if self.arch != target.arch || self.os != target.os {
+            return None;
+        }
+
+        match (self.libc, target.libc) {
+            (host, target) if host == target => Some(2),
+            (_, None) => Some(1),
+            (Some(Libc::Gnu), Some(Libc::Musl)) => Some(1),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) fn normalize_arch(arch: &str) -> String {
+    match arch {
+        "amd64" | "x64" => "x86_64",
+        "arm64" => "aarch64",
+        "i386" | "i586" | "i686" => "x86",
+        arch => arch,
+    }
+    .to_string()
+}
+
+// Looks at which dynamic loaders are installed. Some glibc systems also
+// ship the musl loader, so glibc wins when both are present.
+fn detect_libc() -> Libc {
+    let has_loader = |prefix: &str| {
+        ["/lib", "/lib64", "/usr/lib"].iter().any(|dir| {
+            std::fs::read_dir(dir).is_ok_and(|entries| {
+                entries
+                    .flatten()
+                    .any(|entry| entry.file_name().to_string_lossy().starts_with(prefix))
+            })
+        })
+    };
+
+    if !has_loader("ld-linux") && has_loader("ld-musl-") {
+        Libc::Musl
+    } else {
+        Libc::Gnu
+    }
+}
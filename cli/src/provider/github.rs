@@ -14,12 +14,17 @@ pub struct GithubProvider {
 }
 
 impl GithubProvider {
-    pub async fn fetch_artifactory(&self) -> Result<String> {
-        let client = Client::new();
-        let url = format!(
+    /// Raw URL of the artifactory file
+    pub fn artifactory_url(&self) -> String {
+        format!(
             "https://raw.githubusercontent.com/{}/{}/{}/{}",
             self.owner, self.repo, self.ref_, self.path
-        );
+        )
+    }
+
+    pub async fn fetch_artifactory(&self) -> Result<String> {
+        let client = Client::new();
+        let url = self.artifactory_url();
 
         let response = client.get(&url).send().await?;
         let bytes = response.bytes().await?;
@@ -27,6 +32,20 @@ impl GithubProvider {
         Ok(str)
     }
 
+    /// Fetches the detached signature stored next to the artifactory, if any
+    pub async fn fetch_signature(&self) -> Result<Option<String>> {
+        let client = Client::new();
+        let url = format!("{}.{}", self.artifactory_url(), crate::signature::SIGNATURE_EXTENSION);
+
+        let response = client.get(&url).send().await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let response = response.error_for_status()?;
+        Ok(Some(response.text().await?))
+    }
+
     pub async fn download_package(&self, package_path: &str, destination: &PathBuf) -> Result<()> {
         let client = Client::new();
 
@@ -68,11 +87,3 @@ impl GithubProvider {
         Ok(())
     }
 }
-
-#[derive(Debug, thiserror::Error)]
-pub enum GithubProviderError {
-    #[error("Failed to fetch artifactory: {0}")]
-    FetchError(#[from] reqwest::Error),
-    #[error("Failed to write package: {0}")]
-    IoError(#[from] std::io::Error),
-}
@@ -8,11 +8,26 @@ use futures_util::stream::StreamExt as _;
 use std::path::PathBuf;
 use reqwest;
 
+use crate::{
+    Artifactory,
+    artifactory::{
+        include::Location,
+        index::{self, IndexCache, IndexSource, LoadedIndex},
+    },
+    config::{ArtifactorySource, ArtifactorySubscription},
+};
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Provider {
     pub name: String,
     pub source: ProviderSource,
     pub provider_handler_version: u8,
+    /// Minisign public key the artifactory must be signed with
+    #[serde(default)]
+    pub public_key: Option<String>,
+    /// Accept the artifactory even if it is unsigned or no key is pinned
+    #[serde(default)]
+    pub allow_unsigned: bool,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -37,6 +52,67 @@ impl Provider {
         }
     }
 
+    pub async fn fetch_signature(&self) -> Result<Option<String>> {
+        match &self.source {
+            ProviderSource::Github(github) => github.fetch_signature().await,
+            ProviderSource::Artifactory(artifactory) => {
+                let signature_path = crate::signature::signature_path(&artifactory.path);
+                if !signature_path.exists() {
+                    return Ok(None);
+                }
+
+                std::fs::read_to_string(&signature_path)
+                    .map(Some)
+                    .map_err(|e| anyhow::anyhow!("Failed to read signature file: {}", e))
+            },
+        }
+    }
+
+    /// Fetches the artifactory and checks its signature against the pinned key
+    pub async fn fetch_verified_artifactory(&self) -> Result<String> {
+        let content = self.fetch_artifactory().await?;
+        let signature = self.fetch_signature().await?;
+
+        crate::signature::verify_artifactory(
+            &self.name,
+            &content,
+            signature.as_deref(),
+            self.public_key.as_deref(),
+            self.allow_unsigned,
+        )?;
+
+        Ok(content)
+    }
+
+    /// Where the artifactory file lives, to resolve its includes
+    pub fn location(&self) -> Location {
+        match &self.source {
+            ProviderSource::Github(github) => Location::Url(github.artifactory_url()),
+            ProviderSource::Artifactory(artifactory) => Location::Path(artifactory.path.clone()),
+        }
+    }
+
+    /// Fetches and verifies the artifactory, then merges in the files it
+    /// includes, reusing the compiled index from `cache` if it is current
+    pub async fn fetch_composed_artifactory(&self, cache: Option<&IndexCache>) -> Result<Artifactory> {
+        Ok(self.fetch_composed_index(cache).await?.artifactory)
+    }
+
+    /// Like `fetch_composed_artifactory`, also returning the key the
+    /// artifactory rotated to, if it did.
+    pub async fn fetch_composed_index(&self, cache: Option<&IndexCache>) -> Result<LoadedIndex> {
+        let source = IndexSource {
+            name: &self.name,
+            location: self.location(),
+            content: self.fetch_artifactory().await?,
+            signature: self.fetch_signature().await?,
+            public_key: self.public_key.as_deref(),
+            allow_unsigned: self.allow_unsigned,
+        };
+
+        index::load_index(cache, &source).await
+    }
+
     pub async fn download_package(&self, package_path: &str, destination: &PathBuf) -> Result<()> {
         match &self.source {
             ProviderSource::Github(github) => {
@@ -121,14 +197,19 @@ impl Provider {
         }
     }
     
-    // Create a dummy provider for artifactories
-    pub fn create_dummy_for_artifactory(artifactory_name: &str) -> Result<Self> {
-        Ok(Self {
-            name: format!("artifactory:{}", artifactory_name),
-            source: ProviderSource::Artifactory(ArtifactoryProvider {
-                path: PathBuf::new(), // We don't need the actual path here
+    /// A provider reading the artifactory file of a subscription, so that
+    /// relative package sources resolve next to it. `None` for remote
+    /// subscriptions, which are not supported yet.
+    pub fn for_subscription(subscription: &ArtifactorySubscription) -> Option<Self> {
+        match &subscription.source {
+            ArtifactorySource::Local(path) | ArtifactorySource::Shared(path) => Some(Self {
+                name: format!("artifactory:{}", subscription.name),
+                source: ProviderSource::Artifactory(ArtifactoryProvider { path: path.clone() }),
+                provider_handler_version: crate::schema::PROVIDER_HANDLER_VERSION,
+                public_key: subscription.public_key.clone(),
+                allow_unsigned: subscription.allow_unsigned,
             }),
-            provider_handler_version: 1,
-        })
+            ArtifactorySource::Remote(_) => None,
+        }
     }
 }
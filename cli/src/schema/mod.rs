@@ -0,0 +1,290 @@
+//! Versioned on-disk formats and their migrations.
+//!
+//! Every persisted struct carries a `*_handler_version`. Documents are
+//! upgraded as raw TOML tables, one version at a time, before they are
+//! deserialized, so an older shape never has to parse as the current one.
+//! A document newer than this build understands is rejected.
+//!
+//! Artifactories and app definitions may be written in TOML, JSON or YAML.
+//! JSON and YAML documents are converted to TOML tables first, dropping
+//! `null`s, so they go through the same migrations.
+//!
+//! Version history:
+//! - 0: the original format.
+//! - 1: packages may declare per-platform `variants` instead of a single
+//!   `source`, artifactories may declare a `public_key` and `key_rotations`,
+//!   and providers and subscriptions may pin a `public_key`. Packages whose
+//!   `source` names the target it was built for are upgraded to a single
+//!   variant for it, so other platforms do not install it; the other
+//!   changes are optional fields, so upgrading from 0 only bumps the
+//!   version.
+//! - 2: artifactories may `include` other artifactory files. Older versions
+//!   would silently drop the included apps, hence the bump.
+//!
+//! Apps later gained optional `tags`, `categories`, `homepage`, `repository`,
+//! `maintainers` and `readme` fields. Older versions can safely ignore them,
+//! so the app format stayed at version 1.
+
+use anyhow::Result;
+use serde::{Serialize, de::DeserializeOwned};
+use toml::{Table, Value};
+
+use std::path::Path;
+
+use crate::{App, Artifactory};
+
+type Migration = fn(&mut Table) -> Result<()>;
+
+struct Schema {
+    kind: &'static str,
+    field: &'static str,
+    /// `migrations[n]` upgrades a document from version `n` to `n + 1`
+    migrations: &'static [Migration],
+}
+
+const ARTIFACTORY: Schema = Schema {
+    kind: "Artifactory",
+    field: "artifactory_handler_version",
+    migrations: &[bump, bump],
+};
+
+const APP: Schema = Schema {
+    kind: "App",
+    field: "app_handler_version",
+    migrations: &[bump],
+};
+
+const PACKAGE: Schema = Schema {
+    kind: "Package",
+    field: "package_handler_version",
+    migrations: &[source_to_variant],
+};
+
+const PROVIDER: Schema = Schema {
+    kind: "Provider",
+    field: "provider_handler_version",
+    migrations: &[bump],
+};
+
+const CONFIG: Schema = Schema {
+    kind: "Config",
+    field: "config_handler_version",
+    migrations: &[bump],
+};
+
+pub const ARTIFACTORY_HANDLER_VERSION: u8 = ARTIFACTORY.migrations.len() as u8;
+pub const APP_HANDLER_VERSION: u8 = APP.migrations.len() as u8;
+pub const PACKAGE_HANDLER_VERSION: u8 = PACKAGE.migrations.len() as u8;
+pub const PROVIDER_HANDLER_VERSION: u8 = PROVIDER.migrations.len() as u8;
+pub const CONFIG_HANDLER_VERSION: u8 = CONFIG.migrations.len() as u8;
+
+// Migration for format changes that only added optional fields
+fn bump(_document: &mut Table) -> Result<()> {
+    Ok(())
+}
+
+// Turns the single `source` of a version 0 package into a variant, when its
+// archive name says which target it was built for, e.g.
+// `packages/eza_x86_64-unknown-linux-gnu-v0.20.16.tar.gz`
+fn source_to_variant(document: &mut Table) -> Result<()> {
+    if document.contains_key("variants") || !document.get("sha256").is_some_and(Value::is_str) {
+        return Ok(());
+    }
+    let Some(source) = document.get("source").and_then(Value::as_str) else {
+        return Ok(());
+    };
+    let file_name = source.rsplit('/').next().unwrap_or(source);
+    let Some(target) = crate::artifactory::import::asset_target(file_name) else {
+        return Ok(());
+    };
+
+    let mut variant = Table::new();
+    variant.insert("target".to_string(), Value::String(target));
+    for field in ["source", "sha256"] {
+        variant.insert(field.to_string(), document.remove(field).expect("checked above"));
+    }
+    document.insert("variants".to_string(), Value::Array(vec![Value::Table(variant)]));
+    Ok(())
+}
+
+impl Schema {
+    fn current(&self) -> u8 {
+        self.migrations.len() as u8
+    }
+
+    // Upgrades a single document in place, returning whether it changed
+    fn upgrade(&self, document: &mut Table) -> Result<bool> {
+        let name = document
+            .get("name")
+            .and_then(Value::as_str)
+            .unwrap_or("<unnamed>")
+            .to_string();
+
+        let version = match document.get(self.field) {
+            None => 0,
+            Some(Value::Integer(version)) => u8::try_from(*version)
+                .map_err(|_| anyhow::anyhow!("{} '{}' has an invalid {}: {}", self.kind, name, self.field, version))?,
+            Some(value) => anyhow::bail!("{} '{}' has an invalid {}: {}", self.kind, name, self.field, value),
+        };
+
+        if version > self.current() {
+            anyhow::bail!(
+                "{} '{}' uses format version {}, but this version of diem only understands up to version {}. Please update diem.",
+                self.kind,
+                name,
+                version,
+                self.current()
+            );
+        }
+
+        for migration in &self.migrations[version as usize..] {
+            migration(document)?;
+        }
+        document.insert(self.field.to_string(), Value::Integer(self.current().into()));
+
+        Ok(version != self.current())
+    }
+}
+
+// Applies `upgrade` to every table of the array stored under `key`
+fn upgrade_each(document: &mut Table, key: &str, upgrade: fn(&mut Table) -> Result<bool>) -> Result<bool> {
+    let mut changed = false;
+    if let Some(Value::Array(items)) = document.get_mut(key) {
+        for item in items {
+            if let Value::Table(table) = item {
+                changed |= upgrade(table)?;
+            }
+        }
+    }
+    Ok(changed)
+}
+
+fn upgrade_package(document: &mut Table) -> Result<bool> {
+    let changed = PACKAGE.upgrade(document)?;
+    Ok(upgrade_each(document, "dependencies", upgrade_package)? || changed)
+}
+
+fn upgrade_app(document: &mut Table) -> Result<bool> {
+    let changed = APP.upgrade(document)?;
+    Ok(upgrade_each(document, "packages", upgrade_package)? || changed)
+}
+
+fn upgrade_artifactory(document: &mut Table) -> Result<bool> {
+    let changed = ARTIFACTORY.upgrade(document)?;
+    Ok(upgrade_each(document, "apps", upgrade_app)? || changed)
+}
+
+fn upgrade_provider(document: &mut Table) -> Result<bool> {
+    PROVIDER.upgrade(document)
+}
+
+/// Upgrades a config document in place, returning whether it changed and
+/// should be written back.
+pub fn upgrade_config(document: &mut Table) -> Result<bool> {
+    let mut changed = CONFIG.upgrade(document)?;
+    changed |= upgrade_each(document, "providers", upgrade_provider)?;
+    changed |= upgrade_each(document, "packages", upgrade_package)?;
+    Ok(changed)
+}
+
+/// File formats artifactories and app definitions can be written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    Toml,
+    Json,
+    Yaml,
+}
+
+impl Format {
+    /// Recognizes `.toml`, `.json`, `.yaml` and `.yml` files.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "toml" => Some(Self::Toml),
+            "json" => Some(Self::Json),
+            "yaml" | "yml" => Some(Self::Yaml),
+            _ => None,
+        }
+    }
+
+    /// Guesses the format of a document: JSON starts with `{`, and anything
+    /// that is not valid TOML but is a YAML mapping is YAML.
+    pub fn detect(content: &str) -> Self {
+        if content.trim_start().starts_with('{') {
+            Self::Json
+        } else if toml::from_str::<Table>(content).is_err()
+            && serde_yaml::from_str::<serde_yaml::Mapping>(content).is_ok()
+        {
+            Self::Yaml
+        } else {
+            Self::Toml
+        }
+    }
+
+    /// Uses the extension of `path` if it has a known one, the content
+    /// otherwise.
+    pub fn for_file(path: &Path, content: &str) -> Self {
+        Self::from_path(path).unwrap_or_else(|| Self::detect(content))
+    }
+
+    pub fn serialize<T: Serialize>(self, value: &T) -> Result<String> {
+        Ok(match self {
+            Self::Toml => toml::to_string_pretty(value)?,
+            Self::Json => serde_json::to_string_pretty(value)? + "\n",
+            Self::Yaml => serde_yaml::to_string(value)?,
+        })
+    }
+
+    /// Parses a document into a table, without any upgrade.
+    pub fn parse_table(self, content: &str) -> Result<Table> {
+        let value: serde_json::Value = match self {
+            Self::Toml => return Ok(toml::from_str(content)?),
+            Self::Json => serde_json::from_str(content)?,
+            Self::Yaml => serde_yaml::from_str(content)?,
+        };
+
+        match strip_nulls(value) {
+            Some(value @ serde_json::Value::Object(_)) => Ok(serde_json::from_value(value)?),
+            _ => anyhow::bail!("Expected a {:?} document with fields at the top level", self),
+        }
+    }
+}
+
+// TOML has no null, and a missing field means the same thing
+fn strip_nulls(value: serde_json::Value) -> Option<serde_json::Value> {
+    match value {
+        serde_json::Value::Null => None,
+        serde_json::Value::Array(items) => Some(serde_json::Value::Array(
+            items.into_iter().filter_map(strip_nulls).collect(),
+        )),
+        serde_json::Value::Object(fields) => Some(serde_json::Value::Object(
+            fields
+                .into_iter()
+                .filter_map(|(key, value)| strip_nulls(value).map(|value| (key, value)))
+                .collect(),
+        )),
+        value => Some(value),
+    }
+}
+
+fn parse<T: DeserializeOwned>(content: &str, format: Format, upgrade: fn(&mut Table) -> Result<bool>) -> Result<T> {
+    let mut document = format.parse_table(content)?;
+    upgrade(&mut document)?;
+    Ok(Value::Table(document).try_into()?)
+}
+
+/// Parses an artifactory file in any supported format, upgrading it to the
+/// current format version.
+pub fn parse_artifactory(content: &str) -> Result<Artifactory> {
+    parse_artifactory_as(content, Format::detect(content))
+}
+
+/// Parses an artifactory file written in `format`.
+pub fn parse_artifactory_as(content: &str, format: Format) -> Result<Artifactory> {
+    parse(content, format, upgrade_artifactory)
+}
+
+/// Parses a standalone app definition in any supported format, upgrading it
+/// to the current format version.
+pub fn parse_app(content: &str) -> Result<App> {
+    parse(content, Format::detect(content), upgrade_app)
+}
@@ -0,0 +1,249 @@
+//! Detached minisign signatures for artifactory files.
+//!
+//! Maintainers sign their artifactory with `minisign -Sm artifactory.toml`,
+//! which writes `artifactory.toml.minisig` next to it. Subscribers pin the
+//! maintainer's public key, and diem refuses indexes that are unsigned or
+//! whose signature does not match. Packages are covered transitively: their
+//! sha256 digests live in the signed index.
+//!
+//! An artifactory also declares its current `public_key`, which diem pins
+//! on first use when the user did not provide one. Maintainers roll keys by
+//! appending a `key_rotations` entry: the new key, signed with the previous
+//! one (`minisign -Sm new_key.txt -s old.key`). Subscribers holding any key
+//! of the chain follow it to the current one without re-subscribing.
+
+use anyhow::Result;
+use minisign_verify::{PublicKey, Signature};
+use serde::{Deserialize, Serialize};
+
+use std::path::{Path, PathBuf};
+
+use crate::utils::ui;
+
+/// Extension appended to an artifactory path to find its signature.
+pub const SIGNATURE_EXTENSION: &str = "minisig";
+
+/// A statement that the maintainer moved to `new_key`, signed with the
+/// key it replaces.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct KeyRotation {
+    /// The new public key, as a base64 minisign key line
+    pub new_key: String,
+    /// Content of the `.minisig` file signing `new_key` with the old key
+    pub signature: String,
+}
+
+impl KeyRotation {
+    fn is_signed_by(&self, public_key: &PublicKey) -> bool {
+        Signature::decode(&self.signature)
+            .and_then(|signature| public_key.verify(self.new_key.trim().as_bytes(), &signature, false))
+            .is_ok()
+    }
+}
+
+// The signing-related fields of an artifactory, read before it is trusted.
+// Rotations are self-authenticating, so reading them early is safe.
+#[derive(Default, Deserialize)]
+struct SigningMetadata {
+    public_key: Option<String>,
+    #[serde(default)]
+    key_rotations: Vec<KeyRotation>,
+}
+
+impl SigningMetadata {
+    fn parse(content: &str) -> Self {
+        crate::schema::Format::detect(content)
+            .parse_table(content)
+            .ok()
+            .and_then(|table| toml::Value::Table(table).try_into().ok())
+            .unwrap_or_default()
+    }
+}
+
+/// The public key an artifactory declares for itself, not yet checked
+/// against its signature.
+pub fn declared_public_key(content: &str) -> Option<String> {
+    SigningMetadata::parse(content).public_key.map(|public_key| public_key.trim().to_string())
+}
+
+/// Returns the path of the detached signature for `path`.
+pub fn signature_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".");
+    file_name.push(SIGNATURE_EXTENSION);
+    path.with_file_name(file_name)
+}
+
+/// Parses a public key, either as the bare base64 line or as the full
+/// content of a `minisign.pub` file.
+pub fn parse_public_key(public_key: &str) -> Result<PublicKey> {
+    let public_key = public_key.trim();
+    let parsed = if public_key.contains('\n') {
+        PublicKey::decode(public_key)
+    } else {
+        PublicKey::from_base64(public_key)
+    };
+
+    parsed.map_err(|e| anyhow::anyhow!("Invalid minisign public key: {}", e))
+}
+
+/// Checks `content` against its detached `signature`, returning the key
+/// it is signed with when the artifactory rotated away from the pinned
+/// one, for the caller to pin instead.
+///
+/// A signature that does not match the pinned key, directly or through the
+/// artifactory's key rotations, is always an error. A missing signature or
+/// a missing key is only tolerated when `allow_unsigned` is set, in which
+/// case a warning is printed.
+pub fn verify_artifactory(
+    name: &str,
+    content: &str,
+    signature: Option<&str>,
+    public_key: Option<&str>,
+    allow_unsigned: bool,
+) -> Result<Option<String>> {
+    match (signature, public_key) {
+        (Some(signature), Some(public_key)) => {
+            let signature = Signature::decode(signature)
+                .map_err(|e| anyhow::anyhow!("Invalid signature for artifactory '{}': {}", name, e))?;
+
+            verify_with_rotations(name, content, &signature, public_key)
+        }
+        _ if allow_unsigned => {
+            let reason = if signature.is_none() { "is not signed" } else { "has no pinned public key" };
+            println!("{}", ui::warning(&format!(
+                "Artifactory '{}' {}, trusting it anyway because unsigned artifactories are allowed",
+                name, reason
+            )));
+            Ok(None)
+        }
+        (None, _) => anyhow::bail!(
+            "Artifactory '{}' is not signed. Ask its maintainer to sign it with minisign, or pass --allow-unsigned to trust it anyway",
+            name
+        ),
+        (Some(_), None) => anyhow::bail!(
+            "Artifactory '{}' is signed but no public key is pinned. Pass --public-key with the maintainer's key, or --allow-unsigned to skip verification",
+            name
+        ),
+    }
+}
+
+// Verifies the signature with the pinned key, following the artifactory's
+// key rotations when the maintainer has moved to a newer key, which is
+// returned then.
+fn verify_with_rotations(name: &str, content: &str, signature: &Signature, pinned_key: &str) -> Result<Option<String>> {
+    let mut current_key = parse_public_key(pinned_key)?;
+    if current_key.verify(content.as_bytes(), signature, false).is_ok() {
+        return Ok(None);
+    }
+
+    let metadata = SigningMetadata::parse(content);
+    let mut used = vec![false; metadata.key_rotations.len()];
+
+    while let Some(idx) = (0..metadata.key_rotations.len())
+        .find(|&idx| !used[idx] && metadata.key_rotations[idx].is_signed_by(&current_key))
+    {
+        used[idx] = true;
+        let rotation = &metadata.key_rotations[idx];
+        current_key = parse_public_key(&rotation.new_key)?;
+
+        if current_key.verify(content.as_bytes(), signature, false).is_ok() {
+            return Ok(Some(rotation.new_key.trim().to_string()));
+        }
+    }
+
+    anyhow::bail!(
+        "{}\n  pinned key:   {}\n  declared key: {}\n{}",
+        ui::error(&format!("SIGNING KEY MISMATCH for artifactory '{}'!", name)),
+        pinned_key.trim(),
+        metadata.public_key.as_deref().unwrap_or("none").trim(),
+        ui::warning(
+            "The artifactory is not signed by the key you trusted and no signed key rotation links the two. \
+             Someone may be tampering with it. If the maintainer really changed keys, check the new key with them \
+             and re-add the artifactory with --public-key."
+        )
+    )
+}
+
+/// Trusts the key an artifactory declares for itself, after checking that
+/// it actually signed the artifactory. Used when it is first added without
+/// an explicit `--public-key`.
+pub fn trust_on_first_use(name: &str, content: &str, signature: Option<&str>) -> Result<String> {
+    let Some(signature) = signature else {
+        anyhow::bail!("Artifactory '{}' is not signed", name);
+    };
+
+    let Some(public_key) = SigningMetadata::parse(content).public_key else {
+        anyhow::bail!("Artifactory '{}' does not declare a public key", name);
+    };
+
+    verify_artifactory(name, content, Some(signature), Some(&public_key), false)?;
+    Ok(public_key.trim().to_string())
+}
+
+/// Decides which key to pin when an artifactory is added.
+///
+/// An explicit `public_key` must verify the artifactory. Otherwise the key
+/// the artifactory declares is trusted on first use, and if that fails the
+/// artifactory is only accepted unpinned when `allow_unsigned` is set.
+pub fn pin_public_key(
+    name: &str,
+    content: &str,
+    signature: Option<&str>,
+    public_key: Option<String>,
+    allow_unsigned: bool,
+) -> Result<Option<String>> {
+    if let Some(public_key) = public_key {
+        parse_public_key(&public_key)?;
+        verify_artifactory(name, content, signature, Some(&public_key), allow_unsigned)?;
+        return Ok(Some(public_key));
+    }
+
+    match trust_on_first_use(name, content, signature) {
+        Ok(public_key) => {
+            println!("{}", ui::warning(&format!(
+                "Trusting signing key {} for '{}' on first use. Check it with the maintainer: \
+                 diem will refuse any other key from now on.",
+                public_key, name
+            )));
+            Ok(Some(public_key))
+        }
+        Err(_) if allow_unsigned => {
+            verify_artifactory(name, content, signature, None, true)?;
+            Ok(None)
+        }
+        Err(e) => Err(e.context(
+            "Could not pin a signing key. Pass --public-key with the maintainer's key, or --allow-unsigned to trust it anyway",
+        )),
+    }
+}
+
+/// Reads a local artifactory file and the signature stored next to it.
+pub fn read_with_signature(path: &Path) -> Result<(String, Option<String>)> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read artifactory file {}: {}", path.display(), e))?;
+
+    let signature_path = signature_path(path);
+    let signature = if signature_path.exists() {
+        Some(std::fs::read_to_string(&signature_path)
+            .map_err(|e| anyhow::anyhow!("Failed to read signature {}: {}", signature_path.display(), e))?)
+    } else {
+        None
+    };
+
+    Ok((content, signature))
+}
+
+/// Reads a local artifactory file and verifies it against the signature
+/// stored next to it.
+pub fn read_verified(
+    name: &str,
+    path: &Path,
+    public_key: Option<&str>,
+    allow_unsigned: bool,
+) -> Result<String> {
+    let (content, signature) = read_with_signature(path)?;
+
+    verify_artifactory(name, &content, signature.as_deref(), public_key, allow_unsigned)?;
+    Ok(content)
+}
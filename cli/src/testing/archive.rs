@@ -0,0 +1,70 @@
+use anyhow::Result;
+use flate2::{Compression, write::GzEncoder};
+use sha2::{Digest, Sha256};
+
+use std::path::{Path, PathBuf};
+
+struct ArchiveEntry {
+    path: PathBuf,
+    content: Vec<u8>,
+    mode: u32,
+}
+
+/// Builds `.tar.gz` package archives in memory.
+#[derive(Default)]
+pub struct ArchiveBuilder {
+    entries: Vec<ArchiveEntry>,
+}
+
+impl ArchiveBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a regular file with mode `0o644`.
+    pub fn file(self, path: impl Into<PathBuf>, content: impl Into<Vec<u8>>) -> Self {
+        self.entry(path, content, 0o644)
+    }
+
+    /// Adds an executable file with mode `0o755`.
+    pub fn executable(self, path: impl Into<PathBuf>, content: impl Into<Vec<u8>>) -> Self {
+        self.entry(path, content, 0o755)
+    }
+
+    pub fn entry(mut self, path: impl Into<PathBuf>, content: impl Into<Vec<u8>>, mode: u32) -> Self {
+        self.entries.push(ArchiveEntry {
+            path: path.into(),
+            content: content.into(),
+            mode,
+        });
+        self
+    }
+
+    /// Returns the gzip-compressed tarball.
+    pub fn build(&self) -> Result<Vec<u8>> {
+        let encoder = GzEncoder::new(Vec::new(), Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+
+        for entry in &self.entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(entry.content.len() as u64);
+            header.set_mode(entry.mode);
+            header.set_mtime(0);
+            header.set_cksum();
+            builder.append_data(&mut header, &entry.path, entry.content.as_slice())?;
+        }
+
+        Ok(builder.into_inner()?.finish()?)
+    }
+
+    /// Writes the tarball to `path` and returns its sha256 digest.
+    pub fn write_to(&self, path: &Path) -> Result<String> {
+        let bytes = self.build()?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, &bytes)?;
+
+        Ok(format!("{:x}", Sha256::digest(&bytes)))
+    }
+}
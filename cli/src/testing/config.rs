@@ -0,0 +1,34 @@
+use std::path::PathBuf;
+
+use crate::{Config, package::placement::PlacementPolicy, schema};
+
+impl Config {
+    /// An empty config installing into `install_dir`, without the volumes
+    /// or directories detected on the machine running the tests.
+    pub fn for_tests(install_dir: PathBuf) -> Self {
+        Self {
+            packages: Vec::new(),
+            commands: Vec::new(),
+            providers: Vec::new(),
+            install_dir,
+            sgoinfre_dir: None,
+            goinfre_dir: None,
+            subscribed_artifactories: Vec::new(),
+            shared_artifactory_dir: None,
+            ignored_shared_artifactories: Vec::new(),
+            shared_artifactory_keys: Default::default(),
+            allow_unsigned_shared: false,
+            shared_install_dir: None,
+            download_cache_dir: None,
+            placement: PlacementPolicy::default(),
+            config_handler_version: schema::CONFIG_HANDLER_VERSION,
+        }
+    }
+
+    /// Uses `sgoinfre` and `goinfre` as the sgoinfre and goinfre volumes.
+    pub fn with_volumes(mut self, sgoinfre: PathBuf, goinfre: PathBuf) -> Self {
+        self.sgoinfre_dir = Some(sgoinfre);
+        self.goinfre_dir = Some(goinfre);
+        self
+    }
+}
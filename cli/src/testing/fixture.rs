@@ -0,0 +1,184 @@
+use anyhow::Result;
+use semver::Version;
+
+use std::path::{Path, PathBuf};
+
+use crate::{
+    App, AppCommand, Artifactory, Package, PackageVariant, Provider, ProviderSource,
+    artifactory::include::Include, provider::ArtifactoryProvider, schema, signature::KeyRotation,
+};
+
+use super::{ArchiveBuilder, MinisignKey};
+
+/// A local artifactory laid out on disk, served through a regular
+/// `ProviderSource::Artifactory` provider.
+///
+/// The artifactory is signed with a test key unless `unsigned` is called.
+pub struct FixtureProvider {
+    root: PathBuf,
+    artifactory: Artifactory,
+    signing_key: Option<MinisignKey>,
+}
+
+impl FixtureProvider {
+    /// Creates an empty artifactory rooted at `root`.
+    pub fn new(root: &Path, name: &str) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            artifactory: Artifactory {
+                name: name.to_string(),
+                description: None,
+                apps: Vec::new(),
+                maintainer: None,
+                public: true,
+                artifactory_handler_version: schema::ARTIFACTORY_HANDLER_VERSION,
+                public_key: None,
+                key_rotations: Vec::new(),
+                include: Vec::new(),
+            },
+            signing_key: None,
+        }
+        .signed_with(MinisignKey::from_seed(1))
+    }
+
+    /// Adds an entry to the artifactory's `include` list.
+    pub fn include(mut self, include: Include) -> Self {
+        self.artifactory.include.push(include);
+        self
+    }
+
+    /// Signs the artifactory with `key` instead of the default test key.
+    pub fn signed_with(mut self, key: MinisignKey) -> Self {
+        self.artifactory.public_key = Some(key.public_key());
+        self.signing_key = Some(key);
+        self
+    }
+
+    /// Writes the artifactory without a signature.
+    pub fn unsigned(mut self) -> Self {
+        self.artifactory.public_key = None;
+        self.signing_key = None;
+        self
+    }
+
+    /// Rolls over to `new_key`, recording a rotation signed with the
+    /// current key.
+    pub fn rotate_key(mut self, new_key: MinisignKey) -> Self {
+        if let Some(old_key) = &self.signing_key {
+            let new_public_key = new_key.public_key();
+            self.artifactory.key_rotations.push(KeyRotation {
+                signature: old_key.sign(new_public_key.as_bytes()),
+                new_key: new_public_key,
+            });
+        }
+        self.signed_with(new_key)
+    }
+
+    /// Returns the public key subscribers should pin, if the fixture is signed.
+    pub fn public_key(&self) -> Option<String> {
+        self.signing_key.as_ref().map(MinisignKey::public_key)
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn artifactory_path(&self) -> PathBuf {
+        self.root.join("artifactory.toml")
+    }
+
+    /// Writes `archive` under `packages/` and returns the matching `Package`.
+    pub fn add_package(&self, name: &str, version: &str, archive: &ArchiveBuilder) -> Result<Package> {
+        let source = format!("packages/{}-{}.tar.gz", name, version);
+        let sha256 = archive.write_to(&self.root.join(&source))?;
+
+        Ok(Package {
+            name: name.to_string(),
+            version: Version::parse(version)?,
+            sha256,
+            license: "MIT".to_string(),
+            source: Some(source),
+            dependencies: Vec::new(),
+            package_handler_version: schema::PACKAGE_HANDLER_VERSION,
+            variants: Vec::new(),
+        })
+    }
+
+    /// Writes one archive per target triple and returns a `Package` with a
+    /// variant for each of them.
+    pub fn add_variant_package(&self, name: &str, version: &str, builds: &[(&str, ArchiveBuilder)]) -> Result<Package> {
+        let mut variants = Vec::new();
+        for (target, archive) in builds {
+            let source = format!("packages/{}_{}-v{}.tar.gz", name, target, version);
+            let sha256 = archive.write_to(&self.root.join(&source))?;
+            variants.push(PackageVariant {
+                target: target.to_string(),
+                source,
+                sha256,
+            });
+        }
+
+        Ok(Package {
+            name: name.to_string(),
+            version: Version::parse(version)?,
+            sha256: String::new(),
+            license: "MIT".to_string(),
+            source: None,
+            dependencies: Vec::new(),
+            package_handler_version: schema::PACKAGE_HANDLER_VERSION,
+            variants,
+        })
+    }
+
+    /// Adds an app made of a single package, with one command per
+    /// `(command, path)` pair.
+    pub fn add_app(&mut self, package: Package, commands: &[(&str, &str)]) -> App {
+        let app = App {
+            name: package.name.clone(),
+            version: package.version.clone(),
+            license: package.license.clone(),
+            commands: commands
+                .iter()
+                .map(|(command, path)| AppCommand {
+                    command: command.to_string(),
+                    path: PathBuf::from(path),
+                })
+                .collect(),
+            packages: vec![package],
+            app_handler_version: schema::APP_HANDLER_VERSION,
+            description: None,
+            tags: Vec::new(),
+            categories: Vec::new(),
+            homepage: None,
+            repository: None,
+            maintainers: Vec::new(),
+            readme: None,
+        };
+        self.artifactory.apps.push(app.clone());
+        app
+    }
+
+    /// Writes `artifactory.toml` and its signature, and returns a provider
+    /// pinned to the fixture's public key.
+    pub fn provider(&self) -> Result<Provider> {
+        let path = self.artifactory_path();
+        let content = toml::to_string_pretty(&self.artifactory)?;
+        std::fs::create_dir_all(&self.root)?;
+        std::fs::write(&path, &content)?;
+
+        let signature_path = crate::signature::signature_path(&path);
+        match &self.signing_key {
+            Some(key) => std::fs::write(&signature_path, key.sign(content.as_bytes()))?,
+            None if signature_path.exists() => std::fs::remove_file(&signature_path)?,
+            None => {}
+        }
+
+        Ok(Provider {
+            name: format!("fixture:{}", self.artifactory.name),
+            source: ProviderSource::Artifactory(ArtifactoryProvider { path }),
+            provider_handler_version: schema::PROVIDER_HANDLER_VERSION,
+            public_key: self.public_key(),
+            allow_unsigned: false,
+        })
+    }
+}
@@ -0,0 +1,14 @@
+//! Test fixtures used by the integration tests.
+//!
+//! This module is only compiled with the `testing` feature. It builds real
+//! archives and artifactories on disk so the production install path can be
+//! exercised end-to-end without any network access.
+
+pub mod archive;
+mod config;
+pub mod fixture;
+pub mod signing;
+
+pub use archive::ArchiveBuilder;
+pub use fixture::FixtureProvider;
+pub use signing::MinisignKey;
@@ -0,0 +1,50 @@
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use blake2::{Blake2b512, Digest};
+use ed25519_dalek::{Signer, SigningKey};
+
+/// A minisign-compatible signing key, deterministic so tests are reproducible.
+pub struct MinisignKey {
+    key_id: [u8; 8],
+    signing_key: SigningKey,
+}
+
+impl MinisignKey {
+    pub fn from_seed(seed: u8) -> Self {
+        Self {
+            key_id: [seed; 8],
+            signing_key: SigningKey::from_bytes(&[seed; 32]),
+        }
+    }
+
+    /// Returns the public key as the base64 line of a `minisign.pub` file.
+    pub fn public_key(&self) -> String {
+        let mut bin = Vec::with_capacity(42);
+        bin.extend_from_slice(b"Ed");
+        bin.extend_from_slice(&self.key_id);
+        bin.extend_from_slice(self.signing_key.verifying_key().as_bytes());
+        STANDARD.encode(bin)
+    }
+
+    /// Returns the content of a pre-hashed `.minisig` file for `content`,
+    /// as `minisign -S` would write it.
+    pub fn sign(&self, content: &[u8]) -> String {
+        let trusted_comment = "timestamp:0\tfile:artifactory.toml";
+        let signature = self.signing_key.sign(&Blake2b512::digest(content));
+
+        let mut bin = Vec::with_capacity(74);
+        bin.extend_from_slice(b"ED");
+        bin.extend_from_slice(&self.key_id);
+        bin.extend_from_slice(&signature.to_bytes());
+
+        let mut global = signature.to_bytes().to_vec();
+        global.extend_from_slice(trusted_comment.as_bytes());
+        let global_signature = self.signing_key.sign(&global);
+
+        format!(
+            "untrusted comment: signature from diem test key\n{}\ntrusted comment: {}\n{}\n",
+            STANDARD.encode(bin),
+            trusted_comment,
+            STANDARD.encode(global_signature.to_bytes()),
+        )
+    }
+}
@@ -0,0 +1,59 @@
+#![cfg(unix)]
+
+use diem::{
+    Config, PackageManager,
+    package::cache::DownloadCache,
+    testing::{ArchiveBuilder, FixtureProvider},
+};
+
+#[tokio::test]
+async fn installs_from_the_download_cache() -> anyhow::Result<()> {
+    let artifactory_dir = tempfile::tempdir()?;
+    let root = tempfile::tempdir()?;
+    let cache = DownloadCache::new(root.path().join("sgoinfre/downloads"));
+
+    let fixture = FixtureProvider::new(artifactory_dir.path(), "fixtures");
+    let provider = fixture.provider()?;
+    let hello = fixture.add_package("hello", "1.0.0", &ArchiveBuilder::new().file("README", "hello"))?;
+    let archive = artifactory_dir.path().join(hello.source.as_deref().expect("has a source"));
+
+    let first = PackageManager::new(root.path().join("first")).with_download_cache(cache.clone());
+    first.install_package(&hello, &provider).await?;
+    let cached = cache.path(&hello.sha256);
+    assert_eq!(std::fs::read(&cached)?, std::fs::read(&archive)?);
+
+    // Another install, with its own store, does not download again
+    let archive_content = std::fs::read(&archive)?;
+    std::fs::remove_file(&archive)?;
+    let second = PackageManager::new(root.path().join("second")).with_download_cache(cache.clone());
+    second.install_package(&hello, &provider).await?;
+    assert_eq!(std::fs::read_to_string(second.get_package_dir("hello", &hello.version).join("README"))?, "hello");
+
+    // A changed entry is dropped and downloaded again
+    std::fs::write(&cached, "not an archive")?;
+    let third = PackageManager::new(root.path().join("third")).with_download_cache(cache.clone());
+    assert!(third.install_package(&hello, &provider).await.is_err(), "nothing to download");
+    assert!(!cached.exists());
+
+    std::fs::write(&archive, &archive_content)?;
+    third.install_package(&hello, &provider).await?;
+    assert_eq!(std::fs::read(&cached)?, archive_content);
+    assert!(third.get_package_dir("hello", &hello.version).join("README").is_file());
+
+    Ok(())
+}
+
+#[test]
+fn caches_downloads_in_sgoinfre_by_default() {
+    let root = std::path::Path::new("/diem");
+    let config = Config::for_tests(root.join("packages")).with_volumes(root.join("sgoinfre"), root.join("goinfre"));
+    let cache = config.download_cache().expect("has a cache");
+    assert_eq!(cache.dir(), &root.join("sgoinfre/downloads"));
+
+    let config = Config { download_cache_dir: Some(root.join("shared/downloads")), ..config };
+    assert_eq!(config.download_cache().expect("has a cache").dir(), &root.join("shared/downloads"));
+
+    let config = Config::for_tests(root.join("packages"));
+    let default = DownloadCache::open_default().map(|cache| cache.dir().clone());
+    assert_eq!(config.download_cache().map(|cache| cache.dir().clone()), default);
+}
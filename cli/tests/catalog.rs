@@ -0,0 +1,213 @@
+use diem::{
+    Catalog, Config, PackageManager,
+    artifactory::{
+        index::IndexCache,
+        search::{MatchMode, SearchQuery},
+    },
+    catalog::SourceKind,
+    config::{ArtifactorySource, ArtifactorySubscription},
+    testing::{ArchiveBuilder, FixtureProvider, MinisignKey},
+};
+
+fn subscribe(config: &mut Config, name: &str, fixture: &FixtureProvider) -> anyhow::Result<()> {
+    fixture.provider()?;
+    config.subscribed_artifactories.push(ArtifactorySubscription {
+        name: name.to_string(),
+        source: ArtifactorySource::Local(fixture.artifactory_path()),
+        auto_update: false,
+        public_key: fixture.public_key(),
+        allow_unsigned: false,
+    });
+    Ok(())
+}
+
+fn fixture(root: &std::path::Path, name: &str, app: &str, version: &str) -> anyhow::Result<FixtureProvider> {
+    let mut fixture = FixtureProvider::new(root, name);
+    let archive = ArchiveBuilder::new().executable(format!("bin/{}", app), "#!/bin/sh\n");
+    let package = fixture.add_package(app, version, &archive)?;
+    fixture.add_app(package, &[(app, &format!("bin/{}", app))]);
+    Ok(fixture)
+}
+
+fn catalog(config: &Config, cache_dir: &std::path::Path) -> Catalog {
+    Catalog::new_from_config(config).with_index_cache(Some(IndexCache::new(cache_dir.to_path_buf())))
+}
+
+#[tokio::test]
+async fn merges_providers_and_subscriptions() -> anyhow::Result<()> {
+    let provided_dir = tempfile::tempdir()?;
+    let subscribed_dir = tempfile::tempdir()?;
+    let cache_dir = tempfile::tempdir()?;
+
+    let mut config = Config::for_tests(cache_dir.path().to_path_buf());
+    config.providers.push(fixture(provided_dir.path(), "provided", "hello", "1.0.0")?.provider()?);
+    subscribe(&mut config, "club", &fixture(subscribed_dir.path(), "subscribed", "hello", "2.0.0")?)?;
+
+    let catalog = catalog(&config, cache_dir.path());
+    let kinds: Vec<SourceKind> = catalog.sources().iter().map(|source| source.kind).collect();
+    assert_eq!(kinds, [SourceKind::Provider, SourceKind::Subscription]);
+
+    let hits = catalog.search(&SearchQuery::new("hello", MatchMode::Fuzzy, false)?).await;
+    let found: Vec<(String, String)> =
+        hits.iter().map(|hit| (hit.artifactory.clone(), hit.version.to_string())).collect();
+    assert_eq!(found, [
+        ("subscribed".to_string(), "2.0.0".to_string()),
+        ("provided".to_string(), "1.0.0".to_string()),
+    ]);
+
+    let offers = catalog.find_app_offers("hello").await;
+    let sources: Vec<&str> = offers.iter().map(|offer| offer.source.as_str()).collect();
+    assert_eq!(sources, ["Artifactory: club", "Provider: fixture:provided"]);
+
+    let (app, provider) = catalog.find_app("hello@1.0.0").await?;
+    assert_eq!(app.version.to_string(), "1.0.0");
+    assert_eq!(provider.name, "fixture:provided");
+    assert!(catalog.find_app("hello@3.0.0").await.is_err());
+
+    let (app, provider) = catalog.find_newest_app("hello").await?;
+    assert_eq!((app.version.to_string(), provider.name.as_str()), ("2.0.0".to_string(), "artifactory:club"));
+    assert!(catalog.find_newest_app("goodbye").await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn installs_from_subscriptions() -> anyhow::Result<()> {
+    let subscribed_dir = tempfile::tempdir()?;
+    let install_dir = tempfile::tempdir()?;
+    let cache_dir = tempfile::tempdir()?;
+
+    let mut config = Config::for_tests(install_dir.path().to_path_buf());
+    subscribe(&mut config, "club", &fixture(subscribed_dir.path(), "subscribed", "hello", "1.0.0")?)?;
+
+    // The package source is relative to the subscribed artifactory file
+    let (app, provider) = catalog(&config, cache_dir.path()).find_app("hello").await?;
+    let package_manager = PackageManager::new(install_dir.path().to_path_buf());
+    package_manager.install_package(&app.packages[0], &provider).await?;
+    assert!(package_manager.is_package_installed("hello", Some("1.0.0")).await);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn reports_unreadable_sources() -> anyhow::Result<()> {
+    let cache_dir = tempfile::tempdir()?;
+
+    let mut config = Config::for_tests(cache_dir.path().to_path_buf());
+    config.subscribed_artifactories.push(ArtifactorySubscription {
+        name: "remote".to_string(),
+        source: ArtifactorySource::Remote("https://example.org/artifactory.toml".to_string()),
+        auto_update: false,
+        public_key: None,
+        allow_unsigned: true,
+    });
+
+    let catalog = catalog(&config, cache_dir.path());
+    let loaded = catalog.load_all().await;
+    assert_eq!(loaded.len(), 1);
+    assert_eq!(loaded[0].0.to_string(), "Artifactory: remote");
+    assert!(loaded[0].1.is_err());
+    assert!(catalog.find_app_offers("hello").await.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn subscribes_to_shared_artifactories_implicitly() -> anyhow::Result<()> {
+    let shared_dir = tempfile::tempdir()?;
+    let cache_dir = tempfile::tempdir()?;
+
+    let club = fixture(shared_dir.path(), "club", "hello", "1.0.0")?;
+    club.provider()?;
+    std::fs::write(shared_dir.path().join("notes.txt"), "not an artifactory")?;
+    std::fs::write(shared_dir.path().join(".draft.toml"), "being written")?;
+
+    let mut config = Config::for_tests(cache_dir.path().to_path_buf());
+    config.shared_artifactory_dir = Some(shared_dir.path().to_path_buf());
+    let shared = config.shared_subscriptions();
+    assert_eq!(shared.len(), 1);
+    assert_eq!(shared[0].name, "artifactory");
+    assert_eq!(shared[0].public_key, club.public_key());
+    assert!(matches!(&shared[0].source, ArtifactorySource::Shared(path) if *path == club.artifactory_path()));
+
+    let catalog = catalog(&config, cache_dir.path());
+    let kinds: Vec<SourceKind> = catalog.sources().iter().map(|source| source.kind).collect();
+    assert_eq!(kinds, [SourceKind::Shared]);
+    let (app, provider) = catalog.find_app("hello").await?;
+    assert_eq!(app.version.to_string(), "1.0.0");
+    assert_eq!(provider.name, "artifactory:artifactory");
+
+    // Explicit subscriptions to the same file take precedence
+    subscribe(&mut config, "club", &club)?;
+    assert!(config.shared_subscriptions().is_empty());
+    assert_eq!(config.all_subscriptions().len(), 1);
+
+    config.subscribed_artifactories.clear();
+    config.ignored_shared_artifactories.push("artifactory".to_string());
+    assert!(Catalog::new_from_config(&config).sources().is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn pins_shared_artifactory_keys_on_first_sight() -> anyhow::Result<()> {
+    let shared_dir = tempfile::tempdir()?;
+    let root = tempfile::tempdir()?;
+    let mut config = Config::for_tests(root.path().join("packages"));
+    config.shared_artifactory_dir = Some(shared_dir.path().to_path_buf());
+
+    let club = fixture(shared_dir.path(), "club", "hello", "1.0.0")?;
+    club.provider()?;
+    let first = catalog(&config, &root.path().join("cache"));
+    first.find_app("hello").await?;
+    let rotations = first.key_rotations();
+    assert_eq!(rotations.len(), 1);
+    assert!(rotations[0].first_seen);
+    assert_eq!(config.pin_rotated_keys(&rotations), rotations);
+    assert_eq!(config.shared_artifactory_keys.get("artifactory"), club.public_key().as_ref());
+
+    // Once pinned, another key is refused even though the file declares it
+    let forged = fixture(shared_dir.path(), "club", "hello", "1.0.0")?.signed_with(MinisignKey::from_seed(9));
+    forged.provider()?;
+    let pinned = catalog(&config, &root.path().join("cache"));
+    assert!(pinned.load_all().await.iter().all(|(_, result)| result.is_err()));
+    assert!(pinned.key_rotations().is_empty());
+
+    // So is dropping the signature, even when unsigned files are allowed
+    config.allow_unsigned_shared = true;
+    fixture(shared_dir.path(), "club", "hello", "1.0.0")?.unsigned().provider()?;
+    assert!(catalog(&config, &root.path().join("cache")).load_all().await.iter().all(|(_, result)| result.is_err()));
+
+    // Unsigned files are only used once allowed
+    config.shared_artifactory_keys.clear();
+    assert!(catalog(&config, &root.path().join("cache")).find_app("hello").await.is_ok());
+    config.allow_unsigned_shared = false;
+    assert!(catalog(&config, &root.path().join("cache")).find_app("hello").await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn pins_keys_subscriptions_rotated_to() -> anyhow::Result<()> {
+    let root = tempfile::tempdir()?;
+    let mut config = Config::for_tests(root.path().join("packages"));
+    let fixture = fixture(root.path(), "tools", "hello", "1.0.0")?;
+    subscribe(&mut config, "tools", &fixture)?;
+
+    let fixture = fixture.rotate_key(MinisignKey::from_seed(2)).rotate_key(MinisignKey::from_seed(3));
+    fixture.provider()?;
+    let new_key = MinisignKey::from_seed(3).public_key();
+
+    let rotated = catalog(&config, &root.path().join("cache"));
+    assert!(rotated.load_all().await.iter().all(|(_, result)| result.is_ok()));
+    let rotations = rotated.key_rotations();
+    assert_eq!(config.pin_rotated_keys(&rotations), rotations);
+    assert_eq!(config.subscribed_artifactories[0].public_key.as_deref().map(str::trim), Some(new_key.trim()));
+
+    // Once pinned, the rotation is not followed anymore
+    let pinned = catalog(&config, &root.path().join("cache"));
+    pinned.load_all().await;
+    assert!(pinned.key_rotations().is_empty());
+
+    Ok(())
+}
@@ -0,0 +1,7 @@
+use clap::CommandFactory as _;
+use diem::Cli;
+
+#[test]
+fn command_line_is_consistent() {
+    Cli::command().debug_assert();
+}
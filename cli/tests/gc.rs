@@ -0,0 +1,119 @@
+#![cfg(unix)]
+
+use diem::{
+    Package, PackageManager,
+    package::gc::{Collector, Garbage},
+    testing::{ArchiveBuilder, FixtureProvider},
+};
+use semver::Version;
+
+use std::path::Path;
+use std::time::Duration;
+
+// Installs `hello` at each version, sharing the script between them
+async fn install_versions(artifactory_dir: &Path, install_dir: &Path, versions: &[&str]) -> anyhow::Result<Vec<Package>> {
+    let fixture = FixtureProvider::new(artifactory_dir, "fixtures");
+    let package_manager = PackageManager::new(install_dir.to_path_buf());
+
+    let mut packages = Vec::new();
+    for version in versions {
+        let archive = ArchiveBuilder::new()
+            .executable("bin/hello", "#!/bin/sh\necho hello\n")
+            .file("VERSION", version.to_string());
+        let package = fixture.add_package("hello", version, &archive)?;
+        package_manager.install_package(&package, &fixture.provider()?).await?;
+        packages.push(package);
+    }
+    Ok(packages)
+}
+
+#[tokio::test]
+async fn removes_versions_no_installed_app_uses() -> anyhow::Result<()> {
+    let artifactory_dir = tempfile::tempdir()?;
+    let install_dir = tempfile::tempdir()?;
+    let packages = install_versions(artifactory_dir.path(), install_dir.path(), &["1.0.0", "1.1.0"]).await?;
+    let package_manager = PackageManager::new(install_dir.path().to_path_buf());
+    let old_dir = package_manager.get_package_dir("hello", &packages[0].version);
+
+    let collector = Collector::new(install_dir.path().to_path_buf()).with_installed(&packages[1..]);
+
+    let report = collector.clone().dry_run(true).collect()?;
+    assert_eq!(report.count(Garbage::Version), 1);
+    assert_eq!(report.count(Garbage::Manifest), 1);
+    assert_eq!(report.count(Garbage::Object), 1, "only the old VERSION file is unshared");
+    let manifest_size: u64 =
+        report.removals.iter().filter(|removal| removal.kind == Garbage::Manifest).map(|removal| removal.size).sum();
+    assert_eq!(report.reclaimed, manifest_size + "1.0.0".len() as u64, "linked files free nothing");
+    assert!(old_dir.exists());
+
+    let report = collector.collect()?;
+    assert_eq!(report.removals.len(), 3);
+    assert!(!old_dir.exists());
+    assert!(package_manager.store().manifest(&packages[0].sha256)?.is_none());
+
+    // The remaining version is intact and can still be materialized again
+    let new_dir = package_manager.get_package_dir("hello", &packages[1].version);
+    assert_eq!(std::fs::read_to_string(new_dir.join("VERSION"))?, "1.1.0");
+    package_manager.uninstall_package("hello", Some("1.1.0")).await?;
+    let manifest = package_manager.store().manifest(&packages[1].sha256)?.expect("kept manifest");
+    package_manager.store().materialize(&manifest, &new_dir)?;
+    assert!(new_dir.join("bin/hello").is_file());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn keeps_the_newest_and_linked_versions_of_unrecorded_packages() -> anyhow::Result<()> {
+    let artifactory_dir = tempfile::tempdir()?;
+    let install_dir = tempfile::tempdir()?;
+    let bin_dir = tempfile::tempdir()?;
+    install_versions(artifactory_dir.path(), install_dir.path(), &["1.0.0", "1.1.0", "2.0.0"]).await?;
+    std::os::unix::fs::symlink(
+        install_dir.path().join("hello/1.0.0/bin/hello"),
+        bin_dir.path().join("hello"),
+    )?;
+
+    let report = Collector::new(install_dir.path().to_path_buf())
+        .with_bin_dir(bin_dir.path().to_path_buf())
+        .collect()?;
+
+    let versions = diem::app::info::installed_versions(install_dir.path(), "hello");
+    assert_eq!(versions, [Version::new(2, 0, 0), Version::new(1, 0, 0)]);
+    assert_eq!(report.count(Garbage::Version), 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn removes_leftovers_and_cache_entries_once_expired() -> anyhow::Result<()> {
+    let artifactory_dir = tempfile::tempdir()?;
+    let install_dir = tempfile::tempdir()?;
+    let cache_dir = tempfile::tempdir()?;
+    install_versions(artifactory_dir.path(), install_dir.path(), &["1.0.0"]).await?;
+
+    let staging = install_dir.path().join(".store/tmp/1234-0");
+    std::fs::create_dir_all(&staging)?;
+    std::fs::write(staging.join("package.tmp"), "partial download")?;
+    let materializing = install_dir.path().join("hello/.2.0.0.1234.tmp");
+    std::fs::create_dir_all(&materializing)?;
+    let legacy = install_dir.path().join("hello/1.0.0/temp.tar");
+    std::fs::write(&legacy, "old download")?;
+    let cached = cache_dir.path().join("index.msgpack");
+    std::fs::write(&cached, "index")?;
+
+    // Fresh leftovers may belong to an install still running
+    let collector = Collector::new(install_dir.path().to_path_buf()).with_cache_dir(cache_dir.path().to_path_buf());
+    let report = collector.clone().collect()?;
+    assert_eq!(report.count(Garbage::Temporary), 1);
+    assert!(!legacy.exists());
+    assert!(staging.exists() && materializing.exists() && cached.exists());
+
+    let report = collector.older_than(Duration::ZERO).collect()?;
+    assert_eq!(report.count(Garbage::Temporary), 2);
+    assert_eq!(report.count(Garbage::Cache), 1);
+    assert_eq!(report.count(Garbage::Version), 0);
+    assert!(!staging.exists() && !materializing.exists() && !cached.exists());
+    assert!(install_dir.path().join("hello/1.0.0/bin/hello").is_file());
+
+    Ok(())
+}
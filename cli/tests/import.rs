@@ -0,0 +1,155 @@
+use std::path::Path;
+
+use diem::{
+    artifactory::import::{self, ImportRequest, Release, ReleaseAsset},
+    schema,
+    testing::{ArchiveBuilder, FixtureProvider},
+};
+use sha2::{Digest, Sha256};
+
+fn fixture(name: &str) -> std::path::PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)
+}
+
+fn request(artifactory: &Path) -> ImportRequest {
+    ImportRequest {
+        artifactory: artifactory.to_path_buf(),
+        name: "eza".to_string(),
+        version: None,
+        license: Some("EUPL-1.2".to_string()),
+        description: Some("A modern ls".to_string()),
+        commands: Vec::new(),
+    }
+}
+
+#[test]
+fn guesses_targets_from_asset_names() {
+    let cases = [
+        ("eza_x86_64-unknown-linux-gnu.tar.gz", Some("x86_64-unknown-linux-gnu")),
+        ("ripgrep-14.1.0-x86_64-unknown-linux-musl.tar.gz", Some("x86_64-unknown-linux-musl")),
+        ("fd-v10.1.0-aarch64-apple-darwin.tar.gz", Some("aarch64-apple-darwin")),
+        ("gh_2.40.0_linux_amd64.tar.gz", Some("x86_64-unknown-linux")),
+        ("bat-v0.24.0-x86_64-pc-windows-msvc.zip", Some("x86_64-pc-windows-msvc")),
+        ("eza_arm-unknown-linux-gnueabihf.tar.gz", Some("arm-unknown-linux-gnueabihf")),
+        ("ripgrep-14.1.0-x86_64-unknown-linux-musl.tar.gz.sha256", None),
+        ("ripgrep_14.1.0-1_amd64.deb", None),
+        ("completions-0.20.16.tar.gz", None),
+    ];
+
+    for (name, expected) in cases {
+        assert_eq!(import::asset_target(name).as_deref(), expected, "{}", name);
+    }
+}
+
+#[test]
+fn matches_release_fixture_assets() -> anyhow::Result<()> {
+    let release = import::load_release(&fixture("eza-release.json"))?;
+    assert_eq!(import::version_from_tag(&release.tag_name)?, semver::Version::new(0, 20, 16));
+
+    let matched = import::match_assets(&release);
+    let targets: Vec<&str> = matched.iter().map(|(target, _)| target.as_str()).collect();
+    assert_eq!(targets, [
+        "x86_64-pc-windows-gnu",
+        "aarch64-unknown-linux-gnu",
+        "arm-unknown-linux-gnueabihf",
+        "x86_64-unknown-linux-gnu",
+        "x86_64-unknown-linux-musl",
+    ]);
+
+    // Tarballs are preferred even when the zip is listed first
+    for (_, asset) in &matched {
+        assert!(asset.name.ends_with(".tar.gz"), "{}", asset.name);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn reads_versions_from_tags() -> anyhow::Result<()> {
+    assert_eq!(import::version_from_tag("14.1.0")?, semver::Version::new(14, 1, 0));
+    assert_eq!(import::version_from_tag("ripgrep-14.1.0")?, semver::Version::new(14, 1, 0));
+    assert!(import::version_from_tag("nightly").is_err());
+    assert!(import::parse_repo("eza-community/eza").is_ok());
+    assert!(import::parse_repo("eza").is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn imports_local_release() -> anyhow::Result<()> {
+    let root = tempfile::tempdir()?;
+    let assets = tempfile::tempdir()?;
+
+    let fixture = FixtureProvider::new(root.path(), "imported").unsigned();
+    fixture.provider()?;
+
+    let mut release = Release { tag_name: "v1.0.0".to_string(), assets: Vec::new() };
+    let mut digests = Vec::new();
+    for (name, content) in [
+        ("eza_x86_64-unknown-linux-gnu.tar.gz", "gnu"),
+        ("eza_x86_64-unknown-linux-musl.tar.gz", "musl"),
+        ("man-1.0.0.tar.gz", "man"),
+    ] {
+        let path = assets.path().join(name);
+        ArchiveBuilder::new().executable("eza", content).file("README.md", "eza").write_to(&path)?;
+        digests.push(format!("{:x}", Sha256::digest(std::fs::read(&path)?)));
+        release.assets.push(ReleaseAsset {
+            name: name.to_string(),
+            browser_download_url: format!("file://{}", path.display()),
+        });
+    }
+
+    let app = import::import(&request(&fixture.artifactory_path()), &release).await?;
+    assert_eq!(app.version, semver::Version::new(1, 0, 0));
+    assert_eq!(app.commands.len(), 1);
+    assert_eq!(app.commands[0].path, Path::new("eza"));
+
+    let variants = &app.packages[0].variants;
+    assert_eq!(variants.len(), 2);
+    assert_eq!(variants[0].target, "x86_64-unknown-linux-gnu");
+    assert_eq!(variants[0].sha256, digests[0]);
+    assert_eq!(variants[1].sha256, digests[1]);
+    assert!(variants[1].source.ends_with("eza_x86_64-unknown-linux-musl.tar.gz"));
+
+    // Importing again updates the entries in place
+    import::import(&request(&fixture.artifactory_path()), &release).await?;
+    let artifactory = schema::parse_artifactory(&std::fs::read_to_string(fixture.artifactory_path())?)?;
+    assert_eq!(artifactory.apps.len(), 1);
+    assert_eq!(artifactory.apps[0].packages[0].variants.len(), 2);
+
+    // Downloads are removed even when an asset cannot be read
+    std::fs::write(assets.path().join("broken.tar.gz"), "not an archive")?;
+    let broken = Release {
+        tag_name: "v1.0.1".to_string(),
+        assets: vec![ReleaseAsset {
+            name: "eza_aarch64-unknown-linux-gnu.tar.gz".to_string(),
+            browser_download_url: format!("file://{}", assets.path().join("broken.tar.gz").display()),
+        }],
+    };
+    assert!(import::import(&request(&fixture.artifactory_path()), &broken).await.is_err());
+    let pid = std::process::id().to_string();
+    let leaked = std::fs::read_dir(std::env::temp_dir())?.filter_map(|entry| entry.ok()).any(|entry| {
+        let name = entry.file_name().to_string_lossy().to_string();
+        name.strip_prefix("diem-import-").and_then(|rest| rest.split('-').next()) == Some(pid.as_str())
+    });
+    assert!(!leaked, "download directory left behind");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn rejects_releases_without_matching_assets() -> anyhow::Result<()> {
+    let root = tempfile::tempdir()?;
+    let fixture = FixtureProvider::new(root.path(), "imported").unsigned();
+    fixture.provider()?;
+
+    let release = Release {
+        tag_name: "v1.0.0".to_string(),
+        assets: vec![ReleaseAsset {
+            name: "sha256sums.txt".to_string(),
+            browser_download_url: "file:///nonexistent".to_string(),
+        }],
+    };
+
+    assert!(import::import(&request(&fixture.artifactory_path()), &release).await.is_err());
+    Ok(())
+}
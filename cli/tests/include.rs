@@ -0,0 +1,155 @@
+use std::path::Path;
+
+use diem::{
+    PackageManager,
+    artifactory::{
+        include::{self, Include, Location, Trust},
+        validate,
+    },
+    testing::{ArchiveBuilder, FixtureProvider, MinisignKey},
+};
+
+// Writes a club artifactory under `root/clubs/<name>` with a single app
+fn club(root: &Path, name: &str, app: &str, version: &str) -> anyhow::Result<FixtureProvider> {
+    let mut fixture = FixtureProvider::new(&root.join("clubs").join(name), name);
+    let archive = ArchiveBuilder::new().executable(format!("bin/{}", app), format!("#!/bin/sh\necho {}\n", name));
+    let package = fixture.add_package(app, version, &archive)?;
+    fixture.add_app(package, &[(app, &format!("bin/{}", app))]);
+    Ok(fixture)
+}
+
+fn include(source: &str) -> Include {
+    Include::Source(source.to_string())
+}
+
+#[tokio::test]
+async fn merges_included_files_and_installs_from_them() -> anyhow::Result<()> {
+    let root = tempfile::tempdir()?;
+    let install_dir = tempfile::tempdir()?;
+
+    club(root.path(), "ai", "hello", "1.0.0")?.provider()?;
+    club(root.path(), "games", "tetris", "2.0.0")?.provider()?;
+    let provider = FixtureProvider::new(root.path(), "campus")
+        .include(include("clubs/ai/artifactory.toml"))
+        .include(include("clubs/games/artifactory.toml"))
+        .provider()?;
+
+    let artifactory = provider.fetch_composed_artifactory(None).await?;
+    let names: Vec<&str> = artifactory.apps.iter().map(|app| app.name.as_str()).collect();
+    assert_eq!(names, ["hello", "tetris"]);
+
+    // Package sources point next to the club file that declared them
+    let package = &artifactory.apps[0].packages[0];
+    let source = package.source.as_deref().unwrap_or_default();
+    assert_eq!(Path::new(source), root.path().join("clubs/ai/packages/hello-1.0.0.tar.gz"));
+
+    let package_manager = PackageManager::new(install_dir.path().to_path_buf());
+    package_manager.install_package(package, &provider).await?;
+    let script = std::fs::read_to_string(package_manager.get_package_dir("hello", &package.version).join("bin/hello"))?;
+    assert!(script.contains("echo ai"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn reports_collisions_and_keeps_the_first_definition() -> anyhow::Result<()> {
+    let root = tempfile::tempdir()?;
+
+    club(root.path(), "ai", "hello", "1.0.0")?.provider()?;
+    club(root.path(), "games", "hello", "1.0.0")?.provider()?;
+    let campus = FixtureProvider::new(root.path(), "campus")
+        .include(include("clubs/ai/artifactory.toml"))
+        .include(include("clubs/games/artifactory.toml"));
+    let provider = campus.provider()?;
+
+    let artifactory = diem::schema::parse_artifactory(&std::fs::read_to_string(campus.artifactory_path())?)?;
+    let composition = include::compose(artifactory, &provider.location(), Trust::Unchecked).await?;
+    assert_eq!(composition.artifactory.apps.len(), 1);
+    assert_eq!(composition.collisions.len(), 1);
+    assert!(composition.collisions[0].kept.contains("ai"));
+    assert!(composition.collisions[0].ignored.contains("games"));
+
+    let report = validate::validate_file(&campus.artifactory_path()).await?;
+    assert_eq!(report.errors(), 1);
+    assert!(report.issues[0].message.contains("Defined by both"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn checks_included_signatures() -> anyhow::Result<()> {
+    let root = tempfile::tempdir()?;
+
+    let club_key = MinisignKey::from_seed(2);
+    club(root.path(), "ai", "hello", "1.0.0")?.signed_with(MinisignKey::from_seed(2)).provider()?;
+
+    // The club is not signed with the campus key
+    let provider = FixtureProvider::new(root.path(), "campus")
+        .include(include("clubs/ai/artifactory.toml"))
+        .provider()?;
+    assert!(provider.fetch_composed_artifactory(None).await.is_err());
+
+    // Unless the include pins the club's own key
+    let provider = FixtureProvider::new(root.path(), "campus")
+        .include(Include::Pinned {
+            source: "clubs/ai/artifactory.toml".to_string(),
+            public_key: Some(club_key.public_key()),
+        })
+        .provider()?;
+    assert_eq!(provider.fetch_composed_artifactory(None).await?.apps.len(), 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn checks_includes_against_the_key_the_root_rotated_to() -> anyhow::Result<()> {
+    let root = tempfile::tempdir()?;
+    let new_key = MinisignKey::from_seed(3);
+
+    club(root.path(), "ai", "hello", "1.0.0")?.signed_with(MinisignKey::from_seed(3)).provider()?;
+    let campus = FixtureProvider::new(root.path(), "campus").include(include("clubs/ai/artifactory.toml"));
+    let pinned = campus.provider()?;
+    campus.rotate_key(MinisignKey::from_seed(3)).provider()?;
+
+    // Subscribers still pin the old key, and follow the rotation
+    let loaded = pinned.fetch_composed_index(None).await?;
+    assert_eq!(loaded.artifactory.apps.len(), 1);
+    assert_eq!(loaded.rotated_key.as_deref().map(str::trim), Some(new_key.public_key().trim()));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn rejects_include_cycles() -> anyhow::Result<()> {
+    let root = tempfile::tempdir()?;
+
+    club(root.path(), "ai", "hello", "1.0.0")?
+        .include(include("../../artifactory.toml"))
+        .provider()?;
+    let provider = FixtureProvider::new(root.path(), "campus")
+        .include(include("clubs/ai/artifactory.toml"))
+        .provider()?;
+
+    let error = provider.fetch_composed_artifactory(None).await.unwrap_err();
+    assert!(error.to_string().contains("nested") || error.to_string().contains("cycle"), "{}", error);
+
+    Ok(())
+}
+
+#[test]
+fn resolves_references_against_urls() {
+    let base = Location::parse("https://raw.githubusercontent.com/campus/diem/main/index/artifactory.toml");
+
+    assert_eq!(
+        base.join("../clubs/ai.toml"),
+        Location::Url("https://raw.githubusercontent.com/campus/diem/main/clubs/ai.toml".to_string())
+    );
+    assert_eq!(
+        base.join("./ai.toml"),
+        Location::Url("https://raw.githubusercontent.com/campus/diem/main/index/ai.toml".to_string())
+    );
+    assert_eq!(
+        base.join("https://example.org/a.toml"),
+        Location::Url("https://example.org/a.toml".to_string())
+    );
+}
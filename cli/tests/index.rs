@@ -0,0 +1,115 @@
+use diem::{
+    Provider,
+    artifactory::{
+        include::Include,
+        index::{self, IndexCache, IndexSource},
+    },
+    testing::{ArchiveBuilder, FixtureProvider},
+};
+
+fn cached_files(cache: &IndexCache) -> anyhow::Result<Vec<std::path::PathBuf>> {
+    let mut files = std::fs::read_dir(cache.dir())?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    files.sort();
+    Ok(files)
+}
+
+fn add_app(fixture: &mut FixtureProvider, name: &str) -> anyhow::Result<()> {
+    let archive = ArchiveBuilder::new().executable(format!("bin/{}", name), "#!/bin/sh\n");
+    let package = fixture.add_package(name, "1.0.0", &archive)?;
+    fixture.add_app(package, &[(name, &format!("bin/{}", name))]);
+    Ok(())
+}
+
+async fn load(provider: &Provider, cache: &IndexCache) -> anyhow::Result<Vec<String>> {
+    let artifactory = provider.fetch_composed_artifactory(Some(cache)).await?;
+    Ok(artifactory.apps.into_iter().map(|app| app.name).collect())
+}
+
+#[tokio::test]
+async fn reuses_the_index_until_the_source_changes() -> anyhow::Result<()> {
+    let root = tempfile::tempdir()?;
+    let cache_dir = tempfile::tempdir()?;
+    let cache = IndexCache::new(cache_dir.path().to_path_buf());
+
+    let mut fixture = FixtureProvider::new(root.path(), "campus");
+    add_app(&mut fixture, "hello")?;
+    let provider = fixture.provider()?;
+
+    assert_eq!(load(&provider, &cache).await?, ["hello"]);
+    let files = cached_files(&cache)?;
+    assert_eq!(files.len(), 1);
+    let modified = std::fs::metadata(&files[0])?.modified()?;
+
+    // A second load is served from the cache without rewriting it
+    assert_eq!(load(&provider, &cache).await?, ["hello"]);
+    assert_eq!(cached_files(&cache)?, files);
+    assert_eq!(std::fs::metadata(&files[0])?.modified()?, modified);
+
+    add_app(&mut fixture, "world")?;
+    let provider = fixture.provider()?;
+    assert_eq!(load(&provider, &cache).await?, ["hello", "world"]);
+    assert_eq!(cached_files(&cache)?.len(), 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn recompiles_when_an_included_file_changes() -> anyhow::Result<()> {
+    let root = tempfile::tempdir()?;
+    let cache_dir = tempfile::tempdir()?;
+    let cache = IndexCache::new(cache_dir.path().to_path_buf());
+
+    let mut club = FixtureProvider::new(&root.path().join("club"), "club");
+    add_app(&mut club, "hello")?;
+    club.provider()?;
+    let provider = FixtureProvider::new(root.path(), "campus")
+        .include(Include::Source("club/artifactory.toml".to_string()))
+        .provider()?;
+
+    assert_eq!(load(&provider, &cache).await?, ["hello"]);
+
+    add_app(&mut club, "world")?;
+    club.provider()?;
+    assert_eq!(load(&provider, &cache).await?, ["hello", "world"]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn ignores_corrupt_indexes_and_never_caches_failures() -> anyhow::Result<()> {
+    let root = tempfile::tempdir()?;
+    let cache_dir = tempfile::tempdir()?;
+    let cache = IndexCache::new(cache_dir.path().to_path_buf());
+
+    let mut fixture = FixtureProvider::new(root.path(), "campus");
+    add_app(&mut fixture, "hello")?;
+    let provider = fixture.provider()?;
+
+    load(&provider, &cache).await?;
+    for file in cached_files(&cache)? {
+        std::fs::write(file, b"not an index")?;
+    }
+    assert_eq!(load(&provider, &cache).await?, ["hello"]);
+
+    // Tampering breaks the signature, and the failure is not cached
+    let path = fixture.artifactory_path();
+    let content = std::fs::read_to_string(&path)?.replace("hello", "evil");
+    std::fs::write(&path, &content)?;
+    assert!(load(&provider, &cache).await.is_err());
+    assert_eq!(cached_files(&cache)?.len(), 1);
+
+    let source = IndexSource {
+        name: "campus",
+        location: provider.location(),
+        content,
+        signature: None,
+        public_key: None,
+        allow_unsigned: true,
+    };
+    let artifactory = index::load(Some(&cache), &source).await?;
+    assert_eq!(artifactory.apps[0].name, "evil");
+
+    Ok(())
+}
@@ -0,0 +1,85 @@
+use diem::{
+    Catalog, Config, app::{self, info}, artifactory::{include::Location, index::IndexCache},
+    testing::{ArchiveBuilder, FixtureProvider},
+};
+use semver::Version;
+
+#[test]
+fn parses_app_specs() -> anyhow::Result<()> {
+    assert_eq!(app::parse_spec("eza")?, ("eza".to_string(), None));
+    assert_eq!(app::parse_spec("eza@0.20.16")?, ("eza".to_string(), Some(Version::new(0, 20, 16))));
+    assert!(app::parse_spec("eza@latest").is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn lists_versions_newest_first() -> anyhow::Result<()> {
+    let artifactory_dir = tempfile::tempdir()?;
+    let cache_dir = tempfile::tempdir()?;
+    let install_dir = tempfile::tempdir()?;
+
+    let mut fixture = FixtureProvider::new(artifactory_dir.path(), "fixtures");
+    for version in ["1.0.0", "1.2.0", "1.1.0"] {
+        let archive = ArchiveBuilder::new().executable("bin/hello", format!("#!/bin/sh\necho {}\n", version));
+        let package = fixture.add_package("hello", version, &archive)?;
+        fixture.add_app(package, &[("hello", "bin/hello")]);
+    }
+
+    let mut config = Config::for_tests(install_dir.path().to_path_buf());
+    config.providers.push(fixture.provider()?);
+    let catalog = Catalog::new_from_config(&config).with_index_cache(Some(IndexCache::new(cache_dir.path().to_path_buf())));
+    let offers = catalog.find_app_offers("hello").await;
+
+    let versions: Vec<String> = offers.iter().map(|offer| offer.app.version.to_string()).collect();
+    assert_eq!(versions, ["1.2.0", "1.1.0", "1.0.0"]);
+    assert_eq!(offers[0].source, "Provider: fixture:fixtures");
+    assert_eq!(offers[0].provider.location(), Location::Path(fixture.artifactory_path()));
+
+    assert_eq!(info::select(&offers, None).map(|offer| &offer.app.version), Some(&Version::new(1, 2, 0)));
+    let pinned = info::select(&offers, Some(&Version::new(1, 1, 0))).expect("1.1.0 is offered");
+    assert_eq!(pinned.app.version, Version::new(1, 1, 0));
+    assert!(info::select(&offers, Some(&Version::new(2, 0, 0))).is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn measures_local_download_sizes() -> anyhow::Result<()> {
+    let artifactory_dir = tempfile::tempdir()?;
+
+    let fixture = FixtureProvider::new(artifactory_dir.path(), "fixtures");
+    let archive = ArchiveBuilder::new().file("README.md", "hello ".repeat(100));
+    let package = fixture.add_package("hello", "1.0.0", &archive)?;
+
+    let archive_path = artifactory_dir.path().join(package.source.as_deref().expect("universal package"));
+    let location = Location::Path(fixture.artifactory_path());
+    let size = info::download_size(&package, Some(&location)).await;
+    assert_eq!(size, Some(std::fs::metadata(archive_path)?.len()));
+
+    // Without the artifactory's location the relative source cannot be found
+    assert_eq!(info::download_size(&package, None).await, None);
+
+    Ok(())
+}
+
+#[test]
+fn reads_installed_versions() -> anyhow::Result<()> {
+    let install_dir = tempfile::tempdir()?;
+    for dir in ["hello/1.0.0", "hello/1.10.0", "hello/1.2.0", "hello/not-a-version"] {
+        std::fs::create_dir_all(install_dir.path().join(dir))?;
+    }
+
+    let versions = info::installed_versions(install_dir.path(), "hello");
+    assert_eq!(versions, [Version::new(1, 10, 0), Version::new(1, 2, 0), Version::new(1, 0, 0)]);
+    assert!(info::installed_versions(install_dir.path(), "missing").is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn formats_sizes() {
+    assert_eq!(info::format_size(512), "512 B");
+    assert_eq!(info::format_size(1536), "1.5 KiB");
+    assert_eq!(info::format_size(5 * 1024 * 1024), "5.0 MiB");
+}
@@ -0,0 +1,50 @@
+use diem::{
+    PackageManager,
+    testing::{ArchiveBuilder, FixtureProvider},
+};
+
+#[tokio::test]
+async fn installs_package_from_fixture_provider() -> anyhow::Result<()> {
+    let artifactory_dir = tempfile::tempdir()?;
+    let install_dir = tempfile::tempdir()?;
+
+    let mut fixture = FixtureProvider::new(artifactory_dir.path(), "fixtures");
+    let archive = ArchiveBuilder::new()
+        .executable("bin/hello", "#!/bin/sh\necho \"Hello from diem!\"\n")
+        .file("README.md", "hello");
+    let package = fixture.add_package("hello", "1.0.0", &archive)?;
+    fixture.add_app(package.clone(), &[("hello", "bin/hello")]);
+    let provider = fixture.provider()?;
+
+    let package_manager = PackageManager::new(install_dir.path().to_path_buf());
+    package_manager.install_package(&package, &provider).await?;
+
+    let package_dir = package_manager.get_package_dir("hello", &package.version);
+    let script = std::fs::read_to_string(package_dir.join("bin/hello"))?;
+    assert!(script.contains("Hello from diem!"));
+    assert!(package_dir.join("README.md").is_file());
+    assert!(!package_dir.join("package.tmp").exists());
+    assert!(package_manager.is_package_installed("hello", Some("1.0.0")).await);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn rejects_package_with_bad_checksum() -> anyhow::Result<()> {
+    let artifactory_dir = tempfile::tempdir()?;
+    let install_dir = tempfile::tempdir()?;
+
+    let fixture = FixtureProvider::new(artifactory_dir.path(), "fixtures");
+    let archive = ArchiveBuilder::new().executable("bin/hello", "#!/bin/sh\n");
+    let mut package = fixture.add_package("hello", "1.0.0", &archive)?;
+    package.sha256 = "0".repeat(64);
+    let provider = fixture.provider()?;
+
+    let package_manager = PackageManager::new(install_dir.path().to_path_buf());
+    let result = package_manager.install_package(&package, &provider).await;
+
+    assert!(result.is_err());
+    assert!(!package_manager.is_package_installed("hello", Some("1.0.0")).await);
+
+    Ok(())
+}
@@ -0,0 +1,51 @@
+#![cfg(unix)]
+
+use clap::Parser as _;
+use diem::{Cli, config::lock::Lock};
+
+use std::sync::mpsc;
+use std::time::Duration;
+
+#[test]
+fn waits_for_the_holder_of_the_lock() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("config/diem.lock");
+
+    let lock = Lock::acquire(&path, |_| panic!("nobody holds the lock"))?;
+    assert_eq!(std::fs::read_to_string(&path)?, std::process::id().to_string());
+    assert!(Lock::try_acquire(&path)?.is_none());
+
+    let (waiting, waited) = mpsc::channel();
+    let waiter = std::thread::spawn({
+        let path = path.clone();
+        move || Lock::acquire(&path, |holder| waiting.send(holder).expect("test is running")).map(drop)
+    });
+    assert_eq!(waited.recv_timeout(Duration::from_secs(10))?, Some(std::process::id()));
+    assert!(!waiter.is_finished());
+
+    drop(lock);
+    waiter.join().expect("waiter does not panic")?;
+    assert!(Lock::try_acquire(&path)?.is_some());
+
+    Ok(())
+}
+
+#[test]
+fn read_only_commands_do_not_lock() {
+    let mutating = |args: &[&str]| {
+        let cli = Cli::try_parse_from(std::iter::once("diem").chain(args.iter().copied())).expect("valid command");
+        cli.command.is_mutating()
+    };
+
+    assert!(mutating(&["install", "helix"]));
+    assert!(mutating(&["sync"]));
+    assert!(mutating(&["gc"]));
+    assert!(mutating(&["config", "set-goinfre", "/tmp"]));
+    assert!(mutating(&["artifactory", "unsubscribe", "club"]));
+
+    assert!(!mutating(&["gc", "--dry-run"]));
+    assert!(!mutating(&["search", "helix"]));
+    assert!(!mutating(&["du"]));
+    assert!(!mutating(&["config", "show"]));
+    assert!(!mutating(&["artifactory", "validate", "."]));
+}
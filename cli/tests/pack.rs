@@ -0,0 +1,147 @@
+use std::path::Path;
+
+use diem::{
+    PackageManager,
+    artifactory::{pack, validate},
+    platform::Platform,
+    schema,
+    testing::FixtureProvider,
+};
+
+fn write_build(dir: &Path, output: &str) -> anyhow::Result<()> {
+    std::fs::create_dir_all(dir.join("bin"))?;
+    std::fs::write(dir.join("bin/tool"), format!("#!/bin/sh\necho {}\n", output))?;
+    std::fs::write(dir.join("README.md"), "tool")?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(dir.join("bin/tool"), std::fs::Permissions::from_mode(0o755))?;
+    }
+
+    Ok(())
+}
+
+fn request(dir: &Path, artifactory: &Path, target: Option<&str>) -> anyhow::Result<pack::PackRequest> {
+    Ok(pack::PackRequest {
+        dir: dir.to_path_buf(),
+        artifactory: artifactory.to_path_buf(),
+        name: "tool".to_string(),
+        version: semver::Version::new(1, 2, 3),
+        target: target.map(str::to_string),
+        license: Some("MIT".to_string()),
+        description: Some("A tool".to_string()),
+        commands: vec![pack::parse_command("tool=bin/tool")?],
+    })
+}
+
+#[test]
+fn packs_reproducibly() -> anyhow::Result<()> {
+    let build = tempfile::tempdir()?;
+    let output = tempfile::tempdir()?;
+    write_build(build.path(), "hello")?;
+
+    let first = diem::package::archive::pack_directory(build.path(), &output.path().join("first.tar.gz"))?;
+
+    // Rewriting the files changes their mtimes but not the archive
+    write_build(build.path(), "hello")?;
+    let second = diem::package::archive::pack_directory(build.path(), &output.path().join("second.tar.gz"))?;
+
+    assert_eq!(first, second);
+    assert_eq!(
+        std::fs::read(output.path().join("first.tar.gz"))?,
+        std::fs::read(output.path().join("second.tar.gz"))?
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn registers_and_updates_variants_in_place() -> anyhow::Result<()> {
+    let build = tempfile::tempdir()?;
+    let root = tempfile::tempdir()?;
+    write_build(build.path(), "gnu")?;
+
+    let fixture = FixtureProvider::new(root.path(), "packed").unsigned();
+    fixture.provider()?;
+    let artifactory_path = fixture.artifactory_path();
+
+    pack::pack(&request(build.path(), &artifactory_path, Some("x86_64-unknown-linux-gnu"))?)?;
+    write_build(build.path(), "musl")?;
+    pack::pack(&request(build.path(), &artifactory_path, Some("x86_64-unknown-linux-musl"))?)?;
+
+    // Packing the same target again replaces its variant
+    write_build(build.path(), "gnu again")?;
+    let package = pack::pack(&request(build.path(), &artifactory_path, Some("x86_64-unknown-linux-gnu"))?)?;
+
+    let artifactory = schema::parse_artifactory(&std::fs::read_to_string(&artifactory_path)?)?;
+    assert_eq!(artifactory.apps.len(), 1);
+    assert_eq!(artifactory.apps[0].commands.len(), 1);
+    assert_eq!(artifactory.apps[0].packages[0].variants.len(), 2);
+    assert_eq!(package.variants[0].source, "packages/tool_x86_64-unknown-linux-gnu-v1.2.3.tar.gz");
+    assert!(root.path().join(&package.variants[1].source).is_file());
+
+    let report = validate::validate_file(&artifactory_path).await?;
+    assert!(report.issues.is_empty(), "{:?}", report.issues);
+
+    Ok(())
+}
+
+#[test]
+fn requires_license_for_new_apps() -> anyhow::Result<()> {
+    let build = tempfile::tempdir()?;
+    let root = tempfile::tempdir()?;
+    write_build(build.path(), "hello")?;
+
+    let fixture = FixtureProvider::new(root.path(), "packed").unsigned();
+    fixture.provider()?;
+
+    let mut request = request(build.path(), &fixture.artifactory_path(), None)?;
+    request.license = None;
+    assert!(pack::pack(&request).is_err());
+    let archive = fixture.artifactory_path().with_file_name("packages/tool-v1.2.3.tar.gz");
+    assert!(!archive.exists(), "no orphan archive");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn installs_a_packed_package() -> anyhow::Result<()> {
+    let build = tempfile::tempdir()?;
+    let root = tempfile::tempdir()?;
+    let install_dir = tempfile::tempdir()?;
+    write_build(build.path(), "hello")?;
+
+    let fixture = FixtureProvider::new(root.path(), "packed").unsigned();
+    let mut provider = fixture.provider()?;
+    provider.allow_unsigned = true;
+
+    let host = Platform::current().triple();
+    let package = pack::pack(&request(build.path(), &fixture.artifactory_path(), Some(&host))?)?;
+
+    let package_manager = PackageManager::new(install_dir.path().to_path_buf());
+    package_manager.install_package(&package, &provider).await?;
+
+    let package_dir = package_manager.get_package_dir("tool", &package.version);
+    assert!(std::fs::read_to_string(package_dir.join("bin/tool"))?.contains("hello"));
+
+    Ok(())
+}
+
+#[test]
+fn keeps_the_artifactory_format() -> anyhow::Result<()> {
+    let build = tempfile::tempdir()?;
+    let root = tempfile::tempdir()?;
+    write_build(build.path(), "hello")?;
+
+    let artifactory_path = root.path().join("artifactory.json");
+    std::fs::write(&artifactory_path, r#"{ "name": "packed", "public": true, "apps": [] }"#)?;
+
+    pack::pack(&request(build.path(), &artifactory_path, None)?)?;
+
+    let content = std::fs::read_to_string(&artifactory_path)?;
+    assert_eq!(schema::Format::detect(&content), schema::Format::Json);
+    assert_eq!(schema::parse_artifactory(&content)?.apps.len(), 1);
+
+    Ok(())
+}
@@ -0,0 +1,135 @@
+#![cfg(unix)]
+
+use diem::{
+    Config, PackageManager,
+    package::{
+        gc::{Collector, Garbage},
+        placement::{PlacementPolicy, Reason, Volume},
+    },
+    testing::{ArchiveBuilder, FixtureProvider},
+};
+
+use std::path::Path;
+
+const MIB: u64 = 1024 * 1024;
+
+fn config(root: &Path) -> Config {
+    Config::for_tests(root.join("home/packages")).with_volumes(root.join("sgoinfre"), root.join("goinfre"))
+}
+
+// Free space per volume, in MiB
+fn free(home: u64, sgoinfre: u64, goinfre: u64) -> impl Fn(&Path) -> Option<u64> {
+    move |path: &Path| {
+        let path = path.to_string_lossy();
+        let free = if path.contains("sgoinfre") {
+            sgoinfre
+        } else if path.contains("goinfre") {
+            goinfre
+        } else {
+            home
+        };
+        Some(free * MIB)
+    }
+}
+
+#[test]
+fn places_apps_by_size_and_free_space() -> anyhow::Result<()> {
+    let root = tempfile::tempdir()?;
+    let mut config = config(root.path());
+    let policy = config.placement.clone();
+
+    let small = policy.place(&config, "eza", MIB, free(1000, 1000, 1000))?;
+    assert_eq!((small.volume, small.reason), (Volume::Home, Reason::Small));
+    assert_eq!(small.packages_dir, config.install_dir);
+
+    let bulky = policy.place(&config, "vscode", 200 * MIB, free(1000, 1000, 1000))?;
+    assert_eq!((bulky.volume, bulky.reason), (Volume::Sgoinfre, Reason::Bulky));
+    assert_eq!(bulky.packages_dir, root.path().join("sgoinfre/packages"));
+
+    // Small apps leave the home directory when it is nearly full
+    let crowded = policy.place(&config, "eza", MIB, free(50, 1000, 1000))?;
+    assert_eq!((crowded.volume, crowded.reason), (Volume::Sgoinfre, Reason::Fallback(Volume::Home)));
+
+    let full = policy.place(&config, "vscode", 200 * MIB, free(1000, 100, 1000))?;
+    assert_eq!((full.volume, full.reason), (Volume::Goinfre, Reason::Fallback(Volume::Sgoinfre)));
+
+    let error = policy.place(&config, "vscode", 200 * MIB, free(100, 100, 100)).unwrap_err();
+    assert!(error.to_string().contains("No configured location"));
+
+    // Pinned apps ignore their size, but need their volume
+    config.placement.apps.insert("eza".to_string(), Volume::Goinfre);
+    let pinned = config.placement.place(&config, "eza", MIB, free(1000, 1000, 1000))?;
+    assert_eq!((pinned.volume, pinned.reason), (Volume::Goinfre, Reason::Pinned));
+    config.goinfre_dir = None;
+    assert!(config.placement.place(&config, "eza", MIB, free(1000, 1000, 1000)).is_err());
+
+    // Unknown free space is assumed to be enough
+    let unknown = policy.place(&config, "vscode", 200 * MIB, |_| None)?;
+    assert_eq!(unknown.volume, Volume::Sgoinfre);
+
+    Ok(())
+}
+
+#[test]
+fn reads_placement_from_older_configs() -> anyhow::Result<()> {
+    let root = tempfile::tempdir()?;
+    let mut config = config(root.path());
+    config.placement.apps.insert("vscode".to_string(), Volume::Goinfre);
+
+    let content = toml::to_string(&config)?;
+    assert!(content.contains("vscode = \"goinfre\""));
+    let parsed: Config = toml::from_str(&content)?;
+    assert_eq!(parsed.placement, config.placement);
+
+    let mut document: toml::Table = toml::from_str(&content)?;
+    document.remove("placement");
+    let parsed: Config = toml::Value::Table(document).try_into()?;
+    assert_eq!(parsed.placement, PlacementPolicy::default());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn links_packages_stored_on_another_volume() -> anyhow::Result<()> {
+    let artifactory_dir = tempfile::tempdir()?;
+    let root = tempfile::tempdir()?;
+    let config = config(root.path());
+    let stored_dir = root.path().join("sgoinfre/packages");
+
+    let fixture = FixtureProvider::new(artifactory_dir.path(), "fixtures");
+    let provider = fixture.provider()?;
+    let archive = |version: &str| {
+        ArchiveBuilder::new().executable("bin/hello", "#!/bin/sh\necho hello\n").file("VERSION", version.to_string())
+    };
+    let old = fixture.add_package("hello", "1.0.0", &archive("1.0.0"))?;
+    let new = fixture.add_package("hello", "2.0.0", &archive("2.0.0"))?;
+
+    let package_manager = PackageManager::new(config.install_dir.clone()).with_packages_dir(stored_dir.clone());
+    package_manager.install_package(&old, &provider).await?;
+    package_manager.install_package(&new, &provider).await?;
+
+    let package_dir = package_manager.get_package_dir("hello", &old.version);
+    assert_eq!(std::fs::read_link(&package_dir)?, stored_dir.join("hello/1.0.0"));
+    assert!(package_dir.join("bin/hello").is_file());
+    assert!(package_manager.store().root().starts_with(&stored_dir));
+
+    // A version left behind on the volume without its link
+    let orphan = stored_dir.join("hello/0.9.0");
+    std::fs::create_dir_all(&orphan)?;
+
+    let report = Collector::new(config.install_dir.clone())
+        .with_installed(std::slice::from_ref(&new))
+        .with_volume(stored_dir.clone())
+        .collect()?;
+    assert_eq!(report.count(Garbage::Version), 3, "link, its files and the orphan");
+    assert_eq!(report.count(Garbage::Manifest), 1);
+    assert!(!package_dir.exists() && !stored_dir.join("hello/1.0.0").exists() && !orphan.exists());
+    assert!(package_manager.get_package_dir("hello", &new.version).join("VERSION").is_file());
+
+    // Uninstalling removes the stored files as well
+    package_manager.uninstall_package("hello", None).await?;
+    assert!(!stored_dir.join("hello/2.0.0").exists());
+    assert!(!config.install_dir.join("hello").exists());
+
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use diem::{
+    PackageManager,
+    platform::{Libc, Platform},
+    testing::{ArchiveBuilder, FixtureProvider},
+};
+
+fn linux(arch: &str, libc: Libc) -> Platform {
+    Platform {
+        arch: arch.to_string(),
+        os: "linux".to_string(),
+        libc: Some(libc),
+    }
+}
+
+#[test]
+fn parses_target_triples() -> anyhow::Result<()> {
+    assert_eq!(Platform::parse("x86_64-unknown-linux-gnu")?, linux("x86_64", Libc::Gnu));
+    assert_eq!(Platform::parse("aarch64-unknown-linux-musl")?, linux("aarch64", Libc::Musl));
+    assert_eq!(Platform::parse("arm64-apple-darwin")?.triple(), "aarch64-apple-darwin");
+    assert!(Platform::parse("x86_64").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn prefers_matching_libc_and_falls_back_to_musl() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let fixture = FixtureProvider::new(dir.path(), "variants");
+    let package = fixture.add_variant_package("tool", "1.0.0", &[
+        ("x86_64-unknown-linux-musl", ArchiveBuilder::new().file("musl", "")),
+        ("x86_64-unknown-linux-gnu", ArchiveBuilder::new().file("gnu", "")),
+        ("aarch64-unknown-linux-musl", ArchiveBuilder::new().file("arm", "")),
+    ])?;
+
+    let (source, _) = package.artifact_for(&linux("x86_64", Libc::Gnu))?.unwrap();
+    assert!(source.contains("x86_64-unknown-linux-gnu"));
+
+    let (source, _) = package.artifact_for(&linux("x86_64", Libc::Musl))?.unwrap();
+    assert!(source.contains("x86_64-unknown-linux-musl"));
+
+    // A glibc host can run static musl builds
+    let (source, _) = package.artifact_for(&linux("aarch64", Libc::Gnu))?.unwrap();
+    assert!(source.contains("aarch64-unknown-linux-musl"));
+
+    Ok(())
+}
+
+#[test]
+fn fails_when_no_variant_matches() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let fixture = FixtureProvider::new(dir.path(), "variants");
+    let package = fixture.add_variant_package("tool", "1.0.0", &[
+        ("x86_64-unknown-linux-gnu", ArchiveBuilder::new().file("gnu", "")),
+    ])?;
+
+    let error = package.artifact_for(&linux("x86_64", Libc::Musl)).unwrap_err();
+    assert!(error.to_string().contains("x86_64-unknown-linux-musl"));
+    assert!(package.artifact_for(&linux("riscv64", Libc::Gnu)).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn skips_variants_for_unknown_targets() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let fixture = FixtureProvider::new(dir.path(), "variants");
+    let package = fixture.add_variant_package("tool", "1.0.0", &[
+        ("wasm32-unknown-unknown", ArchiveBuilder::new().file("wasm", "")),
+        ("x86_64-unknown-linux-gnu", ArchiveBuilder::new().file("gnu", "")),
+    ])?;
+
+    let (source, _) = package.artifact_for(&linux("x86_64", Libc::Gnu))?.unwrap();
+    assert!(source.contains("x86_64-unknown-linux-gnu"));
+    assert!(package.artifact_for(&linux("aarch64", Libc::Gnu)).is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn installs_the_variant_for_the_host() -> anyhow::Result<()> {
+    let artifactory_dir = tempfile::tempdir()?;
+    let install_dir = tempfile::tempdir()?;
+    let host = Platform::current().triple();
+
+    let fixture = FixtureProvider::new(artifactory_dir.path(), "variants");
+    let package = fixture.add_variant_package("tool", "1.0.0", &[
+        ("riscv64-unknown-linux-gnu", ArchiveBuilder::new().file("bin/tool", "riscv")),
+        (&host, ArchiveBuilder::new().executable("bin/tool", "host")),
+    ])?;
+    let provider = fixture.provider()?;
+
+    let package_manager = PackageManager::new(install_dir.path().to_path_buf());
+    package_manager.install_package(&package, &provider).await?;
+
+    let package_dir = package_manager.get_package_dir("tool", &package.version);
+    assert_eq!(std::fs::read_to_string(package_dir.join("bin/tool"))?, "host");
+
+    Ok(())
+}
@@ -0,0 +1,202 @@
+use diem::schema;
+
+const SAMPLE_ARTIFACTORY: &str = include_str!("../../artifactory.toml");
+
+// The sample artifactory as it was written before packages had variants
+const VERSION_ZERO_ARTIFACTORY: &str = r#"
+name = "R1's app collection"
+public = true
+artifactory_handler_version = 0
+
+[[apps]]
+name = "eza"
+version = "0.20.16"
+license = "EUPL-1.2"
+app_handler_version = 0
+
+[[apps.commands]]
+command = "eza"
+path = "eza"
+
+[[apps.packages]]
+name = "eza"
+version = "0.20.16"
+license = "EUPL-1.2"
+source = "packages/eza_x86_64-unknown-linux-gnu-v0.20.16.tar.gz"
+sha256 = "d2da926b465e889f812518abac66bf6b66278cb30bcd58af61182564757ece25"
+dependencies = []
+package_handler_version = 0
+
+[[apps.packages]]
+name = "eza-completions"
+version = "0.20.16"
+license = "EUPL-1.2"
+source = "packages/eza-completions-v0.20.16.tar.gz"
+sha256 = "0000000000000000000000000000000000000000000000000000000000000000"
+dependencies = []
+package_handler_version = 0
+"#;
+
+#[test]
+fn upgrades_version_zero_artifactory() -> anyhow::Result<()> {
+    let artifactory = schema::parse_artifactory(VERSION_ZERO_ARTIFACTORY)?;
+    assert_eq!(artifactory.artifactory_handler_version, schema::ARTIFACTORY_HANDLER_VERSION);
+
+    let app = &artifactory.apps[0];
+    assert_eq!(app.app_handler_version, schema::APP_HANDLER_VERSION);
+    assert_eq!(app.packages[0].package_handler_version, schema::PACKAGE_HANDLER_VERSION);
+
+    // A source built for a target becomes the variant for it
+    let (eza, completions) = (&app.packages[0], &app.packages[1]);
+    assert_eq!((eza.source.as_deref(), eza.sha256.as_str()), (None, ""));
+    assert_eq!(eza.variants.len(), 1);
+    assert_eq!(eza.variants[0].target, "x86_64-unknown-linux-gnu");
+    assert_eq!(eza.variants[0].source, "packages/eza_x86_64-unknown-linux-gnu-v0.20.16.tar.gz");
+    assert!(eza.variants[0].sha256.starts_with("d2da926b"));
+
+    // Others stay platform-independent
+    assert_eq!(completions.source.as_deref(), Some("packages/eza-completions-v0.20.16.tar.gz"));
+    assert!(completions.variants.is_empty());
+
+    // The sample is kept at the current format
+    let sample = schema::parse_artifactory(SAMPLE_ARTIFACTORY)?;
+    assert!(SAMPLE_ARTIFACTORY.contains(&format!("artifactory_handler_version = {}", schema::ARTIFACTORY_HANDLER_VERSION)));
+    assert_eq!(toml::to_string(&sample.apps[0].packages[0])?, toml::to_string(eza)?);
+
+    Ok(())
+}
+
+#[test]
+fn treats_missing_version_as_zero() -> anyhow::Result<()> {
+    let artifactory = schema::parse_artifactory("name = \"old\"\npublic = true\napps = []\n")?;
+    assert_eq!(artifactory.artifactory_handler_version, schema::ARTIFACTORY_HANDLER_VERSION);
+
+    Ok(())
+}
+
+#[test]
+fn rejects_newer_artifactory() {
+    let newer = SAMPLE_ARTIFACTORY.replace(
+        &format!("artifactory_handler_version = {}", schema::ARTIFACTORY_HANDLER_VERSION),
+        &format!("artifactory_handler_version = {}", schema::ARTIFACTORY_HANDLER_VERSION + 1),
+    );
+
+    let error = schema::parse_artifactory(&newer).unwrap_err();
+    assert!(error.to_string().contains("update diem"));
+}
+
+#[test]
+fn rejects_newer_nested_package() {
+    let newer = SAMPLE_ARTIFACTORY.replace(
+        &format!("package_handler_version = {}", schema::PACKAGE_HANDLER_VERSION),
+        &format!("package_handler_version = {}", schema::PACKAGE_HANDLER_VERSION + 1),
+    );
+
+    let error = schema::parse_artifactory(&newer).unwrap_err();
+    assert!(error.to_string().contains("Package 'eza'"));
+}
+
+#[test]
+fn upgrades_config_providers() -> anyhow::Result<()> {
+    let mut document: toml::Table = toml::from_str(
+        r#"
+        packages = []
+        install_dir = "/tmp/diem"
+        subscribed_artifactories = []
+        config_handler_version = 0
+
+        [[providers]]
+        name = "github:owner/repo@main:artifactory.toml"
+        provider_handler_version = 0
+
+        [providers.source.Github]
+        owner = "owner"
+        repo = "repo"
+        ref_ = "main"
+        path = "artifactory.toml"
+        "#,
+    )?;
+
+    assert!(schema::upgrade_config(&mut document)?);
+    assert!(!schema::upgrade_config(&mut document)?);
+
+    let config: diem::Config = toml::Value::Table(document).try_into()?;
+    assert_eq!(config.config_handler_version, schema::CONFIG_HANDLER_VERSION);
+    assert_eq!(config.providers[0].provider_handler_version, schema::PROVIDER_HANDLER_VERSION);
+
+    Ok(())
+}
+
+#[test]
+fn parses_json_and_yaml_artifactories() -> anyhow::Result<()> {
+    let expected = schema::parse_artifactory(SAMPLE_ARTIFACTORY)?;
+    let document = schema::Format::Toml.parse_table(SAMPLE_ARTIFACTORY)?;
+
+    for format in [schema::Format::Json, schema::Format::Yaml] {
+        let content = format.serialize(&document)?;
+        assert_eq!(schema::Format::detect(&content), format);
+
+        let artifactory = schema::parse_artifactory(&content)?;
+        assert_eq!(toml::to_string(&artifactory)?, toml::to_string(&expected)?);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn round_trips_nulls_through_json_and_yaml() -> anyhow::Result<()> {
+    let artifactory = schema::parse_artifactory(SAMPLE_ARTIFACTORY)?;
+
+    for format in [schema::Format::Toml, schema::Format::Json, schema::Format::Yaml] {
+        let content = format.serialize(&artifactory)?;
+        let parsed = schema::parse_artifactory_as(&content, format)?;
+        assert_eq!(parsed.maintainer, artifactory.maintainer);
+        assert_eq!(parsed.apps[0].packages[0].variants.len(), 1);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn detects_formats_from_extensions() {
+    use std::path::Path;
+
+    assert_eq!(schema::Format::from_path(Path::new("a.toml")), Some(schema::Format::Toml));
+    assert_eq!(schema::Format::from_path(Path::new("a.json")), Some(schema::Format::Json));
+    assert_eq!(schema::Format::from_path(Path::new("a.YML")), Some(schema::Format::Yaml));
+    assert_eq!(schema::Format::from_path(Path::new("artifactory")), None);
+    assert_eq!(schema::Format::detect("name: campus\npublic: true\napps: []\n"), schema::Format::Yaml);
+    assert_eq!(schema::Format::detect("name = \"campus\"\n"), schema::Format::Toml);
+}
+
+#[test]
+fn parses_optional_app_metadata() -> anyhow::Result<()> {
+    let without = schema::parse_artifactory(SAMPLE_ARTIFACTORY)?;
+    assert!(without.apps[0].tags.is_empty());
+    assert!(!toml::to_string(&without)?.contains("tags"));
+
+    let content = SAMPLE_ARTIFACTORY.replace(
+        "app_handler_version = 1\n",
+        "app_handler_version = 1\n\
+         tags = [\"ls\", \"files\"]\n\
+         categories = [\"utilities\"]\n\
+         homepage = \"https://eza.rocks\"\n\
+         repository = \"https://github.com/eza-community/eza\"\n\
+         maintainers = [\"R1\"]\n\
+         readme = \"\"\"\n# eza\n\nA modern ls.\n\"\"\"\n",
+    );
+    let artifactory = schema::parse_artifactory(&content)?;
+    let app = &artifactory.apps[0];
+    assert_eq!(app.tags, ["ls", "files"]);
+    assert_eq!(app.categories, ["utilities"]);
+    assert_eq!(app.homepage.as_deref(), Some("https://eza.rocks"));
+    assert_eq!(app.maintainers, ["R1"]);
+    assert_eq!(app.readme.as_deref(), Some("# eza\n\nA modern ls.\n"));
+
+    let yaml = schema::Format::Yaml.serialize(&artifactory)?;
+    let parsed = schema::parse_artifactory_as(&yaml, schema::Format::Yaml)?;
+    assert_eq!(parsed.apps[0].readme, app.readme);
+    assert_eq!(parsed.apps[0].repository, app.repository);
+
+    Ok(())
+}
@@ -0,0 +1,168 @@
+use diem::{
+    Artifactory,
+    artifactory::search::{self, Field, MatchMode, SearchQuery},
+    schema,
+};
+
+fn app(name: &str, version: &str, command: &str, description: &str) -> String {
+    tagged_app(name, version, command, description, &[])
+}
+
+fn tagged_app(name: &str, version: &str, command: &str, description: &str, tags: &[&str]) -> String {
+    let tags: Vec<String> = tags.iter().map(|tag| format!("\"{}\"", tag)).collect();
+    let tags = tags.join(", ");
+    format!(
+        r#"
+[[apps]]
+name = "{name}"
+version = "{version}"
+license = "MIT"
+description = "{description}"
+app_handler_version = 1
+packages = []
+tags = [{tags}]
+
+[[apps.commands]]
+command = "{command}"
+path = "bin/{command}"
+"#
+    )
+}
+
+fn artifactory(name: &str, apps: &[String]) -> anyhow::Result<Artifactory> {
+    let content = format!(
+        "name = \"{}\"\npublic = true\nartifactory_handler_version = {}\n{}",
+        name,
+        schema::ARTIFACTORY_HANDLER_VERSION,
+        apps.concat()
+    );
+    schema::parse_artifactory(&content)
+}
+
+fn catalog() -> anyhow::Result<Vec<Artifactory>> {
+    Ok(vec![
+        artifactory("tools", &[
+            app("ripgrep", "14.1.0", "rg", "Recursively search directories for a regex pattern"),
+            tagged_app("grepper", "0.1.0", "grepper", "Find things", &["search", "cli"]),
+            app("eza", "0.20.16", "eza", "A modern ls"),
+            app("eza", "0.19.0", "eza", "A modern ls"),
+        ])?,
+        artifactory("club", &[
+            app("fd", "10.2.0", "fd", "A simple alternative to find, faster than grep -r"),
+            tagged_app("helix", "25.1.0", "hx", "A post-modern modal editor", &["editor", "CLI"]),
+        ])?,
+    ])
+}
+
+fn names(hits: &[search::SearchHit]) -> Vec<&str> {
+    hits.iter().map(|hit| hit.name.as_str()).collect()
+}
+
+#[test]
+fn ranks_name_matches_first() -> anyhow::Result<()> {
+    let catalog = catalog()?;
+    let query = SearchQuery::new("grep", MatchMode::Fuzzy, false)?;
+    let hits = search::search(&catalog, &query);
+
+    // A name prefix beats a name containing the query, which beats a description
+    assert_eq!(names(&hits)[..3], ["grepper", "ripgrep", "fd"]);
+    assert_eq!(hits[0].field, Field::Name);
+    assert_eq!(hits[2].field, Field::Description);
+    assert_eq!(hits[2].artifactory, "club");
+
+    Ok(())
+}
+
+#[test]
+fn fuzzy_matches_tolerate_gaps() -> anyhow::Result<()> {
+    let catalog = catalog()?;
+
+    let fuzzy = search::search(&catalog, &SearchQuery::new("rpgrp", MatchMode::Fuzzy, false)?);
+    assert_eq!(names(&fuzzy).first(), Some(&"ripgrep"));
+
+    let exact = search::search(&catalog, &SearchQuery::new("rpgrp", MatchMode::Exact, false)?);
+    assert!(exact.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn matches_commands() -> anyhow::Result<()> {
+    let catalog = catalog()?;
+    let hits = search::search(&catalog, &SearchQuery::new("rg", MatchMode::Exact, false)?);
+
+    let ripgrep = hits.iter().find(|hit| hit.name == "ripgrep").expect("ripgrep should match its command");
+    assert_eq!(ripgrep.field, Field::Command);
+
+    Ok(())
+}
+
+#[test]
+fn lists_newest_version_first() -> anyhow::Result<()> {
+    let catalog = catalog()?;
+    let hits = search::search(&catalog, &SearchQuery::new("eza", MatchMode::Fuzzy, false)?);
+
+    let versions: Vec<String> = hits.iter().take(2).map(|hit| hit.version.to_string()).collect();
+    assert_eq!(versions, ["0.20.16", "0.19.0"]);
+    assert_eq!(hits[0].description.as_deref(), Some("A modern ls"));
+
+    Ok(())
+}
+
+#[test]
+fn respects_case_sensitivity() -> anyhow::Result<()> {
+    let catalog = catalog()?;
+
+    let insensitive = search::search(&catalog, &SearchQuery::new("MODERN", MatchMode::Exact, false)?);
+    assert_eq!(names(&insensitive), ["eza", "eza", "helix"]);
+
+    let sensitive = search::search(&catalog, &SearchQuery::new("MODERN", MatchMode::Exact, true)?);
+    assert!(sensitive.is_empty());
+
+    // The Kelvin sign lowercases to a shorter `k`
+    let catalog = vec![artifactory("kelvin", &[app("k", "1.0.0", "k", "K")])?];
+    let hits = search::search(&catalog, &SearchQuery::new("\u{212A}", MatchMode::Exact, false)?);
+    assert_eq!(names(&hits), ["k"]);
+
+    Ok(())
+}
+
+#[test]
+fn supports_regular_expressions() -> anyhow::Result<()> {
+    let catalog = catalog()?;
+    let hits = search::search(&catalog, &SearchQuery::new("^(fd|eza)$", MatchMode::Regex, false)?);
+    assert_eq!(names(&hits), ["eza", "eza", "fd"]);
+
+    let error = SearchQuery::new("(unclosed", MatchMode::Regex, false).err().expect("invalid regex");
+    assert!(error.to_string().contains("Invalid regular expression"));
+
+    Ok(())
+}
+
+#[test]
+fn matches_tags() -> anyhow::Result<()> {
+    let catalog = catalog()?;
+    let hits = search::search(&catalog, &SearchQuery::new("editor", MatchMode::Exact, false)?);
+
+    assert_eq!(names(&hits), ["helix"]);
+    assert_eq!(hits[0].field, Field::Tag);
+
+    Ok(())
+}
+
+#[test]
+fn filters_by_tag() -> anyhow::Result<()> {
+    let catalog = catalog()?;
+
+    // Without a pattern, every app carrying the tags is listed
+    let query = SearchQuery::new("", MatchMode::Fuzzy, false)?.with_tags(vec!["cli".to_string()]);
+    assert_eq!(names(&search::search(&catalog, &query)), ["grepper", "helix"]);
+
+    let query = SearchQuery::new("", MatchMode::Fuzzy, false)?.with_tags(vec!["cli".to_string(), "editor".to_string()]);
+    assert_eq!(names(&search::search(&catalog, &query)), ["helix"]);
+
+    let query = SearchQuery::new("grep", MatchMode::Fuzzy, false)?.with_tags(vec!["editor".to_string()]);
+    assert!(search::search(&catalog, &query).is_empty());
+
+    Ok(())
+}
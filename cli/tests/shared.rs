@@ -0,0 +1,139 @@
+#![cfg(unix)]
+
+use diem::{
+    PackageManager,
+    package::{
+        gc::{Collector, Garbage},
+        shared::{self, SharedTree},
+    },
+    testing::{ArchiveBuilder, FixtureProvider},
+};
+
+use std::os::unix::fs::PermissionsExt as _;
+use std::path::Path;
+
+fn mode(path: &Path) -> anyhow::Result<u32> {
+    Ok(std::fs::metadata(path)?.permissions().mode() & 0o777)
+}
+
+#[tokio::test]
+async fn links_packages_published_by_a_maintainer() -> anyhow::Result<()> {
+    let artifactory_dir = tempfile::tempdir()?;
+    let root = tempfile::tempdir()?;
+    let shared = SharedTree::new(root.path().join("sgoinfre/shared"));
+
+    let fixture = FixtureProvider::new(artifactory_dir.path(), "fixtures");
+    let provider = fixture.provider()?;
+    let archive = ArchiveBuilder::new().executable("bin/hello", "#!/bin/sh\necho hello\n").file("README", "hello");
+    let hello = fixture.add_package("hello", "1.0.0", &archive)?;
+
+    // The maintainer installs privately, then publishes
+    let maintainer = PackageManager::new(root.path().join("maintainer"));
+    maintainer.install_package(&hello, &provider).await?;
+    let installed_dir = maintainer.get_package_dir("hello", &hello.version);
+    std::os::unix::fs::symlink(installed_dir.join("bin/hello"), installed_dir.join("hi"))?;
+    assert!(shared.publish(&hello, &installed_dir)?);
+    assert!(!shared.publish(&hello, &installed_dir)?, "already published");
+
+    let published = shared.package_dir("hello", &hello.version);
+    assert_eq!(mode(&published.join("bin/hello"))? & 0o072, 0o050);
+    assert_eq!(mode(&published.join("README"))? & 0o062, 0o040);
+    assert_eq!(std::fs::read_link(published.join("hi"))?, published.join("bin/hello"));
+    assert_eq!(shared.find("hello", &hello.version, Some(&hello.sha256))?, Some(published.clone()));
+
+    // Other users link the published version
+    let user = PackageManager::new(root.path().join("user")).with_shared_tree(shared.clone());
+    user.install_package(&hello, &provider).await?;
+    let package_dir = user.get_package_dir("hello", &hello.version);
+    assert_eq!(std::fs::read_link(&package_dir)?, published);
+    assert!(shared::is_shared(&published));
+    assert!(!user.store().root().exists(), "nothing was downloaded");
+
+    // Collecting and uninstalling only remove the link
+    let newer = fixture.add_package("hello", "2.0.0", &archive)?;
+    let report = Collector::new(root.path().join("user")).with_installed(&[newer]).collect()?;
+    assert_eq!(report.count(Garbage::Version), 1);
+    assert!(std::fs::symlink_metadata(&package_dir).is_err() && published.join("README").is_file());
+
+    user.install_package(&hello, &provider).await?;
+    user.uninstall_package("hello", None).await?;
+    assert!(published.join("README").is_file());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn installs_privately_what_cannot_be_shared() -> anyhow::Result<()> {
+    let artifactory_dir = tempfile::tempdir()?;
+    let root = tempfile::tempdir()?;
+    let shared = SharedTree::new(root.path().join("shared"));
+
+    let fixture = FixtureProvider::new(artifactory_dir.path(), "fixtures");
+    let provider = fixture.provider()?;
+    let hello = fixture.add_package("hello", "1.0.0", &ArchiveBuilder::new().file("README", "hello"))?;
+    let eza = fixture.add_package("eza", "1.0.0", &ArchiveBuilder::new().file("README", "eza"))?;
+
+    let maintainer = PackageManager::new(root.path().join("maintainer"));
+    maintainer.install_package(&hello, &provider).await?;
+    shared.publish(&hello, &maintainer.get_package_dir("hello", &hello.version))?;
+
+    // A file anyone can change is not trusted
+    let readme = shared.package_dir("hello", &hello.version).join("README");
+    let published_mode = mode(&readme)?;
+    std::fs::set_permissions(&readme, std::fs::Permissions::from_mode(0o666))?;
+    let error = shared.find("hello", &hello.version, Some(&hello.sha256)).unwrap_err();
+    assert!(error.to_string().contains("writable by other users"));
+    std::fs::set_permissions(&readme, std::fs::Permissions::from_mode(published_mode))?;
+
+    // Nor is a directory above it anyone can change
+    let name_dir = root.path().join("shared/hello");
+    std::fs::set_permissions(&name_dir, std::fs::Permissions::from_mode(0o777))?;
+    let error = shared.find("hello", &hello.version, Some(&hello.sha256)).unwrap_err();
+    assert!(error.to_string().contains("writable by other users"));
+    std::fs::set_permissions(&name_dir, std::fs::Permissions::from_mode(0o755))?;
+    assert!(shared.find("hello", &hello.version, Some(&hello.sha256))?.is_some());
+
+    // Nor a version built from another archive
+    let error = shared.find("hello", &hello.version, Some(&eza.sha256)).unwrap_err();
+    assert!(error.to_string().contains("another archive"));
+
+    // Nor one changed since it was published
+    std::fs::set_permissions(&readme, std::fs::Permissions::from_mode(0o644))?;
+    std::fs::write(&readme, "hellO")?;
+    std::fs::set_permissions(&readme, std::fs::Permissions::from_mode(published_mode))?;
+    let error = shared.find("hello", &hello.version, Some(&hello.sha256)).unwrap_err();
+    assert!(error.to_string().contains("differ from what was published"));
+    std::fs::set_permissions(&readme, std::fs::Permissions::from_mode(0o644))?;
+    std::fs::write(&readme, "hello")?;
+    std::fs::set_permissions(&readme, std::fs::Permissions::from_mode(published_mode))?;
+
+    // Nor one without a record of what was published
+    let record = root.path().join("shared/.published/hello/1.0.0.json");
+    let record_content = std::fs::read(&record)?;
+    std::fs::remove_file(&record)?;
+    let error = shared.find("hello", &hello.version, Some(&hello.sha256)).unwrap_err();
+    assert!(error.to_string().contains("without a record"));
+    std::fs::write(&record, record_content)?;
+    assert!(shared.find("hello", &hello.version, Some(&hello.sha256))?.is_some());
+
+    // Nor a link out of the tree
+    let outside = root.path().join("outside");
+    std::fs::write(&outside, "mine")?;
+    std::os::unix::fs::symlink(&outside, shared.package_dir("hello", &hello.version).join("NOTES"))?;
+    let error = shared.find("hello", &hello.version, Some(&hello.sha256)).unwrap_err();
+    assert!(error.to_string().contains("outside the shared tree"));
+
+    let user = PackageManager::new(root.path().join("user")).with_shared_tree(shared.clone());
+    user.install_package(&hello, &provider).await?;
+    user.install_package(&eza, &provider).await?;
+    for package in [&hello, &eza] {
+        let package_dir = user.get_package_dir(&package.name, &package.version);
+        assert!(!std::fs::symlink_metadata(&package_dir)?.is_symlink());
+        assert!(package_dir.join("README").is_file());
+    }
+
+    assert!(shared.publish(&eza, &root.path().join("maintainer/eza/1.0.0")).is_err());
+    assert!(!shared.package_dir("eza", &eza.version).exists());
+
+    Ok(())
+}
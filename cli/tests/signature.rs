@@ -0,0 +1,141 @@
+use diem::{
+    ProviderSource, signature,
+    testing::{ArchiveBuilder, FixtureProvider, MinisignKey},
+};
+
+fn fixture_with_app(root: &std::path::Path) -> anyhow::Result<FixtureProvider> {
+    let mut fixture = FixtureProvider::new(root, "signed");
+    let archive = ArchiveBuilder::new().executable("bin/hello", "#!/bin/sh\n");
+    let package = fixture.add_package("hello", "1.0.0", &archive)?;
+    fixture.add_app(package, &[("hello", "bin/hello")]);
+    Ok(fixture)
+}
+
+#[tokio::test]
+async fn accepts_artifactory_signed_with_pinned_key() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let provider = fixture_with_app(dir.path())?.provider()?;
+
+    let content = provider.fetch_verified_artifactory().await?;
+    assert!(content.contains("hello"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn rejects_tampered_artifactory() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let provider = fixture_with_app(dir.path())?.provider()?;
+
+    let ProviderSource::Artifactory(artifactory) = &provider.source else {
+        unreachable!()
+    };
+    let content = std::fs::read_to_string(&artifactory.path)?;
+    std::fs::write(&artifactory.path, content.replace("1.0.0", "6.6.6"))?;
+
+    assert!(provider.fetch_verified_artifactory().await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn rejects_signature_from_another_key() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let mut provider = fixture_with_app(dir.path())?
+        .signed_with(MinisignKey::from_seed(2))
+        .provider()?;
+    provider.public_key = Some(MinisignKey::from_seed(1).public_key());
+
+    assert!(provider.fetch_verified_artifactory().await.is_err());
+
+    // A bad signature is never waved through, even when unsigned is allowed
+    provider.allow_unsigned = true;
+    assert!(provider.fetch_verified_artifactory().await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn rejects_unsigned_artifactory_unless_allowed() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let mut provider = fixture_with_app(dir.path())?.unsigned().provider()?;
+    provider.public_key = Some(MinisignKey::from_seed(1).public_key());
+
+    assert!(provider.fetch_verified_artifactory().await.is_err());
+
+    provider.allow_unsigned = true;
+    assert!(provider.fetch_verified_artifactory().await.is_ok());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn rejects_signed_artifactory_without_pinned_key() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let mut provider = fixture_with_app(dir.path())?.provider()?;
+    provider.public_key = None;
+
+    assert!(provider.fetch_verified_artifactory().await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn pins_declared_key_on_first_use() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let provider = fixture_with_app(dir.path())?.provider()?;
+    let content = provider.fetch_artifactory().await?;
+    let signature = provider.fetch_signature().await?;
+
+    let pinned = signature::pin_public_key("signed", &content, signature.as_deref(), None, false)?;
+    assert_eq!(pinned, Some(MinisignKey::from_seed(1).public_key()));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn refuses_to_pin_unsigned_artifactory() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let provider = fixture_with_app(dir.path())?.unsigned().provider()?;
+    let content = provider.fetch_artifactory().await?;
+
+    assert!(signature::pin_public_key("unsigned", &content, None, None, false).is_err());
+    assert_eq!(signature::pin_public_key("unsigned", &content, None, None, true)?, None);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn follows_signed_key_rotations() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let mut provider = fixture_with_app(dir.path())?
+        .rotate_key(MinisignKey::from_seed(2))
+        .rotate_key(MinisignKey::from_seed(3))
+        .provider()?;
+
+    // A subscriber that pinned the very first key still trusts the index
+    provider.public_key = Some(MinisignKey::from_seed(1).public_key());
+    assert!(provider.fetch_verified_artifactory().await.is_ok());
+
+    provider.public_key = Some(MinisignKey::from_seed(2).public_key());
+    assert!(provider.fetch_verified_artifactory().await.is_ok());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn rejects_key_change_without_rotation() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let mut provider = fixture_with_app(dir.path())?
+        .signed_with(MinisignKey::from_seed(2))
+        .rotate_key(MinisignKey::from_seed(3))
+        .provider()?;
+
+    // The rotation chain starts from key 2, so it does not vouch for key 3
+    // on behalf of a subscriber holding key 1
+    provider.public_key = Some(MinisignKey::from_seed(1).public_key());
+    let error = provider.fetch_verified_artifactory().await.unwrap_err();
+    assert!(error.to_string().contains("SIGNING KEY MISMATCH"));
+
+    Ok(())
+}
@@ -0,0 +1,167 @@
+#![cfg(unix)]
+
+use diem::{
+    PackageManager,
+    package::store::{self, Entry, Store},
+    testing::{ArchiveBuilder, FixtureProvider},
+};
+use semver::Version;
+
+use std::os::unix::fs::{MetadataExt as _, PermissionsExt as _};
+use std::path::Path;
+
+fn unpacked(dir: &Path, readme: &str) -> anyhow::Result<()> {
+    std::fs::create_dir_all(dir.join("bin"))?;
+    std::fs::write(dir.join("bin/hello"), "#!/bin/sh\necho hello\n")?;
+    std::fs::set_permissions(dir.join("bin/hello"), std::fs::Permissions::from_mode(0o755))?;
+    std::fs::write(dir.join("README.md"), readme)?;
+    std::os::unix::fs::symlink("bin/hello", dir.join("hi"))?;
+    Ok(())
+}
+
+#[test]
+fn materializes_packages_with_hardlinks() -> anyhow::Result<()> {
+    let root = tempfile::tempdir()?;
+    let store = Store::new(root.path().join("store"));
+
+    let staging = store.staging_dir()?;
+    unpacked(staging.path(), "hello")?;
+    let manifest = store.add("hello", &Version::new(1, 0, 0), "ABCD", staging.path())?;
+    assert!(store.manifest("abcd")?.is_some());
+    assert!(matches!(&manifest.entries[0], Entry::File { path, .. } if path == Path::new("README.md")));
+
+    let package_dir = root.path().join("packages/hello/1.0.0");
+    let placement = store.materialize(&manifest, &package_dir)?;
+    assert_eq!(placement.hardlinked, 2);
+    assert_eq!(placement.copied, 0);
+
+    let script = package_dir.join("bin/hello");
+    assert_eq!(std::fs::read_to_string(&script)?, "#!/bin/sh\necho hello\n");
+    assert_eq!(std::fs::metadata(&script)?.permissions().mode() & 0o777, 0o555);
+    assert_eq!(std::fs::metadata(&script)?.nlink(), 2);
+    assert_eq!(std::fs::read_link(package_dir.join("hi"))?, Path::new("bin/hello"));
+
+    // Staging is cleaned up once dropped
+    let staging_path = staging.path().to_path_buf();
+    drop(staging);
+    assert!(!staging_path.exists());
+
+    Ok(())
+}
+
+#[test]
+fn deduplicates_identical_files() -> anyhow::Result<()> {
+    let root = tempfile::tempdir()?;
+    let store = Store::new(root.path().to_path_buf());
+
+    let mut manifests = Vec::new();
+    for (version, readme) in [("1.0.0", "first"), ("1.1.0", "second")] {
+        let staging = store.staging_dir()?;
+        unpacked(staging.path(), readme)?;
+        manifests.push(store.add("hello", &Version::parse(version)?, version, staging.path())?);
+    }
+
+    let objects = |manifest: &diem::package::store::Manifest| -> Vec<String> {
+        manifest
+            .entries
+            .iter()
+            .filter_map(|entry| match entry {
+                Entry::File { object, .. } => Some(object.clone()),
+                _ => None,
+            })
+            .collect()
+    };
+    let (first, second) = (objects(&manifests[0]), objects(&manifests[1]));
+    assert_ne!(first[0], second[0], "READMEs differ");
+    assert_eq!(first[1], second[1], "scripts are shared");
+    assert!(first[1].ends_with("-x"));
+
+    let object_count = walk_files(&root.path().join("objects"))?;
+    assert_eq!(object_count, 3);
+
+    Ok(())
+}
+
+#[test]
+fn changes_modes_on_a_copy_of_the_object() -> anyhow::Result<()> {
+    let root = tempfile::tempdir()?;
+    let store = Store::new(root.path().join("store"));
+
+    let staging = store.staging_dir()?;
+    unpacked(staging.path(), "hello")?;
+    let manifest = store.add("hello", &Version::new(1, 0, 0), "abcd", staging.path())?;
+    let (first, second) = (root.path().join("packages/first"), root.path().join("packages/second"));
+    store.materialize(&manifest, &first)?;
+    store.materialize(&manifest, &second)?;
+
+    let readme = first.join("README.md");
+    store::unshare(&readme)?;
+    std::fs::set_permissions(&readme, std::fs::Permissions::from_mode(0o555))?;
+    assert_eq!(std::fs::read_to_string(&readme)?, "hello");
+    assert_eq!(std::fs::metadata(&readme)?.nlink(), 1);
+    assert_eq!(std::fs::metadata(second.join("README.md"))?.permissions().mode() & 0o777, 0o444);
+    assert_eq!(std::fs::metadata(second.join("README.md"))?.nlink(), 2, "the object and the other package");
+
+    Ok(())
+}
+
+#[test]
+fn leaves_nothing_behind_when_an_object_is_missing() -> anyhow::Result<()> {
+    let root = tempfile::tempdir()?;
+    let store = Store::new(root.path().join("store"));
+
+    let staging = store.staging_dir()?;
+    unpacked(staging.path(), "hello")?;
+    let manifest = store.add("hello", &Version::new(1, 0, 0), "abcd", staging.path())?;
+    let Entry::File { object, .. } = &manifest.entries[0] else {
+        panic!("first entry should be README.md");
+    };
+    std::fs::remove_file(store.object_path(object))?;
+
+    let package_dir = root.path().join("packages/hello/1.0.0");
+    let error = store.materialize(&manifest, &package_dir).unwrap_err();
+    assert!(error.to_string().contains("is missing"));
+    assert!(!package_dir.exists());
+    assert_eq!(std::fs::read_dir(root.path().join("packages/hello"))?.count(), 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn reinstalls_from_the_store_without_downloading() -> anyhow::Result<()> {
+    let artifactory_dir = tempfile::tempdir()?;
+    let install_dir = tempfile::tempdir()?;
+
+    let fixture = FixtureProvider::new(artifactory_dir.path(), "fixtures");
+    let archive = ArchiveBuilder::new().executable("bin/hello", "#!/bin/sh\necho hello\n");
+    let package = fixture.add_package("hello", "1.0.0", &archive)?;
+    let provider = fixture.provider()?;
+
+    let package_manager = PackageManager::new(install_dir.path().to_path_buf());
+    package_manager.install_package(&package, &provider).await?;
+    assert!(package_manager.store().manifest(&package.sha256)?.is_some());
+
+    // Remove both the installed package and the archive it came from
+    package_manager.uninstall_package("hello", Some("1.0.0")).await?;
+    std::fs::remove_file(artifactory_dir.path().join(package.source.as_deref().expect("universal package")))?;
+
+    package_manager.install_package(&package, &provider).await?;
+    let script = package_manager.get_package_dir("hello", &package.version).join("bin/hello");
+    assert!(script.is_file());
+    assert!(std::fs::read_dir(package_manager.store().root().join("tmp"))?.next().is_none());
+
+    Ok(())
+}
+
+fn walk_files(dir: &Path) -> anyhow::Result<usize> {
+    let mut count = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            count += walk_files(&entry.path())?;
+        } else {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
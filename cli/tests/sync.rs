@@ -0,0 +1,178 @@
+#![cfg(unix)]
+
+use diem::{
+    App, AppCommand, Config, Package,
+    config::sync::{self, SyncReport},
+    package::gc::{Collector, Garbage},
+};
+use semver::Version;
+
+use std::os::unix::fs::MetadataExt as _;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+#[test]
+fn copies_only_what_changed() -> anyhow::Result<()> {
+    let sgoinfre = tempfile::tempdir()?;
+    let goinfre = tempfile::tempdir()?;
+    let (source, mirror) = (sgoinfre.path(), sync::mirror_dir(goinfre.path()));
+
+    std::fs::create_dir_all(source.join("store/objects"))?;
+    std::fs::write(source.join("store/objects/hello"), "#!/bin/sh\necho hello\n")?;
+    std::fs::create_dir_all(source.join("packages/hello/1.0.0"))?;
+    std::fs::hard_link(source.join("store/objects/hello"), source.join("packages/hello/1.0.0/hello"))?;
+    std::fs::write(source.join("packages/hello/1.0.0/README"), "hello")?;
+    std::os::unix::fs::symlink("hello", source.join("packages/hello/1.0.0/hi"))?;
+    // Packages stored in goinfre itself are not part of the mirror
+    std::fs::create_dir_all(goinfre.path().join("packages/eza/1.0.0"))?;
+
+    assert!(sync::is_fresh(goinfre.path()));
+    let report = sync::sync(source, goinfre.path())?;
+    assert_eq!(report, SyncReport { copied: 3, hardlinked: 1, unchanged: 0, deleted: 0, bytes: 26, relinked: 0 });
+    let object = std::fs::metadata(mirror.join("store/objects/hello"))?;
+    assert_eq!(object.ino(), std::fs::metadata(mirror.join("packages/hello/1.0.0/hello"))?.ino());
+    assert_eq!(std::fs::read_link(mirror.join("packages/hello/1.0.0/hi"))?, Path::new("hello"));
+    assert!(!sync::is_fresh(goinfre.path()));
+
+    let report = sync::sync(source, goinfre.path())?;
+    assert_eq!(report, SyncReport { unchanged: 4, ..SyncReport::default() });
+
+    // A copy edited in goinfre is repaired even though its size is the same
+    std::fs::write(mirror.join("packages/hello/1.0.0/README"), "jello")?;
+    let report = sync::sync(source, goinfre.path())?;
+    assert_eq!((report.copied, report.unchanged), (1, 3));
+    assert_eq!(std::fs::read_to_string(mirror.join("packages/hello/1.0.0/README"))?, "hello");
+    assert_eq!(sync::sync(source, goinfre.path())?.unchanged, 4);
+
+    // A touched file with the same content is not copied again
+    let readme = std::fs::File::options().append(true).open(source.join("packages/hello/1.0.0/README"))?;
+    readme.set_modified(SystemTime::now() + Duration::from_secs(60))?;
+    assert_eq!(sync::sync(source, goinfre.path())?.copied, 0);
+
+    std::fs::write(source.join("packages/hello/1.0.0/README"), "hello, world")?;
+    std::fs::remove_file(source.join("packages/hello/1.0.0/hi"))?;
+    let report = sync::sync(source, goinfre.path())?;
+    assert_eq!((report.copied, report.deleted, report.bytes), (1, 1, 12));
+    assert_eq!(std::fs::read_to_string(mirror.join("packages/hello/1.0.0/README"))?, "hello, world");
+    assert!(std::fs::symlink_metadata(mirror.join("packages/hello/1.0.0/hi")).is_err());
+
+    // Removed directories disappear from the mirror
+    std::fs::remove_dir_all(source.join("packages"))?;
+    let report = sync::sync(source, goinfre.path())?;
+    assert_eq!(report.deleted, 2);
+    assert!(!mirror.join("packages").exists());
+    assert!(goinfre.path().join("packages/eza/1.0.0").is_dir());
+
+    Ok(())
+}
+
+#[test]
+fn resyncs_goinfre_synced_on_another_machine() -> anyhow::Result<()> {
+    let sgoinfre = tempfile::tempdir()?;
+    let goinfre = tempfile::tempdir()?;
+    std::fs::write(sgoinfre.path().join("file"), "content")?;
+    sync::sync(sgoinfre.path(), goinfre.path())?;
+
+    let manifest_path = goinfre.path().join(sync::MANIFEST_FILE);
+    let mut manifest: sync::SyncManifest = serde_json::from_str(&std::fs::read_to_string(&manifest_path)?)?;
+    manifest.host = format!("{}-elsewhere", sync::hostname());
+    std::fs::write(&manifest_path, serde_json::to_string(&manifest)?)?;
+    assert!(sync::is_fresh(goinfre.path()));
+
+    std::fs::remove_file(&manifest_path)?;
+    assert!(sync::is_fresh(goinfre.path()));
+    assert_eq!(sync::sync(sgoinfre.path(), goinfre.path())?.unchanged, 0, "nothing is trusted without a manifest");
+
+    Ok(())
+}
+
+#[test]
+fn points_installed_packages_at_the_mirror() -> anyhow::Result<()> {
+    let root = tempfile::tempdir()?;
+    let (install_dir, packages_dir, goinfre) =
+        (root.path().join("packages"), root.path().join("sgoinfre/packages"), root.path().join("goinfre"));
+    let mirror = sync::mirror_dir(&goinfre);
+
+    std::fs::create_dir_all(packages_dir.join("hello/1.0.0"))?;
+    std::fs::write(packages_dir.join("hello/1.0.0/README"), "hello")?;
+    std::fs::create_dir_all(install_dir.join("hello"))?;
+    std::os::unix::fs::symlink(packages_dir.join("hello/1.0.0"), install_dir.join("hello/1.0.0"))?;
+
+    sync::sync(&packages_dir, &goinfre)?;
+    assert_eq!(sync::relink(&install_dir, &packages_dir, &mirror)?, 1);
+    assert_eq!(std::fs::read_link(install_dir.join("hello/1.0.0"))?, mirror.join("hello/1.0.0"));
+    assert_eq!(sync::relink(&install_dir, &packages_dir, &mirror)?, 0);
+
+    // The version stored in sgoinfre is still in use through its mirror
+    let report = Collector::new(install_dir.clone())
+        .with_volume(packages_dir.clone())
+        .with_mirror(mirror.clone(), packages_dir.clone())
+        .collect()?;
+    assert_eq!(report.count(Garbage::Version), 0);
+    assert!(packages_dir.join("hello/1.0.0/README").is_file());
+
+    // Once goinfre is wiped, the version is used from sgoinfre again
+    std::fs::remove_dir_all(&goinfre)?;
+    assert_eq!(sync::relink(&install_dir, &packages_dir, &mirror)?, 1);
+    assert_eq!(std::fs::read_link(install_dir.join("hello/1.0.0"))?, packages_dir.join("hello/1.0.0"));
+
+    Ok(())
+}
+
+#[test]
+fn links_the_commands_of_installed_apps_again() -> anyhow::Result<()> {
+    let root = tempfile::tempdir()?;
+    let bin_dir = root.path().join("bin");
+    let mut config = Config::for_tests(root.path().join("packages"))
+        .with_volumes(root.path().join("sgoinfre"), root.path().join("goinfre"));
+
+    let package = Package {
+        name: "hello".to_string(),
+        version: Version::new(1, 0, 0),
+        sha256: String::new(),
+        license: "MIT".to_string(),
+        source: None,
+        dependencies: Vec::new(),
+        package_handler_version: 1,
+        variants: Vec::new(),
+    };
+    let app: App = toml::from_str(
+        r#"
+        name = "hello"
+        version = "1.0.0"
+        license = "MIT"
+        packages = []
+        commands = []
+        app_handler_version = 1
+        "#,
+    )?;
+    let app = App {
+        packages: vec![package],
+        commands: vec![
+            AppCommand { command: "hello".to_string(), path: "bin/hello".into() },
+            AppCommand { command: "hi".to_string(), path: "bin/hello".into() },
+        ],
+        ..app
+    };
+    config.record_installed(&app);
+
+    // Nothing to link while the package is missing
+    assert_eq!(config.ensure_binaries_symlinked(&bin_dir)?, 0);
+
+    let target = config.install_dir.join("hello/1.0.0/bin/hello");
+    std::fs::create_dir_all(target.parent().expect("in a directory"))?;
+    std::fs::write(&target, "#!/bin/sh\n")?;
+    std::os::unix::fs::symlink("/nowhere", bin_dir.join("hello"))?;
+    // A command of the user's own with the same name
+    std::fs::write(bin_dir.join("hi"), "#!/bin/sh\necho mine\n")?;
+
+    assert_eq!(config.ensure_binaries_symlinked(&bin_dir)?, 1);
+    assert_eq!(std::fs::read_link(bin_dir.join("hello"))?, target);
+    assert_eq!(std::fs::read_to_string(bin_dir.join("hi"))?, "#!/bin/sh\necho mine\n");
+    assert_eq!(config.ensure_binaries_symlinked(&bin_dir)?, 0);
+
+    config.forget_installed("hello");
+    assert!(config.commands.is_empty() && config.packages.is_empty());
+
+    Ok(())
+}
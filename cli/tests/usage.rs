@@ -0,0 +1,73 @@
+#![cfg(unix)]
+
+use diem::{
+    PackageManager,
+    package::usage,
+    testing::{ArchiveBuilder, FixtureProvider},
+};
+use semver::Version;
+
+const SCRIPT: &str = "#!/bin/sh\necho hello\n";
+
+#[tokio::test]
+async fn counts_files_shared_between_versions_once() -> anyhow::Result<()> {
+    let artifactory_dir = tempfile::tempdir()?;
+    let install_dir = tempfile::tempdir()?;
+    let fixture = FixtureProvider::new(artifactory_dir.path(), "fixtures");
+    let package_manager = PackageManager::new(install_dir.path().to_path_buf());
+
+    let mut packages = Vec::new();
+    for version in ["1.0.0", "1.1.0"] {
+        let archive = ArchiveBuilder::new().executable("bin/hello", SCRIPT).file("VERSION", version.to_string());
+        let package = fixture.add_package("hello", version, &archive)?;
+        package_manager.install_package(&package, &fixture.provider()?).await?;
+        packages.push(package);
+    }
+
+    let usages = usage::package_usage(install_dir.path())?;
+    let versions: Vec<&Version> = usages.iter().map(|usage| &usage.version).collect();
+    assert_eq!(versions, [&Version::new(1, 1, 0), &Version::new(1, 0, 0)]);
+    for usage in &usages {
+        assert_eq!(usage.bytes, (SCRIPT.len() + "1.0.0".len()) as u64);
+        assert_eq!(usage.exclusive, "1.0.0".len() as u64, "the script is shared");
+    }
+
+    // The store holds one copy of each file, and the packages link them
+    let packages_dir = usage::measure(&install_dir.path().join("hello"))?;
+    assert_eq!(packages_dir.files, 3);
+    assert_eq!(packages_dir.bytes, (SCRIPT.len() + 2 * "1.0.0".len()) as u64);
+    let store = usage::measure(package_manager.store().root())?;
+    let registry = usage::measure(package_manager.registry().root())?;
+    let installed = usage::measure(install_dir.path())?;
+    assert_eq!(registry.files, 2, "one file manifest per version");
+    assert_eq!(
+        (installed.files - registry.files, installed.bytes - registry.bytes),
+        (store.files, store.bytes),
+        "installed files are all linked from the store"
+    );
+
+    assert_eq!(usage::app_size(&packages[1..], &usages), usages[0].bytes);
+    assert_eq!(usage::app_size(&packages, &usages), 2 * usages[0].bytes);
+
+    Ok(())
+}
+
+#[test]
+fn finds_files_duplicated_by_a_sync() -> anyhow::Result<()> {
+    let sgoinfre = tempfile::tempdir()?;
+    let goinfre = tempfile::tempdir()?;
+    for dir in [sgoinfre.path(), goinfre.path()] {
+        std::fs::create_dir_all(dir.join("packages/hello"))?;
+        std::fs::write(dir.join("packages/hello/bin"), SCRIPT)?;
+    }
+    std::fs::write(sgoinfre.path().join("only-here"), "sgoinfre")?;
+    std::fs::write(sgoinfre.path().join("changed"), "old")?;
+    std::fs::write(goinfre.path().join("changed"), "newer")?;
+
+    let duplicates = usage::duplicates(sgoinfre.path(), goinfre.path())?;
+    assert_eq!(duplicates, usage::Duplicates { bytes: SCRIPT.len() as u64, files: 1 });
+
+    assert_eq!(usage::measure(&sgoinfre.path().join("missing"))?, usage::Usage::default());
+
+    Ok(())
+}
@@ -0,0 +1,112 @@
+use std::path::Path;
+
+use diem::{
+    artifactory::validate::{self, Severity, ValidationReport},
+    schema,
+    testing::{ArchiveBuilder, FixtureProvider},
+};
+
+fn messages(report: &ValidationReport, severity: Severity) -> Vec<String> {
+    report
+        .issues
+        .iter()
+        .filter(|issue| issue.severity == severity)
+        .map(|issue| format!("{}: {}", issue.location, issue.message))
+        .collect()
+}
+
+fn write_fixture(root: &Path) -> anyhow::Result<FixtureProvider> {
+    let mut fixture = FixtureProvider::new(root, "validate");
+    let archive = ArchiveBuilder::new().executable("bin/hello", "#!/bin/sh\n");
+    let package = fixture.add_package("hello", "1.0.0", &archive)?;
+    fixture.add_app(package, &[("hello", "bin/hello")]);
+    fixture.provider()?;
+    Ok(fixture)
+}
+
+// Rewrites the fixture's artifactory.toml through `edit`
+fn edit_artifactory(fixture: &FixtureProvider, edit: impl FnOnce(&mut diem::Artifactory)) -> anyhow::Result<()> {
+    let path = fixture.artifactory_path();
+    let mut artifactory = schema::parse_artifactory(&std::fs::read_to_string(&path)?)?;
+    edit(&mut artifactory);
+    std::fs::write(&path, toml::to_string_pretty(&artifactory)?)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn accepts_the_sample_artifactory() -> anyhow::Result<()> {
+    let report = validate::validate_file(Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/../artifactory.toml"))).await?;
+    assert!(report.issues.is_empty(), "{:?}", report.issues);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn accepts_a_valid_fixture() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let fixture = write_fixture(dir.path())?;
+
+    let report = validate::validate_file(&fixture.artifactory_path()).await?;
+    assert!(report.issues.is_empty(), "{:?}", report.issues);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn reports_duplicates_checksums_licenses_and_commands() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let fixture = write_fixture(dir.path())?;
+    edit_artifactory(&fixture, |artifactory| {
+        let mut duplicate = artifactory.apps[0].clone();
+        duplicate.license = "Definitely not SPDX".to_string();
+        duplicate.packages[0].sha256 = "ABC".to_string();
+        duplicate.commands[0].path = "bin/missing".into();
+        artifactory.apps.push(duplicate);
+    })?;
+
+    let report = validate::validate_file(&fixture.artifactory_path()).await?;
+    let errors = messages(&report, Severity::Error);
+
+    assert!(errors.iter().any(|error| error.contains("Duplicate app and version")));
+    assert!(errors.iter().any(|error| error.contains("not a valid SPDX expression")));
+    assert!(errors.iter().any(|error| error.contains("Malformed sha256")));
+    assert!(errors.iter().any(|error| error.contains("bin/missing is not in the package archive")));
+    assert!(report.has_errors());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn reports_missing_sources_and_command_fallbacks() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let fixture = write_fixture(dir.path())?;
+    edit_artifactory(&fixture, |artifactory| {
+        let mut missing = artifactory.apps[0].clone();
+        missing.version = semver::Version::new(2, 0, 0);
+        missing.packages[0].source = Some("packages/missing.tar.gz".to_string());
+        artifactory.apps.push(missing);
+
+        artifactory.apps[0].commands[0].path = "hello".into();
+    })?;
+
+    let report = validate::validate_file(&fixture.artifactory_path()).await?;
+
+    assert!(messages(&report, Severity::Error).iter().any(|error| error.contains("missing.tar.gz cannot be read")));
+    assert!(messages(&report, Severity::Warning).iter().any(|warning| warning.contains("diem will fall back to it")));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn reports_unknown_handler_versions() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let fixture = write_fixture(dir.path())?;
+    edit_artifactory(&fixture, |artifactory| {
+        artifactory.artifactory_handler_version = schema::ARTIFACTORY_HANDLER_VERSION + 1;
+    })?;
+
+    let report = validate::validate_file(&fixture.artifactory_path()).await?;
+    assert!(messages(&report, Severity::Error)[0].contains("update diem"));
+
+    Ok(())
+}
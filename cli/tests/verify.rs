@@ -0,0 +1,104 @@
+#![cfg(unix)]
+
+use diem::{
+    PackageManager,
+    config::InstalledCommand,
+    package::verify::{self, CommandProblem, Problem},
+    testing::{ArchiveBuilder, FixtureProvider},
+};
+
+use std::os::unix::fs::PermissionsExt as _;
+use std::path::PathBuf;
+
+#[tokio::test]
+async fn repairs_from_the_store_what_was_changed() -> anyhow::Result<()> {
+    let artifactory_dir = tempfile::tempdir()?;
+    let root = tempfile::tempdir()?;
+    let install_dir = root.path().join("packages");
+
+    let fixture = FixtureProvider::new(artifactory_dir.path(), "fixtures");
+    let provider = fixture.provider()?;
+    let archive = ArchiveBuilder::new()
+        .executable("bin/hello", "#!/bin/sh\necho hello\n")
+        .file("README", "hello")
+        .file("share/doc", "docs");
+    let hello = fixture.add_package("hello", "1.0.0", &archive)?;
+
+    let manager = PackageManager::new(install_dir.clone());
+    manager.install_package(&hello, &provider).await?;
+    assert_eq!(verify::verify_package(&install_dir, "hello", &hello.version)?, Vec::new());
+
+    let package_dir = manager.get_package_dir("hello", &hello.version);
+    std::fs::remove_file(package_dir.join("README"))?;
+    std::fs::remove_file(package_dir.join("share/doc"))?;
+    std::fs::write(package_dir.join("share/doc"), "other docs")?;
+    std::fs::set_permissions(package_dir.join("bin/hello"), std::fs::Permissions::from_mode(0o644))?;
+    std::fs::write(package_dir.join("notes"), "mine")?;
+
+    let problems = verify::verify_package(&install_dir, "hello", &hello.version)?;
+    assert_eq!(
+        problems,
+        vec![
+            Problem::Missing(PathBuf::from("README")),
+            Problem::Mode(PathBuf::from("bin/hello")),
+            Problem::Modified(PathBuf::from("share/doc")),
+            Problem::Extra(PathBuf::from("notes")),
+        ]
+    );
+
+    // Nothing needs downloading while the store has every file
+    let repair = manager.repair_package(&hello, None).await?;
+    assert_eq!((repair.restored, repair.removed, repair.downloaded), (3, 1, false));
+    assert_eq!(verify::verify_package(&install_dir, "hello", &hello.version)?, Vec::new());
+    assert_eq!(std::fs::read_to_string(package_dir.join("share/doc"))?, "docs");
+
+    // A file changed in place also changed its store object
+    std::fs::write(package_dir.join("README"), "changed")?;
+    assert!(manager.repair_package(&hello, None).await.is_err(), "no provider to download from");
+    let repair = manager.repair_package(&hello, Some(&provider)).await?;
+    assert!(repair.downloaded);
+    assert_eq!(std::fs::read_to_string(package_dir.join("README"))?, "hello");
+
+    // A package that is gone is installed again
+    std::fs::remove_dir_all(&package_dir)?;
+    assert_eq!(verify::verify_package(&install_dir, "hello", &hello.version)?, vec![Problem::MissingPackage]);
+    assert!(manager.repair_package(&hello, Some(&provider)).await?.reinstalled);
+    assert_eq!(verify::verify_package(&install_dir, "hello", &hello.version)?, Vec::new());
+
+    manager.uninstall_package("hello", None).await?;
+    assert_eq!(manager.registry().manifest("hello", &hello.version)?, None);
+
+    Ok(())
+}
+
+#[test]
+fn finds_broken_command_links() -> anyhow::Result<()> {
+    let root = tempfile::tempdir()?;
+    let (install_dir, bin_dir) = (root.path().join("packages"), root.path().join("bin"));
+    std::fs::create_dir_all(&bin_dir)?;
+
+    let command = InstalledCommand {
+        command: "hello".to_string(),
+        package: "hello".to_string(),
+        version: semver::Version::new(1, 0, 0),
+        path: "bin/hello".into(),
+    };
+    let target = command.target(&install_dir);
+    assert_eq!(verify::verify_command(&command, &install_dir, &bin_dir), Some(CommandProblem::Missing));
+
+    std::os::unix::fs::symlink(&target, bin_dir.join("hello"))?;
+    assert_eq!(verify::verify_command(&command, &install_dir, &bin_dir), Some(CommandProblem::Dangling));
+
+    std::fs::create_dir_all(target.parent().expect("in a directory"))?;
+    std::fs::write(&target, "#!/bin/sh\n")?;
+    assert_eq!(verify::verify_command(&command, &install_dir, &bin_dir), None);
+
+    std::fs::remove_file(bin_dir.join("hello"))?;
+    std::os::unix::fs::symlink("/nowhere", bin_dir.join("hello"))?;
+    assert_eq!(
+        verify::verify_command(&command, &install_dir, &bin_dir),
+        Some(CommandProblem::Elsewhere(PathBuf::from("/nowhere")))
+    );
+
+    Ok(())
+}